@@ -1 +1,7 @@
 pub mod _buffer;
+pub mod directory;
+pub mod encoding;
+pub mod grapheme;
+pub mod injection;
+pub mod signs;
+pub mod syntax;
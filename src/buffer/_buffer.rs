@@ -1,29 +1,156 @@
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::Context;
+
 use crate::editor::main_editor::InsertModeTextAddInfo;
+use crate::editor::options::Options;
+
+use super::directory::{ListSettings, Listing};
+use super::encoding::{decode, encode, is_binary, split_lines, FileFormat};
+use super::grapheme::{byte_to_grapheme, grapheme_count, grapheme_to_byte};
+use super::signs::Signs;
+use super::syntax::Syntax;
+use crate::language::_language::registry;
+
+/// What the lines of a buffer stand for.
+#[derive(Debug)]
+pub enum Kind {
+    File,
+    Directory(Listing), // saving changes the directory instead of writing a file
+}
 
 pub struct Buffer {
     pub file: Option<String>,
+    pub kind: Kind,
     pub lines: Vec<String>,
+    pub format: Option<FileFormat>, // how the file was written, None until it is on disk
+    final_newline: bool,            // whether the file ended its last line, kept on write
+    pub readonly: bool,             // the file can't be written, or shouldn't be
+    unread: bool,                   // the file is there but couldn't be read
+    pub binary: bool,               // written back byte for byte, with no newline added
+    pub modified: bool,             // changed since it was read or written
+    pub signs: Signs,
+    syntax: Option<Syntax>,
 }
 
 impl Buffer {
-    pub fn new(file: Option<String>, content: Option<String>) -> Self {
-        let lines = match &file {
-            Some(_) => content.unwrap().lines().map(|s| s.to_string()).collect(),
-            None => vec![],
-        };
-        Self { file, lines }
+    pub fn new(file: Option<String>, bytes: Option<&[u8]>) -> Self {
+        let (text, fileencoding, bomb) = bytes.map_or((String::new(), "utf-8", false), decode);
+        let (mut lines, fileformat, mut final_newline) = split_lines(&text);
+        // there is always a line to put the cursor on, an empty file is one
+        // empty line without its ending.
+        if lines.is_empty() {
+            lines.push(String::new());
+            final_newline = false;
+        }
+        let binary = bytes.is_some_and(|bytes| is_binary(bytes, fileencoding));
+        let format = bytes.map(|_| FileFormat {
+            fileformat: fileformat.to_string(),
+            fileencoding: fileencoding.to_string(),
+            bomb,
+        });
+        let syntax = registry()
+            .detect(file.as_deref(), &lines)
+            .and_then(|config| Syntax::new(config).ok());
+        Self {
+            file,
+            kind: Kind::File,
+            lines,
+            format,
+            final_newline,
+            readonly: binary,
+            unread: false,
+            binary,
+            modified: false,
+            signs: Signs::default(),
+            syntax,
+        }
     }
 
-    pub fn from_file(file: Option<String>) -> Self {
-        let buf = match &file {
-            Some(str) => Self::new(
-                Some(str.to_string()),
-                Some(std::fs::read_to_string(str).unwrap()),
-            ),
-            None => Self::new(file, None),
+    /// Reads `path` into a buffer. Whatever keeps it from being read or
+    /// written comes back as the message to show, the buffer opens anyway: a
+    /// file that doesn't exist yet is created on save, one that can't be read
+    /// or written is read-only.
+    pub fn open(path: Option<String>) -> (Self, Option<String>) {
+        let Some(path) = path else {
+            return (Self::new(None, None), None);
         };
+        if Path::new(&path).is_dir() {
+            return match Self::directory(Path::new(&path), ListSettings::default()) {
+                Ok(buffer) => (buffer, None),
+                Err(e) => (Self::new(None, None), Some(format!("{e:#}"))),
+            };
+        }
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return (
+                    Self::new(Some(path.clone()), None),
+                    Some(format!("\"{path}\" [New]")),
+                );
+            }
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                let message = format!("\"{path}\" [Permission Denied]");
+                return (Self::unreadable(path), Some(message));
+            }
+            Err(e) => return (Self::new(None, None), Some(format!("\"{path}\": {e}"))),
+        };
+        let mut buffer = Self::new(Some(path.clone()), Some(&bytes));
+        if buffer.binary {
+            return (
+                buffer,
+                Some(format!("\"{path}\" [binary] opened read-only")),
+            );
+        }
+        // opening for appending tells without changing the file.
+        let writable = OpenOptions::new().append(true).open(&path).is_ok();
+        buffer.readonly = !writable;
+        (
+            buffer,
+            (!writable).then(|| format!("\"{path}\" [readonly]")),
+        )
+    }
 
-        buf
+    /// An empty read-only buffer for a file that couldn't be read. It is
+    /// never written over the file, not even with `:w!`, since that would
+    /// replace what is in it with nothing.
+    fn unreadable(path: String) -> Self {
+        let mut buffer = Self::new(Some(path), None);
+        buffer.readonly = true;
+        buffer.unread = true;
+        buffer
+    }
+
+    /// A listing of the directory at `path`. The name ends in a slash so it
+    /// reads as a directory in the statusline.
+    pub fn directory(path: &Path, settings: ListSettings) -> anyhow::Result<Self> {
+        let listing = Listing::read(path, settings)?;
+        let name = listing.path.join("").display().to_string();
+        let mut buffer = Self::new(Some(name), None);
+        buffer.lines = listing.lines();
+        buffer.kind = Kind::Directory(listing);
+        Ok(buffer)
+    }
+
+    /// Name of the language the buffer is highlighted as, "text" when it
+    /// isn't.
+    pub fn filetype(&self) -> &str {
+        self.syntax.as_ref().map_or("text", |s| s.config.name)
+    }
+
+    pub fn set_filetype(&mut self, name: &str) -> anyhow::Result<()> {
+        if name == "text" || name.is_empty() {
+            self.syntax = None;
+            return Ok(());
+        }
+        let Some(config) = registry().by_name(name) else {
+            anyhow::bail!("unknown filetype: {name}");
+        };
+        self.syntax = Some(Syntax::new(config)?);
+        Ok(())
     }
 
     pub fn get(&self, line: usize) -> Option<String> {
@@ -33,45 +160,243 @@ impl Buffer {
         None
     }
 
-    pub fn insert_char(&mut self, x: u16, y: u16, c: char) {
-        let line = self.lines.get_mut(y as usize);
-        if let Some(line) = line {
-            line.insert(x as usize, c);
-        }
+    /// Number of grapheme clusters on `line`, 0 if the line doesn't exist.
+    pub fn line_len(&self, line: usize) -> usize {
+        self.lines.get(line).map_or(0, |l| grapheme_count(l))
     }
+
+    // x is a grapheme index into the line, not a screen column. returns the
+    // grapheme index right after the inserted char, which isn't always x + 1
+    // since combining marks merge into the cluster before them.
+    pub fn insert_char(&mut self, x: u16, y: u16, c: char) -> u16 {
+        let mut buf = [0; 4];
+        self.replace_graphemes(y, x, x, c.encode_utf8(&mut buf))
+    }
+
     pub fn delete_char(&mut self, x: u16, y: u16) {
-        let line = self.lines.get_mut(y as usize);
-        if let Some(line) = line {
-            line.remove(x as usize);
-        }
+        self.replace_graphemes(y, x, x + 1, "");
+    }
+
+    // replaces the graphemes start..end on line y with text and returns the
+    // grapheme index right after it.
+    pub fn replace_graphemes(&mut self, y: u16, start: u16, end: u16, text: &str) -> u16 {
+        let Some(line) = self.lines.get(y as usize) else {
+            return start;
+        };
+        let start = grapheme_to_byte(line, start as usize);
+        let end = grapheme_to_byte(line, end as usize);
+        let old = line[start..end].to_string();
+        self.record_edit(y as usize, start, &old, text);
+
+        let line = &mut self.lines[y as usize];
+        line.replace_range(start..end, text);
+        byte_to_grapheme(line, start + text.len()) as u16
     }
 
     pub fn delete_line(&mut self, line_no: u16) -> String {
-        self.lines.remove(line_no as usize)
+        let idx = line_no as usize;
+        let Some(line) = self.lines.get(idx).cloned() else {
+            return String::new();
+        };
+        // the newline that goes with the line is the one after it, or the one
+        // before it for the last line.
+        if idx + 1 < self.lines.len() {
+            self.record_edit(idx, 0, &format!("{line}\n"), "");
+        } else if idx > 0 {
+            let col = self.lines[idx - 1].len();
+            self.record_edit(idx - 1, col, &format!("\n{line}"), "");
+        } else {
+            self.record_edit(idx, 0, &line, "");
+        }
+        self.line_removed(idx);
+        self.lines.remove(idx);
+        // the only line is emptied rather than removed.
+        if self.lines.is_empty() {
+            self.line_inserted(0);
+            self.lines.push(String::new());
+        }
+        line
     }
 
     pub fn restore_line(&mut self, line: String, idx: u16) {
-        self.lines.insert(idx as usize, line);
+        let idx = (idx as usize).min(self.lines.len());
+        // the line deleted from a one line buffer goes back in place of the
+        // empty one it left.
+        if idx == 0 && self.lines == [""] {
+            self.record_edit(0, 0, "", &line);
+            self.lines[0] = line;
+            return;
+        }
+        if idx < self.lines.len() {
+            self.record_edit(idx, 0, "", &format!("{line}\n"));
+        } else if let Some(last) = self.lines.last() {
+            let col = last.len();
+            self.record_edit(idx - 1, col, "", &format!("\n{line}"));
+        } else {
+            self.record_edit(idx, 0, "", &line);
+        }
+        self.line_inserted(idx);
+        self.lines.insert(idx, line);
     }
+
+    // keeps the signs and the entries of a listing on their lines.
+    fn line_inserted(&mut self, idx: usize) {
+        self.signs.line_inserted(idx);
+        if let Kind::Directory(listing) = &mut self.kind {
+            listing.line_inserted(idx);
+        }
+    }
+
+    fn line_removed(&mut self, idx: usize) {
+        self.signs.line_removed(idx);
+        if let Kind::Directory(listing) = &mut self.kind {
+            listing.line_removed(idx);
+        }
+    }
+
     pub fn insert_line(&mut self, idx: u16) {
-        self.lines.insert(idx as usize, String::new());
+        self.restore_line(String::new(), idx);
+    }
+
+    // byte offset of the start of `line` in the text the syntax tree sees,
+    // which is the lines joined with '\n'.
+    pub fn line_offset(&self, line: usize) -> usize {
+        self.lines.iter().take(line).map(|l| l.len() + 1).sum()
+    }
+
+    /// Reads the listing of a directory buffer again, with other settings.
+    /// The cursor line stays what it is.
+    pub fn reload_directory(&mut self, settings: ListSettings) -> anyhow::Result<()> {
+        let Kind::Directory(listing) = &self.kind else {
+            anyhow::bail!("not a directory listing");
+        };
+        let listing = Listing::read(&listing.path, settings)?;
+        self.lines = listing.lines();
+        self.kind = Kind::Directory(listing);
+        self.modified = false;
+        Ok(())
+    }
+
+    // has to run before the lines change, start_byte is computed from them.
+    fn record_edit(&mut self, row: usize, col: usize, old: &str, new: &str) {
+        self.modified |= old != new;
+        if self.syntax.is_none() || (old.is_empty() && new.is_empty()) {
+            return;
+        }
+        let start_byte = self.line_offset(row) + col;
+        if let Some(syntax) = &mut self.syntax {
+            syntax.edit(start_byte, row, col, old, new);
+        }
+    }
+
+    /// Highlight captures inside the byte range of the document, see
+    /// `Syntax::highlights`.
+    pub fn highlights(&mut self, range: Range<usize>) -> Vec<(Range<usize>, &'static str)> {
+        match &mut self.syntax {
+            Some(syntax) => syntax.highlights(&self.lines, range),
+            None => vec![],
+        }
+    }
+
+    /// Writes the lines to `path` with the line endings, last newline and
+    /// encoding `options` ask for, and returns how many bytes that was.
+    /// trimwhitespace trims the buffer itself so what is shown stays what is
+    /// on disk.
+    pub fn write(&mut self, path: &str, options: &Options) -> anyhow::Result<usize> {
+        if self.unread && self.file.as_deref() == Some(path) {
+            anyhow::bail!("{path} couldn't be read, write the buffer to another file");
+        }
+        if options.trimwhitespace {
+            for row in 0..self.lines.len() {
+                let line = &self.lines[row];
+                let end = line.trim_end_matches([' ', '\t']).len();
+                if end < line.len() {
+                    let trailing = line[end..].to_string();
+                    self.record_edit(row, end, &trailing, "");
+                    self.lines[row].truncate(end);
+                }
+            }
+        }
+        let ending = match options.fileformat.as_str() {
+            "dos" => "\r\n",
+            "mac" => "\r",
+            _ => "\n",
+        };
+        let mut text = self.lines.join(ending);
+        // an empty buffer is written as an empty file, fixendofline or not.
+        let empty = self.lines == [""] && !self.final_newline;
+        let final_newline =
+            !empty && ((options.fixendofline && !self.binary) || self.final_newline);
+        if final_newline {
+            text.push_str(ending);
+        }
+        let bytes = encode(&text, &options.fileencoding, options.bomb).context(path.to_string())?;
+        std::fs::write(path, &bytes).context(path.to_string())?;
+        // the file is in the new format now, going back to it is no longer a
+        // conversion.
+        self.format = Some(FileFormat {
+            fileformat: options.fileformat.clone(),
+            fileencoding: options.fileencoding.clone(),
+            bomb: options.bomb,
+        });
+        self.final_newline = final_newline;
+        self.modified = false;
+        Ok(bytes.len())
     }
 
     pub fn remove_insert_changes(&mut self, insert_changes: InsertModeTextAddInfo) {
         let indexes = insert_changes.index;
-        let starting_index = indexes.0 as usize;
-        let ending_index = indexes.1 as usize;
         let line_no = insert_changes.line_no;
 
-        let mut string = self.lines.remove(line_no as usize);
-        string.replace_range(starting_index..=ending_index, "");
-        if !string.trim().is_empty() {
-            self.lines.insert(line_no as usize, string);
+        self.replace_graphemes(line_no, indexes.0, indexes.1 + 1, "");
+        if self.lines[line_no as usize].trim().is_empty() {
+            self.delete_line(line_no);
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn edits_a_file_that_does_not_exist_yet() {
+        let (mut buffer, message) = Buffer::open(Some("no/such/file.txt".into()));
+        assert_eq!(message.as_deref(), Some("\"no/such/file.txt\" [New]"));
+        assert_eq!(buffer.lines, [""]);
+
+        assert_eq!(buffer.delete_line(0), "");
+        assert_eq!(buffer.lines, [""]);
+        buffer.insert_line(1);
+        assert_eq!(buffer.insert_char(0, 1, 'a'), 1);
+        assert_eq!(buffer.lines, ["", "a"]);
+
+        // the only line left is emptied, and undoing that puts it back.
+        buffer.delete_line(0);
+        let line = buffer.delete_line(0);
+        assert_eq!(buffer.lines, [""]);
+        buffer.restore_line(line, 0);
+        assert_eq!(buffer.lines, ["a"]);
+        assert_eq!(buffer.delete_line(5), "");
+    }
+
+    #[test]
+    fn never_writes_over_a_file_it_could_not_read() {
+        let dir = TestDir::new("unreadable");
+        let path = dir.join("secret.txt");
+        std::fs::write(&path, "keep me\n").unwrap();
+        let path = path.display().to_string();
+
+        // what open gives for a permission error, which root doesn't get.
+        let mut buffer = Buffer::unreadable(path.clone());
+        assert!(buffer.readonly);
+        buffer.insert_char(0, 0, 'x');
+        assert!(buffer.write(&path, &Options::default()).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me\n");
 
-    pub fn viewport_buf(&self, vtop: usize, vheight: usize) -> String {
-        let height = std::cmp::min(vtop + vheight, self.lines.len());
-        self.lines[vtop..height].join("\n")
+        let other = dir.join("other.txt").display().to_string();
+        buffer.write(&other, &Options::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(other).unwrap(), "x\n");
     }
 }
@@ -0,0 +1,319 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use ignore::WalkBuilder;
+
+/// Order of the entries of a listing, directories always come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sort {
+    #[default]
+    Name,
+    Modified, // newest first
+    Size,     // largest first
+}
+
+impl Sort {
+    pub fn next(self) -> Self {
+        match self {
+            Sort::Name => Sort::Modified,
+            Sort::Modified => Sort::Size,
+            Sort::Size => Sort::Name,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sort::Name => "name",
+            Sort::Modified => "modified",
+            Sort::Size => "size",
+        }
+    }
+}
+
+/// What a listing shows, kept by the editor from one directory to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ListSettings {
+    pub sort: Sort,
+    pub hidden: bool,  // show dotfiles
+    pub ignored: bool, // show what .gitignore and friends leave out
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Entry {
+    // how it is shown, directories end in a slash.
+    fn line(&self) -> String {
+        match self.is_dir {
+            true => format!("{}/", self.name),
+            false => self.name.clone(),
+        }
+    }
+}
+
+/// What saving an edited listing does to the directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Create(String), // a trailing slash makes it a directory
+    Rename(String, String),
+    Delete(String),
+}
+
+/// A directory shown as a buffer, one entry per line. Editing the lines and
+/// saving renames, creates and deletes the entries, so every line remembers
+/// the entry it started as.
+#[derive(Debug)]
+pub struct Listing {
+    pub path: PathBuf,
+    entries: Vec<Entry>,
+    ids: Vec<Option<usize>>, // entry of each line, None for lines typed in
+}
+
+impl Listing {
+    pub fn read(path: &Path, settings: ListSettings) -> anyhow::Result<Self> {
+        let path = std::path::absolute(path).context(path.display().to_string())?;
+        // read_dir tells a directory we may not read, the walker would just
+        // come back empty.
+        fs::read_dir(&path).context(path.display().to_string())?;
+        let walker = WalkBuilder::new(&path)
+            .max_depth(Some(1))
+            .hidden(!settings.hidden)
+            .git_ignore(!settings.ignored)
+            .git_global(!settings.ignored)
+            .git_exclude(!settings.ignored)
+            .ignore(!settings.ignored)
+            .parents(!settings.ignored)
+            .build();
+        let mut entries = vec![];
+        for found in walker.flatten().filter(|found| found.depth() == 1) {
+            let metadata = fs::metadata(found.path()).ok();
+            entries.push(Entry {
+                name: found.file_name().to_string_lossy().into_owned(),
+                is_dir: metadata.as_ref().is_some_and(|m| m.is_dir()),
+                size: metadata.as_ref().map_or(0, |m| m.len()),
+                modified: metadata.and_then(|m| m.modified().ok()),
+            });
+        }
+        entries.sort_by(|a, b| {
+            let order = match settings.sort {
+                Sort::Name => std::cmp::Ordering::Equal,
+                Sort::Modified => b.modified.cmp(&a.modified),
+                Sort::Size => b.size.cmp(&a.size),
+            };
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then(order)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+        Ok(Self {
+            path,
+            ids: (0..entries.len()).map(Some).collect(),
+            entries,
+        })
+    }
+
+    /// One line per entry, an empty directory still has a line for the
+    /// cursor to be on.
+    pub fn lines(&self) -> Vec<String> {
+        match self.entries.is_empty() {
+            true => vec![String::new()],
+            false => self.entries.iter().map(Entry::line).collect(),
+        }
+    }
+
+    /// The path on the line as it was read, None for a line typed in.
+    pub fn entry_path(&self, line: usize) -> Option<PathBuf> {
+        let id = (*self.ids.get(line)?)?;
+        Some(self.path.join(&self.entries[id].name))
+    }
+
+    // the buffer calls these when a line comes or goes, like for signs.
+    pub fn line_inserted(&mut self, line: usize) {
+        self.ids.insert(line.min(self.ids.len()), None);
+    }
+
+    pub fn line_removed(&mut self, line: usize) {
+        if line < self.ids.len() {
+            self.ids.remove(line);
+        }
+    }
+
+    /// What the edited `lines` ask for, compared to the entries they started
+    /// as. A name that is deleted and typed in again is left alone.
+    pub fn changes(&self, lines: &[String]) -> anyhow::Result<Vec<Change>> {
+        let mut kept = HashSet::new();
+        let mut names = HashSet::new();
+        let mut created = vec![];
+        let mut renamed = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            let id = self.ids.get(i).copied().flatten();
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            check_name(line)?;
+            if !names.insert(line.trim_end_matches('/')) {
+                anyhow::bail!("{line} is there twice");
+            }
+            match id {
+                Some(id) if self.entries[id].line() == line => {
+                    kept.insert(id);
+                }
+                Some(id) => {
+                    kept.insert(id);
+                    let to = line.trim_end_matches('/').to_string();
+                    renamed.push(Change::Rename(self.entries[id].name.clone(), to));
+                }
+                None => created.push(line.to_string()),
+            }
+        }
+        let mut deleted: Vec<_> = (0..self.entries.len())
+            .filter(|id| !kept.contains(id))
+            .map(|id| self.entries[id].line())
+            .collect();
+        created.retain(|name| match deleted.iter().position(|d| d == name) {
+            Some(i) => {
+                deleted.remove(i);
+                false
+            }
+            None => true,
+        });
+        let deleted = deleted
+            .into_iter()
+            .map(|name| Change::Delete(name.trim_end_matches('/').to_string()));
+        Ok(deleted
+            .chain(renamed)
+            .chain(created.into_iter().map(Change::Create))
+            .collect())
+    }
+
+    /// Carries out the changes, deletions first so their names are free for
+    /// the rest. Stops at the first one that fails.
+    pub fn apply(&self, changes: &[Change]) -> anyhow::Result<()> {
+        for change in changes {
+            match change {
+                Change::Delete(name) => {
+                    let path = self.path.join(name);
+                    let deleted = match path.is_dir() && !path.is_symlink() {
+                        true => fs::remove_dir_all(&path),
+                        false => fs::remove_file(&path),
+                    };
+                    deleted.with_context(|| format!("deleting {name}"))?;
+                }
+                Change::Rename(from, to) => {
+                    let target = self.path.join(to);
+                    if target.exists() {
+                        anyhow::bail!("renaming {from}: {to} already exists");
+                    }
+                    create_parent(&target)?;
+                    fs::rename(self.path.join(from), &target)
+                        .with_context(|| format!("renaming {from} to {to}"))?;
+                }
+                Change::Create(name) => {
+                    let path = self.path.join(name);
+                    let created = match name.ends_with('/') {
+                        true => fs::create_dir_all(&path),
+                        false => create_parent(&path).and_then(|_| {
+                            fs::File::create_new(&path)?;
+                            Ok(())
+                        }),
+                    };
+                    created.with_context(|| format!("creating {name}"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// names may go into subdirectories but not out of the listed one.
+fn check_name(name: &str) -> anyhow::Result<()> {
+    let path = Path::new(name);
+    let outside = path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)));
+    if outside {
+        anyhow::bail!("{name} is not a name inside the directory");
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// The changes as one line, for asking before they are made.
+pub fn describe(changes: &[Change]) -> String {
+    let described: Vec<_> = changes
+        .iter()
+        .map(|change| match change {
+            Change::Create(name) => format!("create {name}"),
+            Change::Rename(from, to) => format!("rename {from} to {to}"),
+            Change::Delete(name) => format!("delete {name}"),
+        })
+        .collect();
+    described.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn turns_edited_lines_into_changes() {
+        let dir = TestDir::new("listing");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        for name in ["b.txt", "a.txt", ".hidden", "old.rs"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let mut listing = Listing::read(&dir, ListSettings::default()).unwrap();
+        assert_eq!(listing.lines(), ["src/", "a.txt", "b.txt", "old.rs"]);
+
+        // a.txt deleted, old.rs renamed, one file and one directory typed in.
+        let mut lines = listing.lines();
+        lines.remove(1);
+        listing.line_removed(1);
+        lines[2] = "new.rs".into();
+        lines.push("docs/".into());
+        listing.line_inserted(3);
+        lines.insert(0, "src/notes.md".into());
+        listing.line_inserted(0);
+        let changes = listing.changes(&lines).unwrap();
+        assert_eq!(
+            changes,
+            [
+                Change::Delete("a.txt".into()),
+                Change::Rename("old.rs".into(), "new.rs".into()),
+                Change::Create("src/notes.md".into()),
+                Change::Create("docs/".into()),
+            ]
+        );
+        listing.apply(&changes).unwrap();
+        let listing = Listing::read(&dir, ListSettings::default()).unwrap();
+        assert_eq!(listing.lines(), ["docs/", "src/", "b.txt", "new.rs"]);
+        assert!(dir.join("src/notes.md").is_file());
+
+        let hidden = ListSettings {
+            hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(Listing::read(&dir, hidden).unwrap().lines()[2], ".hidden");
+        assert!(listing.changes(&["../x".into()]).is_err());
+
+        let empty = Listing::read(&dir.join("docs"), ListSettings::default()).unwrap();
+        assert_eq!(empty.lines(), [""]);
+        assert_eq!(empty.changes(&empty.lines()).unwrap(), []);
+    }
+}
@@ -0,0 +1,204 @@
+// the bytes a file is written as. the text is kept as utf-8 in the buffer,
+// fileencoding only matters on the way to disk.
+
+/// How a file is written, what its fileformat, fileencoding and bomb
+/// options start from so saving gives back the same bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileFormat {
+    pub fileformat: String,
+    pub fileencoding: String,
+    pub bomb: bool,
+}
+
+/// The lines of `text`, the fileformat they end in and whether the last one
+/// has its line ending. A file is dos when every line ends in \r\n and mac
+/// when it only has \r, the lines of a unix file keep a \r they end with.
+pub fn split_lines(text: &str) -> (Vec<String>, &'static str, bool) {
+    let newlines = text.matches('\n').count();
+    let (fileformat, ending) = if newlines > 0 && text.matches("\r\n").count() == newlines {
+        ("dos", "\r\n")
+    } else if newlines == 0 && text.contains('\r') {
+        ("mac", "\r")
+    } else {
+        ("unix", "\n")
+    };
+    if text.is_empty() {
+        return (vec![], fileformat, true);
+    }
+    let mut lines: Vec<String> = text.split(ending).map(String::from).collect();
+    // the ending after the last line leaves an empty piece behind.
+    let final_newline = text.ends_with(ending);
+    if final_newline {
+        lines.pop();
+    }
+    (lines, fileformat, final_newline)
+}
+
+/// What a file's bytes say about how it was written: the text, the
+/// fileencoding it is in and whether it had a byte order mark. Bytes that
+/// are no valid utf-8 or utf-16 are taken as latin1, which any byte is.
+pub fn decode(bytes: &[u8]) -> (String, &'static str, bool) {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        if let Ok(text) = std::str::from_utf8(rest) {
+            return (text.to_string(), "utf-8", true);
+        }
+    }
+    for (encoding, bom) in [("utf-16le", [0xFF, 0xFE]), ("utf-16be", [0xFE, 0xFF])] {
+        if let Some(text) = bytes
+            .strip_prefix(&bom)
+            .and_then(|rest| utf16(rest, encoding))
+        {
+            return (text, encoding, true);
+        }
+    }
+    // utf-16 without a mark shows itself by the zero high bytes of ascii,
+    // which would pass for utf-8 otherwise. binaries are full of zeros too,
+    // but text has no zero chars.
+    if let Some(encoding) = utf16_without_bom(bytes) {
+        if let Some(text) = utf16(bytes, encoding).filter(|text| !text.contains('\0')) {
+            return (text, encoding, false);
+        }
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return (text.to_string(), "utf-8", false);
+    }
+    let text = bytes.iter().map(|b| char::from(*b)).collect();
+    (text, "latin1", false)
+}
+
+/// Whether the bytes are no text, by a zero byte early on like git tells.
+/// utf-16 has those all over.
+pub fn is_binary(bytes: &[u8], encoding: &str) -> bool {
+    !encoding.starts_with("utf-16") && bytes.iter().take(8000).any(|b| *b == 0)
+}
+
+fn utf16(bytes: &[u8], encoding: &str) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.chunks_exact(2).map(|pair| match encoding {
+        "utf-16le" => u16::from_le_bytes([pair[0], pair[1]]),
+        _ => u16::from_be_bytes([pair[0], pair[1]]),
+    });
+    char::decode_utf16(units).collect::<Result<_, _>>().ok()
+}
+
+// most of the bytes on one side of the pairs being zero.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static str> {
+    let pairs = bytes.len() / 2;
+    if pairs == 0 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let zeros = |side: usize| {
+        bytes
+            .iter()
+            .skip(side)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    match (zeros(0), zeros(1)) {
+        (_, odd) if odd * 2 > pairs => Some("utf-16le"),
+        (even, _) if even * 2 > pairs => Some("utf-16be"),
+        _ => None,
+    }
+}
+
+/// `text` in `encoding`, one of the names the fileencoding option takes,
+/// with a byte order mark in front if `bomb` asks for one.
+pub fn encode(text: &str, encoding: &str, bomb: bool) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    match encoding {
+        "utf-8" => {
+            if bomb {
+                bytes.extend([0xEF, 0xBB, 0xBF]);
+            }
+            bytes.extend(text.as_bytes());
+        }
+        // latin1 has no byte order mark.
+        "latin1" => {
+            for c in text.chars() {
+                let Ok(byte) = u8::try_from(c) else {
+                    anyhow::bail!("{c:?} can't be written as latin1");
+                };
+                bytes.push(byte);
+            }
+        }
+        "utf-16le" | "utf-16be" => {
+            let little = encoding == "utf-16le";
+            let units = bomb
+                .then_some(0xFEFF)
+                .into_iter()
+                .chain(text.encode_utf16());
+            for unit in units {
+                if little {
+                    bytes.extend(unit.to_le_bytes());
+                } else {
+                    bytes.extend(unit.to_be_bytes());
+                }
+            }
+        }
+        _ => anyhow::bail!("can't write {encoding}"),
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_each_encoding() {
+        assert_eq!(
+            encode("é", "utf-8", true).unwrap(),
+            [0xEF, 0xBB, 0xBF, 0xC3, 0xA9]
+        );
+        assert_eq!(encode("é", "latin1", true).unwrap(), [0xE9]);
+        assert!(encode("€", "latin1", false).is_err());
+        assert_eq!(
+            encode("a", "utf-16le", true).unwrap(),
+            [0xFF, 0xFE, 0x61, 0]
+        );
+        assert_eq!(encode("a", "utf-16be", false).unwrap(), [0, 0x61]);
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        for (encoding, bomb) in [
+            ("utf-8", false),
+            ("utf-8", true),
+            ("latin1", false),
+            ("utf-16le", true),
+            ("utf-16be", true),
+            ("utf-16le", false),
+        ] {
+            let bytes = encode("café\r\n", encoding, bomb).unwrap();
+            assert_eq!(decode(&bytes), ("café\r\n".to_string(), encoding, bomb));
+        }
+        assert_eq!(decode(&[0xFF, 0x41]).1, "latin1");
+        let elf = [0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0xC3];
+        assert!(is_binary(&elf, decode(&elf).1));
+        // mostly zero odd bytes, like utf-16le, but zero chars in it.
+        let mut elf = vec![0x7F, b'E', b'L', b'F', 2, 1, 1, 0];
+        elf.extend([0; 8]);
+        elf.extend([2, 0, 0x3E, 0, 1, 0, 0, 0]);
+        assert_eq!(decode(&elf).1, "utf-8");
+        assert!(is_binary(&elf, decode(&elf).1));
+        let text = encode("text", "utf-16be", false).unwrap();
+        assert!(!is_binary(&text, decode(&text).1));
+    }
+
+    #[test]
+    fn finds_the_line_endings() {
+        let lines = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(split_lines("a\r\nb\r\n"), (lines(&["a", "b"]), "dos", true));
+        assert_eq!(split_lines("a\rb"), (lines(&["a", "b"]), "mac", false));
+        // one line without the \r keeps the file unix, and the \r as text.
+        assert_eq!(
+            split_lines("a\r\nb\n"),
+            (lines(&["a\r", "b"]), "unix", true)
+        );
+        assert_eq!(split_lines("\n"), (lines(&[""]), "unix", true));
+        assert_eq!(split_lines(""), (vec![], "unix", true));
+    }
+}
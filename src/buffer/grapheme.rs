@@ -0,0 +1,164 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+// the cursor lives in grapheme clusters, the terminal in display columns and
+// the strings in bytes. everything that converts between the three goes
+// through here so the rest of the editor never slices a line by hand.
+
+pub fn graphemes(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.grapheme_indices(true)
+}
+
+pub fn grapheme_count(line: &str) -> usize {
+    line.graphemes(true).count()
+}
+
+/// Byte offset of the grapheme at `idx`. Indexes past the end clamp to the line length.
+pub fn grapheme_to_byte(line: &str, idx: usize) -> usize {
+    graphemes(line)
+        .nth(idx)
+        .map(|(byte, _)| byte)
+        .unwrap_or(line.len())
+}
+
+/// Index of the grapheme containing `byte`.
+pub fn byte_to_grapheme(line: &str, byte: usize) -> usize {
+    graphemes(line)
+        .take_while(|(start, g)| start + g.len() <= byte)
+        .count()
+}
+
+/// Number of terminal cells a grapheme cluster occupies.
+pub fn grapheme_width(g: &str) -> usize {
+    let mut chars = g.chars();
+    let Some(base) = chars.next() else {
+        return 0;
+    };
+    // an emoji presentation selector or a zwj sequence turns the cluster
+    // into a single wide glyph no matter what the base char says.
+    if g.contains('\u{FE0F}') || g.contains('\u{200D}') {
+        return 2;
+    }
+    // a pair of regional indicators is a flag.
+    if ('\u{1F1E6}'..='\u{1F1FF}').contains(&base) {
+        return 2;
+    }
+    // control chars have no width of their own, give them a cell so the
+    // cursor can still land on them.
+    base.width().unwrap_or(1).max(1)
+}
+
+/// Cells a grapheme takes when it starts at display column `col`. A tab
+/// stretches to the next multiple of `tabstop`, everything else is fixed width.
+pub fn cell_width(g: &str, col: usize, tabstop: usize) -> usize {
+    if g == "\t" {
+        let tabstop = tabstop.max(1);
+        return tabstop - col % tabstop;
+    }
+    grapheme_width(g)
+}
+
+/// Display column at which the grapheme at `idx` starts.
+pub fn grapheme_to_col(line: &str, idx: usize, tabstop: usize) -> usize {
+    line.graphemes(true)
+        .take(idx)
+        .fold(0, |col, g| col + cell_width(g, col, tabstop))
+}
+
+/// Grapheme under display column `col`. Columns past the end clamp to the grapheme count.
+pub fn col_to_grapheme(line: &str, col: usize, tabstop: usize) -> usize {
+    let mut width = 0;
+    for (idx, g) in line.graphemes(true).enumerate() {
+        width += cell_width(g, width, tabstop);
+        if width > col {
+            return idx;
+        }
+    }
+    grapheme_count(line)
+}
+
+/// Whitespace that spans display columns `from..to`, using tabs where a whole
+/// tab stop fits unless `expandtab` is set.
+pub fn indent_fill(from: usize, to: usize, tabstop: usize, expandtab: bool) -> String {
+    let tabstop = tabstop.max(1);
+    let mut fill = String::new();
+    let mut col = from;
+    if !expandtab {
+        while (col / tabstop + 1) * tabstop <= to {
+            fill.push('\t');
+            col = (col / tabstop + 1) * tabstop;
+        }
+    }
+    fill.push_str(&" ".repeat(to.saturating_sub(col)));
+    fill
+}
+
+/// Start of the run of spaces and tabs that ends at grapheme `idx`.
+pub fn whitespace_start(line: &str, idx: usize) -> usize {
+    let before: Vec<&str> = line.graphemes(true).take(idx).collect();
+    let run = before
+        .iter()
+        .rev()
+        .take_while(|g| **g == " " || **g == "\t")
+        .count();
+    before.len() - run
+}
+
+/// The symbol a control char is drawn as, ␍ for \r. Tabs are left to the
+/// callers, they take more than one cell.
+pub fn control_picture(g: &str) -> Option<char> {
+    let mut chars = g.chars();
+    let c = chars.next().filter(|c| c.is_control() && *c != '\t')?;
+    match c as u32 {
+        code @ 0..=0x1F => char::from_u32(0x2400 + code),
+        0x7F => Some('\u{2421}'),
+        _ => Some('\u{FFFD}'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_between_bytes_graphemes_and_columns() {
+        let line = "aé日🇯🇵e\u{301}x";
+
+        assert_eq!(grapheme_count(line), 6);
+        assert_eq!(grapheme_to_byte(line, 2), 3);
+        assert_eq!(grapheme_to_byte(line, 3), 6);
+        assert_eq!(byte_to_grapheme(line, 6), 3);
+        assert_eq!(byte_to_grapheme(line, 7), 3);
+        assert_eq!(grapheme_to_col(line, 3, 8), 4);
+        assert_eq!(grapheme_to_col(line, 6, 8), 8);
+        assert_eq!(col_to_grapheme(line, 3, 8), 2);
+        assert_eq!(col_to_grapheme(line, 99, 8), 6);
+    }
+
+    #[test]
+    fn tabs_expand_to_the_next_tabstop() {
+        let line = "a\tb\t\tc";
+
+        assert_eq!(grapheme_to_col(line, 2, 4), 4);
+        assert_eq!(grapheme_to_col(line, 5, 4), 12);
+        assert_eq!(col_to_grapheme(line, 2, 4), 1);
+        assert_eq!(col_to_grapheme(line, 6, 4), 3);
+        assert_eq!(indent_fill(2, 9, 4, false), "\t\t ");
+        assert_eq!(indent_fill(2, 9, 4, true), "       ");
+    }
+
+    #[test]
+    fn zwj_sequences_are_one_wide_cluster() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        assert_eq!(grapheme_count(family), 1);
+        assert_eq!(grapheme_width(family), 2);
+    }
+
+    #[test]
+    fn control_chars_are_drawn_as_pictures() {
+        assert_eq!(control_picture("\r"), Some('␍'));
+        assert_eq!(control_picture("\u{7f}"), Some('␡'));
+        assert_eq!(control_picture("\t"), None);
+        assert_eq!(control_picture("a"), None);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, ops::Range};
+
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Node, Parser, Point, QueryCursor, Tree};
+
+use super::syntax::{line_chunk, node_text};
+use crate::language::_language::{registry, LanguageConfig};
+
+/// A part of a document written in another language, like a fenced code
+/// block in markdown or the body of a rust macro.
+#[derive(Clone)]
+pub struct Site {
+    pub config: &'static LanguageConfig,
+    pub ranges: Vec<tree_sitter::Range>,
+    // language for the sites nested in this one that don't name their own.
+    pub default_language: Option<&'static LanguageConfig>,
+}
+
+impl Site {
+    pub fn touches(&self, range: &Range<usize>) -> bool {
+        self.ranges
+            .iter()
+            .any(|r| r.start_byte < range.end && r.end_byte > range.start)
+    }
+
+    pub fn key(&self) -> (&'static str, Vec<Range<usize>>) {
+        let ranges = self.ranges.iter().map(|r| r.start_byte..r.end_byte);
+        (self.config.name, ranges.collect())
+    }
+}
+
+/// A site parsed in its own language, along with the sites found inside it.
+pub struct Layer {
+    pub tree: Tree,
+    pub sites: Vec<Site>,
+}
+
+pub fn parse(lines: &[String], site: &Site) -> Option<Layer> {
+    let mut parser = Parser::new();
+    parser.set_language(&site.config.language).ok()?;
+    parser.set_included_ranges(&site.ranges).ok()?;
+    let mut read = |_, point: Point| line_chunk(lines, point);
+    let tree = parser.parse_with_options(&mut read, None, None)?;
+    let sites = find(site.config, &tree, lines, site.default_language);
+    Some(Layer { tree, sites })
+}
+
+/// Runs the injections query of `config` over `tree`, which was parsed from
+/// `lines`. `default` is the language of content that doesn't name one.
+pub fn find(
+    config: &'static LanguageConfig,
+    tree: &Tree,
+    lines: &[String],
+    default: Option<&'static LanguageConfig>,
+) -> Vec<Site> {
+    let Some(query) = config.injection_query() else {
+        return vec![];
+    };
+    let names = query.capture_names();
+    let mut cursor = QueryCursor::new();
+    let text = |node: Node| node_text(lines, node);
+    let mut matches = cursor.matches(query, tree.root_node(), text);
+
+    let mut sites: Vec<Site> = vec![];
+    let mut seen = vec![];
+    // combined matches of a pattern all go into one site, by (pattern, language).
+    let mut combined: HashMap<(usize, &str), usize> = HashMap::new();
+    while let Some(mat) = matches.next() {
+        let mut language = None;
+        let mut default_language = None;
+        let mut include_children = false;
+        let mut is_combined = false;
+        for prop in query.property_settings(mat.pattern_index) {
+            let value = prop.value.as_deref();
+            match prop.key.as_ref() {
+                "injection.language" => language = value.map(String::from),
+                "injection.default-language" => {
+                    default_language = value.and_then(|l| registry().by_name(l))
+                }
+                "injection.include-children" => include_children = true,
+                "injection.combined" => is_combined = true,
+                _ => {}
+            }
+        }
+        let mut ranges = vec![];
+        for capt in mat.captures {
+            match names[capt.index as usize] {
+                "injection.language" => {
+                    let name = node_text(lines, capt.node).map(String::from_utf8_lossy);
+                    language = Some(name.collect());
+                }
+                "injection.content" => ranges.extend(content_ranges(capt.node, include_children)),
+                _ => {}
+            }
+        }
+
+        // a code block that names its language also matches the fallback
+        // pattern for blocks that don't, the first match wins.
+        if ranges.is_empty() || seen.contains(&ranges) {
+            continue;
+        }
+        seen.push(ranges.clone());
+        let Some(config) = resolve(language.as_deref(), default) else {
+            continue;
+        };
+
+        if is_combined {
+            if let Some(&i) = combined.get(&(mat.pattern_index, config.name)) {
+                sites[i].ranges.extend(ranges);
+                continue;
+            }
+            combined.insert((mat.pattern_index, config.name), sites.len());
+        }
+        sites.push(Site {
+            config,
+            ranges,
+            default_language,
+        });
+    }
+
+    for site in &mut sites {
+        site.ranges.sort_by_key(|r| r.start_byte);
+        site.ranges.dedup_by_key(|r| r.start_byte);
+    }
+    sites
+}
+
+// names like "rust,no_run" or "python title=x" go by their first known word.
+// a name nothing matches falls back to the default, since code blocks in rust
+// docs are often only marked `ignore` or `no_run`, except for plain text.
+fn resolve(
+    name: Option<&str>,
+    default: Option<&'static LanguageConfig>,
+) -> Option<&'static LanguageConfig> {
+    let Some(name) = name else {
+        return default;
+    };
+    let mut words = name
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|w| !w.is_empty());
+    if words.clone().next() == Some("text") {
+        return None;
+    }
+    words.find_map(|w| registry().by_name(w)).or(default)
+}
+
+// the parts of the node that belong to the embedded language. without
+// include-children the child nodes are cut out, like the block quote markers
+// in front of the lines of a markdown paragraph.
+fn content_ranges(node: Node, include_children: bool) -> Vec<tree_sitter::Range> {
+    if include_children {
+        return vec![node.range()];
+    }
+    let mut ranges = vec![];
+    let mut start = (node.start_byte(), node.start_position());
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.start_byte() > start.0 {
+            ranges.push(tree_sitter::Range {
+                start_byte: start.0,
+                end_byte: child.start_byte(),
+                start_point: start.1,
+                end_point: child.start_position(),
+            });
+        }
+        start = (child.end_byte(), child.end_position());
+    }
+    if node.end_byte() > start.0 {
+        ranges.push(tree_sitter::Range {
+            start_byte: start.0,
+            end_byte: node.end_byte(),
+            start_point: start.1,
+            end_point: node.end_position(),
+        });
+    }
+    ranges
+}
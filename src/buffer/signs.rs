@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use unicode_width::UnicodeWidthStr;
+
+/// How a sign looks: up to two cells of text in the foreground of one of
+/// the theme's ui styles, see `Ui::by_name`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignDef {
+    pub text: String,
+    pub texthl: String,
+}
+
+/// A sign put on a line. Groups let diagnostics, git and breakpoints clear
+/// their own signs without touching the others, the highest priority sign of
+/// a line is the one shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placed {
+    pub line: usize,
+    pub name: String,
+    pub group: String,
+    pub priority: i32,
+}
+
+/// Priority of a sign placed without one, the same as vim's.
+pub const DEFAULT_PRIORITY: i32 = 10;
+
+// what the editor knows how to draw before anything is defined.
+#[rustfmt::skip]
+const DEFAULTS: &[(&str, &str, &str)] = &[
+    ("error", "E>", "error"),
+    ("warning", "W>", "warning"),
+    ("info", "I>", "info"),
+    ("hint", "H>", "hint"),
+    ("added", "+", "added"),
+    ("changed", "~", "changed"),
+    ("removed", "_", "removed"),
+    ("breakpoint", "●", "error"),
+];
+
+/// The signs of a buffer. They stay on their line when lines are inserted or
+/// deleted above it and go away with it.
+#[derive(Debug)]
+pub struct Signs {
+    defined: HashMap<String, SignDef>,
+    placed: Vec<Placed>,
+}
+
+impl Default for Signs {
+    fn default() -> Self {
+        let defined = DEFAULTS
+            .iter()
+            .map(|(name, text, texthl)| {
+                let def = SignDef {
+                    text: text.to_string(),
+                    texthl: texthl.to_string(),
+                };
+                (name.to_string(), def)
+            })
+            .collect();
+        Self {
+            defined,
+            placed: vec![],
+        }
+    }
+}
+
+impl Signs {
+    /// Defines or redefines the sign `name`, the signs already placed with it
+    /// change too.
+    pub fn define(&mut self, name: &str, def: SignDef) -> anyhow::Result<()> {
+        if !(1..=2).contains(&def.text.width()) {
+            anyhow::bail!("sign text has to be one or two cells: {:?}", def.text);
+        }
+        self.defined.insert(name.to_string(), def);
+        Ok(())
+    }
+
+    /// Puts the sign `name` on `line`, in place of the sign the group already
+    /// has there.
+    pub fn place(&mut self, placed: Placed) -> anyhow::Result<()> {
+        if !self.defined.contains_key(&placed.name) {
+            anyhow::bail!("unknown sign: {}", placed.name);
+        }
+        self.placed
+            .retain(|p| p.line != placed.line || p.group != placed.group);
+        self.placed.push(placed);
+        Ok(())
+    }
+
+    /// Removes the signs on `line` or on every line, of `group` or of all
+    /// groups, and returns how many there were.
+    pub fn unplace(&mut self, line: Option<usize>, group: Option<&str>) -> usize {
+        let before = self.placed.len();
+        self.placed.retain(|p| {
+            line.is_some_and(|line| p.line != line) || group.is_some_and(|group| p.group != group)
+        });
+        before - self.placed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.placed.is_empty()
+    }
+
+    /// The sign shown on `line`, the last one placed wins a tie.
+    pub fn get(&self, line: usize) -> Option<&SignDef> {
+        let placed = self
+            .placed
+            .iter()
+            .filter(|p| p.line == line)
+            .max_by_key(|p| p.priority)?;
+        self.defined.get(&placed.name)
+    }
+
+    // the buffer calls these when a line comes or goes.
+    pub fn line_inserted(&mut self, line: usize) {
+        for p in &mut self.placed {
+            if p.line >= line {
+                p.line += 1;
+            }
+        }
+    }
+
+    pub fn line_removed(&mut self, line: usize) {
+        self.placed.retain(|p| p.line != line);
+        for p in &mut self.placed {
+            if p.line > line {
+                p.line -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(signs: &mut Signs, line: usize, name: &str, group: &str, priority: i32) {
+        let placed = Placed {
+            line,
+            name: name.into(),
+            group: group.into(),
+            priority,
+        };
+        signs.place(placed).unwrap();
+    }
+
+    #[test]
+    fn shows_the_highest_priority_and_follows_lines() {
+        let mut signs = Signs::default();
+        place(&mut signs, 3, "added", "git", 5);
+        place(&mut signs, 3, "error", "diagnostics", 10);
+        place(&mut signs, 6, "breakpoint", "debug", 20);
+        assert_eq!(signs.get(3).unwrap().text, "E>");
+
+        signs.line_inserted(0);
+        signs.line_removed(4);
+        assert!(signs.get(4).is_none());
+        assert_eq!(signs.get(6).unwrap().text, "●");
+
+        assert_eq!(signs.unplace(None, Some("diagnostics")), 0);
+        signs.line_removed(6);
+        assert!(signs.is_empty());
+        let too_wide = SignDef {
+            text: ">>>".into(),
+            texthl: "error".into(),
+        };
+        assert!(signs.define("wide", too_wide).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{collections::HashMap, ops::Range};
+
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Node, Parser, Point, Query, QueryCursor, Tree};
+
+use super::injection::{self, Layer, Site};
+use crate::language::_language::LanguageConfig;
+
+// how deep injections may nest, a macro inside a macro inside a macro...
+const MAX_INJECTION_DEPTH: usize = 4;
+
+/// Syntax tree of a whole buffer. It is parsed once and after that only
+/// reparsed incrementally, every change to the buffer has to be reported
+/// through `edit` first.
+pub struct Syntax {
+    pub config: &'static LanguageConfig,
+    parser: Parser,
+    tree: Option<Tree>,
+    query: &'static Query, // compiled once per language, see LanguageConfig::highlight_query
+    sites: Vec<Site>,      // injections in the tree
+    layers: HashMap<(&'static str, Vec<Range<usize>>), Layer>, // injections parsed so far, dropped on edits
+    dirty: bool,
+}
+
+impl Syntax {
+    pub fn new(config: &'static LanguageConfig) -> anyhow::Result<Self> {
+        let mut parser = Parser::new();
+        parser.set_language(&config.language)?;
+        Ok(Self {
+            config,
+            parser,
+            tree: None,
+            query: config.highlight_query()?,
+            sites: vec![],
+            layers: HashMap::new(),
+            dirty: true,
+        })
+    }
+
+    // `old` at (row, col) got replaced with `new`. col and start_byte are in
+    // bytes, the same as tree-sitter uses.
+    pub fn edit(&mut self, start_byte: usize, row: usize, col: usize, old: &str, new: &str) {
+        self.dirty = true;
+        let Some(tree) = &mut self.tree else {
+            return;
+        };
+        let start = Point::new(row, col);
+        tree.edit(&InputEdit {
+            start_byte,
+            old_end_byte: start_byte + old.len(),
+            new_end_byte: start_byte + new.len(),
+            start_position: start,
+            old_end_position: advance(start, old),
+            new_end_position: advance(start, new),
+        });
+    }
+
+    fn update(&mut self, lines: &[String]) {
+        if !self.dirty {
+            return;
+        }
+        let mut read = |_, point: Point| line_chunk(lines, point);
+        self.tree = self
+            .parser
+            .parse_with_options(&mut read, self.tree.as_ref(), None);
+        self.sites = match &self.tree {
+            Some(tree) => injection::find(self.config, tree, lines, None),
+            None => vec![],
+        };
+        self.layers.clear();
+        self.dirty = false;
+    }
+
+    /// Highlight captures that touch the byte range, as (byte range, capture
+    /// name) pairs. The range is matched against the tree of the full
+    /// document so constructs starting above it are still known. Spans from
+    /// injected languages come before the spans of the text around them, the
+    /// first span that covers a position is the one that counts.
+    pub fn highlights(
+        &mut self,
+        lines: &[String],
+        range: Range<usize>,
+    ) -> Vec<(Range<usize>, &'static str)> {
+        self.update(lines);
+        let Some(tree) = self.tree.clone() else {
+            return vec![];
+        };
+        let mut spans = vec![];
+        for site in self.sites.clone() {
+            if site.touches(&range) {
+                self.injected_highlights(lines, &site, &range, 1, &mut spans);
+            }
+        }
+        spans.extend(captures(self.query, &tree, lines, range));
+        spans
+    }
+
+    fn injected_highlights(
+        &mut self,
+        lines: &[String],
+        site: &Site,
+        range: &Range<usize>,
+        depth: usize,
+        spans: &mut Vec<(Range<usize>, &'static str)>,
+    ) {
+        if depth > MAX_INJECTION_DEPTH {
+            return;
+        }
+        let key = site.key();
+        if !self.layers.contains_key(&key) {
+            let Some(layer) = injection::parse(lines, site) else {
+                return;
+            };
+            self.layers.insert(key.clone(), layer);
+        }
+        let layer = &self.layers[&key];
+        let (tree, nested) = (layer.tree.clone(), layer.sites.clone());
+        for nested in nested.iter().filter(|s| s.touches(range)) {
+            self.injected_highlights(lines, nested, range, depth + 1, spans);
+        }
+        if let Ok(query) = site.config.highlight_query() {
+            spans.extend(captures(query, &tree, lines, range.clone()));
+        }
+    }
+}
+
+fn captures(
+    query: &'static Query,
+    tree: &Tree,
+    lines: &[String],
+    range: Range<usize>,
+) -> Vec<(Range<usize>, &'static str)> {
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(range);
+    let text = |node: Node| node_text(lines, node);
+    let names = query.capture_names();
+    let mut matches = cursor.matches(query, tree.root_node(), text);
+    let mut spans = vec![];
+    while let Some(mat) = matches.next() {
+        for capt in mat.captures {
+            let name = names[capt.index as usize];
+            // the locals patterns only mark scopes, they don't color anything.
+            if !name.starts_with("local") && !name.starts_with('_') {
+                spans.push((capt.node.byte_range(), name));
+            }
+        }
+    }
+    spans
+}
+
+fn advance(start: Point, text: &str) -> Point {
+    match text.rfind('\n') {
+        Some(i) => Point::new(start.row + text.matches('\n').count(), text.len() - i - 1),
+        None => Point::new(start.row, start.column + text.len()),
+    }
+}
+
+// the text of a buffer is its lines joined with '\n', the parser is fed one
+// line at a time straight from them.
+pub fn line_chunk(lines: &[String], point: Point) -> &[u8] {
+    let Some(line) = lines.get(point.row) else {
+        return b"";
+    };
+    let line = line.as_bytes();
+    if point.column < line.len() {
+        &line[point.column..]
+    } else if point.row + 1 < lines.len() {
+        b"\n"
+    } else {
+        b""
+    }
+}
+
+pub fn node_text<'a>(lines: &'a [String], node: Node) -> impl Iterator<Item = &'a [u8]> + 'a {
+    let start = node.start_position();
+    let end = node.end_position();
+    (start.row..=end.row)
+        .filter(move |row| *row < lines.len())
+        .flat_map(move |row| {
+            let line = lines[row].as_bytes();
+            let from = if row == start.row {
+                start.column.min(line.len())
+            } else {
+                0
+            };
+            let to = if row == end.row {
+                end.column.min(line.len())
+            } else {
+                line.len()
+            };
+            let newline: &[u8] = if row < end.row { b"\n" } else { b"" };
+            [&line[from..to.max(from)], newline]
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::_language::registry;
+
+    fn rust() -> Syntax {
+        Syntax::new(registry().by_name("rust").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn incremental_parse_matches_a_fresh_one() {
+        let mut lines = vec!["let a = 1;".to_string(), "let b = 2; */".to_string()];
+        let mut syntax = rust();
+        syntax.highlights(&lines, 0..usize::MAX);
+
+        // opening the comment above the highlighted range has to reach it.
+        syntax.edit(0, 0, 0, "", "/* ");
+        lines[0].insert_str(0, "/* ");
+        assert_eq!(syntax.highlights(&lines, 14..27), vec![(0..27, "comment")]);
+
+        let mut fresh = rust();
+        fresh.highlights(&lines, 0..usize::MAX);
+        assert_eq!(
+            syntax.tree.as_ref().unwrap().root_node().to_sexp(),
+            fresh.tree.as_ref().unwrap().root_node().to_sexp()
+        );
+    }
+
+    fn spans_at(syntax: &mut Syntax, lines: &[String], byte: usize) -> Vec<&'static str> {
+        let spans = syntax.highlights(lines, 0..usize::MAX);
+        let spans = spans.into_iter().filter(|(r, _)| r.contains(&byte));
+        spans.map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn injected_code_is_highlighted_first() {
+        let markdown = registry().by_name("markdown").unwrap();
+        let lines: Vec<String> = ["# title", "```rust", "let a = 1;", "```"]
+            .map(String::from)
+            .into();
+        let mut syntax = Syntax::new(markdown).unwrap();
+        // "let" starts at byte 16.
+        assert_eq!(spans_at(&mut syntax, &lines, 16).first(), Some(&"keyword"));
+
+        // rustdoc code blocks are rust unless they say otherwise.
+        let lines: Vec<String> = ["/// ```", "/// let a = 1;", "/// ```", "fn a() {}"]
+            .map(String::from)
+            .into();
+        let mut syntax = rust();
+        let spans = spans_at(&mut syntax, &lines, 12);
+        assert_eq!(spans.first(), Some(&"keyword"));
+        assert_eq!(spans.last(), Some(&"comment.documentation"));
+    }
+}
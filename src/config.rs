@@ -0,0 +1,2 @@
+pub mod _config;
+pub mod editorconfig;
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+use serde::{de::Error, Deserialize, Deserializer};
+use toml::Spanned;
+
+use crate::editor::keymap::{Binding, DEFAULT_LEADER};
+use crate::editor::keys::{parse_keys, Key};
+use crate::editor::main_editor::is_command;
+use crate::editor::mode::Mode;
+use crate::editor::options::{Assignment, Options};
+use crate::theme::depth::ColorDepth;
+
+/// Name of the config file that applies to one project, looked up from the
+/// working directory upwards.
+const PROJECT_FILE: &str = ".rusted.toml";
+
+/// Everything the config files set, project settings over the user's own.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub options: Options,
+    pub theme: Option<String>,
+    pub colors: Option<ColorDepth>,
+    pub leader: Option<Key>,
+    pub keybindings: Vec<(Mode, Vec<Key>, Binding)>,
+    pub filetypes: HashMap<String, String>, // :setlocal arguments for each filetype
+}
+
+// one config file as it is written. everything is optional, whatever a file
+// leaves out keeps the value it had.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    editor: EditorSection,
+    #[serde(default)]
+    keybindings: HashMap<String, HashMap<String, Spanned<BindingValue>>>,
+    #[serde(default)]
+    filetypes: HashMap<String, Spanned<String>>,
+}
+
+// a command name or keys, which aren't mapped again unless the table form
+// asks for it.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum BindingValue {
+    Plain(String),
+    Table {
+        keys: String,
+        #[serde(default)]
+        remap: bool,
+    },
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct EditorSection {
+    line_numbers: Option<bool>,
+    relative_line_numbers: Option<bool>,
+    number_width: Option<usize>,
+    sign_column: Option<String>,
+    tab_width: Option<NonZeroUsize>,
+    shift_width: Option<usize>, // 0 means use tab_width
+    soft_tab_stop: Option<isize>,
+    expand_tab: Option<bool>,
+    wrap: Option<bool>,
+    line_break: Option<bool>,
+    break_indent: Option<bool>,
+    show_break: Option<String>,
+    scrolloff: Option<usize>,
+    side_scroll: Option<usize>,
+    side_scrolloff: Option<usize>,
+    cursor_line: Option<bool>,
+    color_column: Option<Vec<usize>>,
+    list: Option<bool>,
+    ignore_case: Option<bool>,
+    smart_case: Option<bool>,
+    file_format: Option<String>,
+    theme: Option<String>,
+    watch_theme: Option<bool>,
+    timeout_len: Option<usize>,
+    leader: Option<String>,
+    #[serde(default, deserialize_with = "color_depth")]
+    colors: Option<ColorDepth>,
+}
+
+// the depth can be written as a number or as "truecolor".
+fn color_depth<'de, D: Deserializer<'de>>(d: D) -> Result<Option<ColorDepth>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Depth {
+        Number(u32),
+        Name(String),
+    }
+    let value = match Depth::deserialize(d)? {
+        Depth::Number(n) => n.to_string(),
+        Depth::Name(name) => name,
+    };
+    ColorDepth::parse(&value)
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+impl Config {
+    /// Reads the user config and then the project one. A file with errors is
+    /// skipped, the errors come back to be shown.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut errors = vec![];
+        for path in config_files() {
+            let Ok(source) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Err(e) = config.apply(&source) {
+                errors.push(format!("{}: {e:#}", path.display()));
+            }
+        }
+        (config, errors)
+    }
+
+    // nothing is changed unless the whole file is valid.
+    fn apply(&mut self, source: &str) -> anyhow::Result<()> {
+        // toml's own errors quote the source over several lines, the message
+        // line has room for one.
+        let file: ConfigFile = toml::from_str(source).map_err(|e| match e.span() {
+            Some(span) => {
+                let (line, col) = line_col(source, span.start);
+                anyhow::anyhow!("line {line}, column {col}: {}", e.message())
+            }
+            None => anyhow::anyhow!("{}", e.message()),
+        })?;
+
+        let editor = file.editor;
+        let leader = match &editor.leader {
+            Some(leader) => match parse_keys(leader, DEFAULT_LEADER)?[..] {
+                [key] => Some(key),
+                _ => anyhow::bail!("leader: {leader} is more than one key"),
+            },
+            None => self.leader,
+        };
+        let mut keybindings = vec![];
+        for (mode, bindings) in &file.keybindings {
+            let mode = match mode.as_str() {
+                "normal" => Mode::Normal,
+                "insert" => Mode::Insert,
+                "command" => Mode::Command,
+                _ => anyhow::bail!("keybindings.{mode}: modes are normal, insert and command"),
+            };
+            for (lhs, rhs) in bindings {
+                let at = |e: anyhow::Error| {
+                    let (line, col) = line_col(source, rhs.span().start);
+                    anyhow::anyhow!("line {line}, column {col}: {e}")
+                };
+                let leader = leader.unwrap_or(DEFAULT_LEADER);
+                let keys = parse_keys(lhs, leader).map_err(at)?;
+                let (rhs, noremap) = match rhs.get_ref() {
+                    BindingValue::Plain(keys) => (keys, true),
+                    BindingValue::Table { keys, remap } => (keys, !remap),
+                };
+                let binding = Binding::parse(rhs, noremap, leader, is_command).map_err(at)?;
+                keybindings.push((mode, keys, binding));
+            }
+        }
+
+        // each filetype's arguments are tried on the defaults to catch typos.
+        let mut filetypes = vec![];
+        for (filetype, args) in file.filetypes {
+            let at = |e: anyhow::Error| {
+                let (line, col) = line_col(source, args.span().start);
+                anyhow::anyhow!("line {line}, column {col}: {e}")
+            };
+            for arg in args.get_ref().split_whitespace() {
+                let assignment = Assignment::parse(arg).map_err(at)?;
+                assignment.apply(&mut Options::default()).map_err(at)?;
+            }
+            filetypes.push((filetype, args.into_inner()));
+        }
+
+        let mut options = self.options.clone();
+        let set = |option: &mut usize, value: Option<usize>| *option = value.unwrap_or(*option);
+        set(&mut options.numberwidth, editor.number_width);
+        set(&mut options.tabstop, editor.tab_width.map(usize::from));
+        set(&mut options.shiftwidth, editor.shift_width);
+        set(&mut options.scrolloff, editor.scrolloff);
+        set(&mut options.sidescroll, editor.side_scroll);
+        set(&mut options.sidescrolloff, editor.side_scrolloff);
+        for (option, value) in [
+            (&mut options.number, editor.line_numbers),
+            (&mut options.relativenumber, editor.relative_line_numbers),
+            (&mut options.expandtab, editor.expand_tab),
+            (&mut options.wrap, editor.wrap),
+            (&mut options.linebreak, editor.line_break),
+            (&mut options.breakindent, editor.break_indent),
+            (&mut options.cursorline, editor.cursor_line),
+            (&mut options.list, editor.list),
+            (&mut options.ignorecase, editor.ignore_case),
+            (&mut options.smartcase, editor.smart_case),
+            (&mut options.watchtheme, editor.watch_theme),
+        ] {
+            *option = value.unwrap_or(*option);
+        }
+        options.timeoutlen = editor.timeout_len.unwrap_or(options.timeoutlen);
+        options.softtabstop = editor.soft_tab_stop.unwrap_or(options.softtabstop);
+        if let Some(showbreak) = editor.show_break {
+            options.showbreak = showbreak;
+        }
+        if let Some(columns) = editor.color_column {
+            options.colorcolumn = columns.iter().map(usize::to_string).collect();
+        }
+        if let Some(signcolumn) = editor.sign_column {
+            options.signcolumn = signcolumn;
+        }
+        if let Some(format) = editor.file_format {
+            options.fileformat = format;
+        }
+        options.check()?;
+
+        self.options = options;
+        self.keybindings.extend(keybindings);
+        self.leader = leader;
+        self.filetypes.extend(filetypes);
+        self.theme = editor.theme.or(self.theme.take());
+        self.colors = editor.colors.or(self.colors);
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/rusted/config.toml`, or the same under ~/.config.
+fn user_config() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("rusted").join("config.toml"))
+}
+
+fn project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+fn config_files() -> Vec<PathBuf> {
+    let cwd = std::env::current_dir().ok();
+    let project = cwd.as_deref().and_then(project_config);
+    user_config().into_iter().chain(project).collect()
+}
+
+// 1-based line and column of a byte offset into the source.
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+    (line, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_files_in_order() {
+        let mut config = Config::default();
+        config
+            .apply(
+                r#"
+                [editor]
+                tab_width = 8
+                wrap = true
+                colors = 256
+                theme = "mocha"
+                leader = "<Space>"
+                [keybindings.normal]
+                "<C-s>" = ":w<CR>"
+                "<leader>q" = "quit"
+                "#,
+            )
+            .unwrap();
+        config
+            .apply("[editor]\ntab_width = 2\n[filetypes]\npython = \"sw=2 noet\"\n")
+            .unwrap();
+        assert_eq!(config.options.tabstop, 2);
+        assert!(config.options.wrap);
+        assert_eq!(config.colors, Some(ColorDepth::Ansi256));
+        assert_eq!(config.theme.as_deref(), Some("mocha"));
+        assert_eq!(config.filetypes["python"], "sw=2 noet");
+        let binding = |lhs: &str| {
+            let keys = parse_keys(lhs, DEFAULT_LEADER).unwrap();
+            let found = config.keybindings.iter().find(|(_, k, _)| *k == keys);
+            found.map(|(_, _, binding)| binding.clone())
+        };
+        assert!(matches!(
+            binding("<C-s>"),
+            Some(Binding::Keys { keys, noremap: true }) if keys.len() == 3
+        ));
+        assert_eq!(binding(" q"), Some(Binding::Command("quit".into())));
+    }
+
+    #[test]
+    fn reports_where_the_error_is() {
+        let mut config = Config::default();
+        let err = config.apply("[editor]\ntab_width = 0\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 13: invalid value: integer `0`, expected a nonzero usize"
+        );
+        let err = config.apply("[editor]\ntabwidth = 4\n").unwrap_err();
+        assert!(
+            err.to_string().contains("unknown field `tabwidth`"),
+            "{err}"
+        );
+        let err = config
+            .apply("[keybindings.normal]\nx = \"\"\n")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "line 2, column 5: no keys given");
+        let err = config.apply("[filetypes]\ngo = \"ts=0\"\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 6: tabstop must be positive"
+        );
+        assert_eq!(config.options.tabstop, Options::default().tabstop);
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Name of the files looked up from the directory of a file upwards, see
+/// https://editorconfig.org.
+const FILE_NAME: &str = ".editorconfig";
+
+// one file as it is written, sections in order.
+#[derive(Debug, Default)]
+struct EditorConfig {
+    root: bool, // files further up are not read
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+/// The properties that apply to `path` from every .editorconfig above it,
+/// nearer files and later sections winning. Keys and values are lower case.
+/// Files that can't be read are skipped like missing ones.
+pub fn properties(path: &Path) -> HashMap<String, String> {
+    let Ok(path) = std::path::absolute(path) else {
+        return HashMap::new();
+    };
+    let mut files = vec![];
+    for dir in path.ancestors().skip(1) {
+        let Ok(source) = std::fs::read_to_string(dir.join(FILE_NAME)) else {
+            continue;
+        };
+        let file = parse(&source);
+        let root = file.root;
+        files.push((dir, file));
+        if root {
+            break;
+        }
+    }
+
+    let mut properties = HashMap::new();
+    for (dir, file) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative: Vec<_> = relative.iter().map(|c| c.to_string_lossy()).collect();
+        let relative = format!("/{}", relative.join("/"));
+        for (glob, section) in &file.sections {
+            if !section_matches(glob, &relative) {
+                continue;
+            }
+            for (key, value) in section {
+                if value == "unset" {
+                    properties.remove(key);
+                } else {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+    properties
+}
+
+/// What the properties mean as :setlocal arguments. Properties and values
+/// the editor doesn't know are left out, like the spec asks.
+pub fn setlocal_args(properties: &HashMap<String, String>) -> Vec<String> {
+    let get = |key: &str| properties.get(key).map(String::as_str);
+    let number = |key: &str| get(key).filter(|v| v.parse::<usize>().is_ok());
+    let mut args = vec![];
+    match get("indent_style") {
+        Some("tab") => args.push("noexpandtab".to_string()),
+        Some("space") => args.push("expandtab".to_string()),
+        _ => (),
+    }
+    // tab_width is indent_size unless it is given too.
+    match get("indent_size") {
+        Some("tab") => args.extend(["shiftwidth=0".into(), "softtabstop=0".into()]),
+        Some(size) if number("indent_size").is_some() => {
+            args.extend([format!("shiftwidth={size}"), "softtabstop=-1".into()]);
+            if number("tab_width").is_none() {
+                args.push(format!("tabstop={size}"));
+            }
+        }
+        _ => (),
+    }
+    if let Some(width) = number("tab_width") {
+        args.push(format!("tabstop={width}"));
+    }
+    match get("end_of_line") {
+        Some("lf") => args.push("fileformat=unix".into()),
+        Some("crlf") => args.push("fileformat=dos".into()),
+        Some("cr") => args.push("fileformat=mac".into()),
+        _ => (),
+    }
+    match get("charset") {
+        Some("utf-8") => args.extend(["fileencoding=utf-8".into(), "nobomb".into()]),
+        Some("utf-8-bom") => args.extend(["fileencoding=utf-8".into(), "bomb".into()]),
+        Some(charset @ ("latin1" | "utf-16le" | "utf-16be")) => {
+            args.push(format!("fileencoding={charset}"))
+        }
+        _ => (),
+    }
+    let flag = |key: &str, option: &str| match get(key) {
+        Some("true") => Some(option.to_string()),
+        Some("false") => Some(format!("no{option}")),
+        _ => None,
+    };
+    args.extend(flag("trim_trailing_whitespace", "trimwhitespace"));
+    args.extend(flag("insert_final_newline", "fixendofline"));
+    match get("max_line_length") {
+        Some("off") => args.push("textwidth=0".into()),
+        Some(length) if number("max_line_length").is_some() => {
+            args.push(format!("textwidth={length}"))
+        }
+        _ => (),
+    }
+    args
+}
+
+fn parse(source: &str) -> EditorConfig {
+    let mut file = EditorConfig::default();
+    for line in source.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            file.sections.push((glob.to_string(), vec![]));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        match file.sections.last_mut() {
+            Some((_, section)) => section.push((key, value)),
+            None if key == "root" => file.root = value == "true",
+            None => (),
+        }
+    }
+    file
+}
+
+// a glob without a slash matches the file name in any directory below the
+// .editorconfig, one with a slash the path from there.
+fn section_matches(glob: &str, path: &str) -> bool {
+    let pattern = if !glob.contains('/') {
+        format!("**/{glob}")
+    } else if glob.starts_with('/') {
+        glob.to_string()
+    } else {
+        format!("/{glob}")
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_match(&pattern, &path)
+}
+
+// the editorconfig globs: `*` stays inside a directory, `**` doesn't, `?`,
+// `[a-z]` and `[!a-z]`, `{a,b}` and `{1..10}`, and `\` escapes.
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    let Some(&first) = pattern.first() else {
+        return text.is_empty();
+    };
+    let one = |matches: bool| matches && glob_match(&pattern[1..], &text[1..]);
+    match first {
+        '*' if pattern.get(1) == Some(&'*') => {
+            (0..=text.len()).any(|i| glob_match(&pattern[2..], &text[i..]))
+        }
+        '*' => {
+            let dir_end = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+            (0..=dir_end).any(|i| glob_match(&pattern[1..], &text[i..]))
+        }
+        '?' => text.first().is_some_and(|c| *c != '/') && one(true),
+        '[' => match class(&pattern[1..]) {
+            Some((matches, len)) => {
+                text.first().is_some_and(|c| *c != '/' && matches(*c))
+                    && glob_match(&pattern[len + 1..], &text[1..])
+            }
+            None => text.first() == Some(&'[') && one(true),
+        },
+        '{' => match braces(pattern) {
+            Some((alternatives, rest)) => alternatives.into_iter().any(|alternative| {
+                let pattern: Vec<char> = alternative
+                    .into_iter()
+                    .chain(rest.iter().copied())
+                    .collect();
+                glob_match(&pattern, text)
+            }),
+            None => match number_range(pattern) {
+                Some((range, rest)) => {
+                    // the longest run of digits that is in range, or a shorter one.
+                    let digits = text
+                        .iter()
+                        .enumerate()
+                        .take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && **c == '-'))
+                        .count();
+                    (1..=digits).rev().any(|len| {
+                        let n: String = text[..len].iter().collect();
+                        n.parse().is_ok_and(|n: i64| range.contains(&n))
+                            && glob_match(rest, &text[len..])
+                    })
+                }
+                None => text.first() == Some(&'{') && one(true),
+            },
+        },
+        '\\' if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..])
+        }
+        c => text.first() == Some(&c) && one(true),
+    }
+}
+
+// a bracket expression after its `[`: what it matches and how many chars it
+// takes up, the `]` included. None if it is never closed.
+fn class(pattern: &[char]) -> Option<(impl Fn(char) -> bool + '_, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    // a `]` right at the start is one of the chars.
+    let close = start + 1 + pattern.get(start + 1..)?.iter().position(|c| *c == ']')?;
+    let items = &pattern[start..close];
+    let matches = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < items.len() {
+            if items.get(i + 1) == Some(&'-') && i + 2 < items.len() {
+                found |= (items[i]..=items[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= items[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((matches, close + 1))
+}
+
+// the `}` that closes the `{` at the start of the pattern.
+fn closing_brace(pattern: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+// `{a,b}` split at its top level commas, and what follows it. a `{}` without
+// commas is no alternation.
+fn braces(pattern: &[char]) -> Option<(Vec<Vec<char>>, &[char])> {
+    let close = closing_brace(pattern)?;
+    let mut alternatives = vec![vec![]];
+    let mut depth = 0;
+    let mut escaped = false;
+    for &c in &pattern[1..close] {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(vec![]);
+                continue;
+            }
+            _ => (),
+        }
+        alternatives.last_mut()?.push(c);
+    }
+    (alternatives.len() > 1).then_some((alternatives, &pattern[close + 1..]))
+}
+
+// `{3..15}`, the numbers it allows and what follows it.
+fn number_range(pattern: &[char]) -> Option<(std::ops::RangeInclusive<i64>, &[char])> {
+    let close = closing_brace(pattern)?;
+    let inner: String = pattern[1..close].iter().collect();
+    let (from, to) = inner.split_once("..")?;
+    let range = from.parse().ok()?..=to.parse().ok()?;
+    Some((range, &pattern[close + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn matches_editorconfig_globs() {
+        let matches = |glob: &str, path: &str| section_matches(glob, path);
+        assert!(matches("*", "/src/main.rs"));
+        assert!(matches("*.rs", "/src/main.rs"));
+        assert!(!matches("/*.rs", "/src/main.rs"));
+        assert!(matches("src/*.rs", "/src/main.rs"));
+        assert!(!matches("src/*.rs", "/src/editor/mode.rs"));
+        assert!(matches("src/**.rs", "/src/editor/mode.rs"));
+        assert!(matches("*.{js,ts}", "/web/app.ts"));
+        assert!(!matches("*.{js,ts}", "/web/app.rs"));
+        assert!(matches("{package.json,.travis.yml}", "/package.json"));
+        assert!(matches("file[0-9].txt", "/file7.txt"));
+        assert!(!matches("file[!0-9].txt", "/file7.txt"));
+        assert!(matches("part{1..12}.md", "/part10.md"));
+        assert!(!matches("part{1..12}.md", "/part13.md"));
+        assert!(matches("Makefile", "/sub/Makefile"));
+    }
+
+    #[test]
+    fn maps_properties_to_options() {
+        let dir = TestDir::new("editorconfig");
+        let sub = dir.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(
+            dir.join(FILE_NAME),
+            "root = true\n[*]\nindent_style = space\nindent_size = 2\nend_of_line = crlf\n\
+             [*.go]\nindent_style = tab\n",
+        )
+        .unwrap();
+        std::fs::write(
+            sub.join(FILE_NAME),
+            "[*]\nindent_size = unset\ntrim_trailing_whitespace = TRUE\nmax_line_length = 100\n",
+        )
+        .unwrap();
+
+        let args = setlocal_args(&properties(&sub.join("main.go")));
+        assert_eq!(
+            args,
+            [
+                "noexpandtab",
+                "fileformat=dos",
+                "trimwhitespace",
+                "textwidth=100"
+            ]
+        );
+        let args = setlocal_args(&properties(&dir.join("a.py")));
+        assert_eq!(
+            args,
+            [
+                "expandtab",
+                "shiftwidth=2",
+                "softtabstop=-1",
+                "tabstop=2",
+                "fileformat=dos"
+            ]
+        );
+    }
+}
@@ -1,3 +1,12 @@
 pub mod main_editor;
 pub mod mode;
 pub mod action;
+pub mod options;
+pub mod wrap;
+pub mod viewport;
+pub mod grid;
+pub mod command;
+pub mod keys;
+pub mod keymap;
+pub mod picker;
+pub mod window;
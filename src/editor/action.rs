@@ -1,6 +1,5 @@
 use super::{main_editor::InsertModeTextAddInfo, mode::Mode};
 
-
 #[derive(Debug)]
 pub enum Action {
     Quit,
@@ -8,20 +7,36 @@ pub enum Action {
     MoveDown,
     MoveLeft,
     MoveRight,
+    MoveDisplayLineDown,
+    MoveDisplayLineUp,
     MoveToEndOfLine,
     MoveToBeginningOfLine,
     InsertCharCursorPos(char),
     DeleteCharCursorPos,
-    UndoInsertModeTextAdd(InsertModeTextAddInfo), 
+    UndoInsertModeTextAdd(InsertModeTextAddInfo),
     InsertLineBelowCursor,
     GoToEndOfBuffer,
     PageUp,
     PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    ScrollLineUp,
+    ScrollLineDown,
     DeleteFullLine,
-    EnterWaitingMode(char),
     EnterMode(Mode),
+    ExecuteCommand(String),
     Undo,
     CenterLineToViewport,
+    LineToTopOfViewport,
+    LineToTopOfViewportFirstNonBlank,
+    LineToBottomOfViewport,
+    ScrollViewLeft,
+    ScrollViewRight,
+    ScrollCursorToStart,
+    ScrollCursorToEnd,
     GoToStartOfBuffer,
     Backspace,
+    InsertTab,
+    IndentLine,
+    DedentLine,
 }
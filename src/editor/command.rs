@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use super::mode::Mode;
+use super::options;
+use crate::theme::loader::available_themes;
+
+/// An ex command typed after `:`.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // :setlocal leaves the global values alone.
+    Set {
+        args: Vec<String>,
+        local: bool,
+    },
+    Colorscheme(Option<String>),
+    // without keys it lists the bindings, without rhs the ones starting with
+    // the keys.
+    Map {
+        mode: Mode,
+        noremap: bool,
+        lhs: Option<String>,
+        rhs: Option<String>,
+    },
+    Unmap {
+        mode: Mode,
+        lhs: String,
+    },
+    // without a path it writes to the buffer's file, force writes it even
+    // when it is read-only.
+    Write {
+        path: Option<String>,
+        quit: bool,
+        force: bool,
+    },
+    // force drops unsaved changes.
+    Quit {
+        force: bool,
+    },
+    // without a path it reads the buffer's file again.
+    Edit {
+        path: Option<String>,
+        force: bool,
+    },
+    // a new window below the current one, or a new tab after it.
+    Split {
+        path: String,
+        tab: bool,
+    },
+    Tabnext,
+    Tabprev,
+    // without a directory it lists the one of the buffer's file.
+    Explore(Option<String>),
+    // without a directory it finds files under the working directory.
+    Files(Option<String>),
+    // without a pattern the search starts as it is typed.
+    Grep {
+        pattern: Option<String>,
+        dir: Option<String>,
+    },
+    Cnext,
+    Cprev,
+    Copen,
+    SignDefine {
+        name: String,
+        text: String,
+        texthl: Option<String>,
+    },
+    // without a line it goes on the cursor line.
+    SignPlace {
+        name: String,
+        line: Option<usize>,
+        group: Option<String>,
+        priority: Option<i32>,
+    },
+    // without a line or group it takes every line or group.
+    SignUnplace {
+        line: Option<usize>,
+        group: Option<String>,
+    },
+}
+
+pub fn parse(input: &str) -> anyhow::Result<Command> {
+    let input = input.trim();
+    let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    if let Some((mode, noremap)) = map_command(name) {
+        // the rhs is the rest of the line, spaces and all.
+        let (lhs, rhs) = args
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((args.trim(), ""));
+        let some = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        return Ok(Command::Map {
+            mode,
+            noremap,
+            lhs: some(lhs),
+            rhs: some(rhs.trim_start()),
+        });
+    }
+    if let Some(mode) = unmap_command(name) {
+        if args.trim().is_empty() {
+            anyhow::bail!("{name} needs the keys to unmap");
+        }
+        return Ok(Command::Unmap {
+            mode,
+            lhs: args.trim().to_string(),
+        });
+    }
+    if matches!(name, "grep" | "gr") {
+        return grep(args);
+    }
+    let args = args.split_whitespace().map(String::from).collect();
+    // :w! and :q! are the same commands with a bang.
+    let (name, force) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    match name {
+        "set" | "se" => Ok(Command::Set { args, local: false }),
+        "setlocal" | "setl" => Ok(Command::Set { args, local: true }),
+        "colorscheme" | "colo" => Ok(Command::Colorscheme(args.into_iter().next())),
+        "write" | "w" => Ok(Command::Write {
+            path: args.into_iter().next(),
+            quit: false,
+            force,
+        }),
+        "wq" | "xit" | "x" => Ok(Command::Write {
+            path: args.into_iter().next(),
+            quit: true,
+            force,
+        }),
+        "quit" | "q" => Ok(Command::Quit { force }),
+        "edit" | "e" => Ok(Command::Edit {
+            path: args.into_iter().next(),
+            force,
+        }),
+        "split" | "sp" | "tabedit" | "tabe" | "tabnew" => {
+            let Some(path) = args.into_iter().next() else {
+                anyhow::bail!("{name} needs a file name, windows don't share buffers");
+            };
+            let tab = name.starts_with("tab");
+            Ok(Command::Split { path, tab })
+        }
+        "tabnext" | "tabn" => Ok(Command::Tabnext),
+        "tabprevious" | "tabp" | "tabNext" | "tabN" => Ok(Command::Tabprev),
+        "Explore" | "Ex" => Ok(Command::Explore(args.into_iter().next())),
+        "Files" => Ok(Command::Files(args.into_iter().next())),
+        "cnext" | "cn" => Ok(Command::Cnext),
+        "cprevious" | "cprev" | "cp" | "cNext" | "cN" => Ok(Command::Cprev),
+        "copen" | "cope" => Ok(Command::Copen),
+        "sign" | "sig" => sign(args),
+        "" => anyhow::bail!("no command given"),
+        _ => anyhow::bail!("not an editor command: {name}"),
+    }
+}
+
+// :grep pattern dir, a pattern with spaces goes in quotes.
+fn grep(args: &str) -> anyhow::Result<Command> {
+    let args = args.trim();
+    let (pattern, rest) = match args.chars().next() {
+        Some(quote @ ('"' | '\'')) => match args[1..].split_once(quote) {
+            Some((pattern, rest)) => (pattern, rest),
+            None => anyhow::bail!("grep: the pattern has no closing {quote}"),
+        },
+        _ => args.split_once(char::is_whitespace).unwrap_or((args, "")),
+    };
+    let some = |s: &str| (!s.is_empty()).then(|| s.to_string());
+    Ok(Command::Grep {
+        pattern: some(pattern),
+        dir: some(rest.trim()),
+    })
+}
+
+// :sign define name text=.. texthl=.., :sign place name line=.. group=..
+// priority=.. and :sign unplace line=.. group=..
+fn sign(args: Vec<String>) -> anyhow::Result<Command> {
+    let mut args = args.into_iter();
+    let Some(subcommand) = args.next() else {
+        anyhow::bail!("sign needs define, place or unplace");
+    };
+    let name = match subcommand.as_str() {
+        "define" | "place" => match args.next() {
+            Some(name) => name,
+            None => anyhow::bail!("sign {subcommand} needs a sign name"),
+        },
+        _ => String::new(),
+    };
+    let mut values = HashMap::new();
+    for arg in args {
+        let Some((key, value)) = arg.split_once('=') else {
+            anyhow::bail!("sign {subcommand}: expected key=value, got {arg}");
+        };
+        values.insert(key.to_string(), value.to_string());
+    }
+    let allowed: &[&str] = match subcommand.as_str() {
+        "define" => &["text", "texthl"],
+        "place" => &["line", "group", "priority"],
+        "unplace" => &["line", "group"],
+        _ => anyhow::bail!("unknown sign command: {subcommand}"),
+    };
+    if let Some(key) = values.keys().find(|key| !allowed.contains(&key.as_str())) {
+        anyhow::bail!("sign {subcommand}: unknown argument {key}");
+    }
+    let line = match values.remove("line") {
+        Some(line) => match line.parse::<usize>() {
+            Ok(line) if line > 0 => Some(line),
+            _ => anyhow::bail!("invalid line: {line}"),
+        },
+        None => None,
+    };
+    let group = values.remove("group");
+    let command = match subcommand.as_str() {
+        "define" => Command::SignDefine {
+            name,
+            text: values
+                .remove("text")
+                .ok_or_else(|| anyhow::anyhow!("sign define needs text="))?,
+            texthl: values.remove("texthl"),
+        },
+        "place" => Command::SignPlace {
+            name,
+            line,
+            group,
+            priority: match values.remove("priority") {
+                Some(priority) => Some(
+                    priority
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("invalid priority: {priority}"))?,
+                ),
+                None => None,
+            },
+        },
+        _ => Command::SignUnplace { line, group },
+    };
+    Ok(command)
+}
+
+// the mode a :map command is for and whether it is a noremap one. plain
+// :map is for normal mode, there is no visual or operator pending mode.
+fn map_command(name: &str) -> Option<(Mode, bool)> {
+    let found = match name {
+        "map" | "nmap" | "nm" => (Mode::Normal, false),
+        "imap" | "im" => (Mode::Insert, false),
+        "cmap" | "cm" => (Mode::Command, false),
+        "noremap" | "no" | "nnoremap" | "nn" => (Mode::Normal, true),
+        "inoremap" | "ino" => (Mode::Insert, true),
+        "cnoremap" | "cno" => (Mode::Command, true),
+        _ => return None,
+    };
+    Some(found)
+}
+
+fn unmap_command(name: &str) -> Option<Mode> {
+    match name {
+        "unmap" | "unm" | "nunmap" | "nun" => Some(Mode::Normal),
+        "iunmap" | "iu" => Some(Mode::Insert),
+        "cunmap" | "cu" => Some(Mode::Command),
+        _ => None,
+    }
+}
+
+/// Whole command lines that complete what has been typed so far, for the
+/// commands that take something to complete.
+pub fn complete(input: &str) -> Vec<String> {
+    let Some((name, arg)) = input.trim_start().split_once(' ') else {
+        return vec![];
+    };
+    // :set completes the option name it ends with.
+    let (name, arg) = match name {
+        "set" | "se" | "setlocal" | "setl" => match arg.rsplit_once(' ') {
+            Some((before, last)) => (format!("{name} {before}"), last),
+            None => (name.to_string(), arg),
+        },
+        _ => (name.to_string(), arg),
+    };
+    let candidates = match name.split(' ').next().unwrap_or_default() {
+        "colorscheme" | "colo" => available_themes(),
+        "set" | "se" | "setlocal" | "setl" => options::names().map(String::from).collect(),
+        _ => return vec![],
+    };
+    let arg = arg.trim_start();
+    candidates
+        .into_iter()
+        .filter(|c| c.starts_with(arg))
+        .map(|c| format!("{} {c}", name.trim_end()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_set() {
+        assert_eq!(
+            parse(" set ft=python  ts=2").unwrap(),
+            Command::Set {
+                args: vec!["ft=python".into(), "ts=2".into()],
+                local: false,
+            }
+        );
+        assert_eq!(complete("setl ts=2 rel"), ["setl ts=2 relativenumber"]);
+        assert_eq!(
+            parse("w new.txt").unwrap(),
+            Command::Write {
+                path: Some("new.txt".into()),
+                quit: false,
+                force: false,
+            }
+        );
+        assert_eq!(
+            parse("wq!").unwrap(),
+            Command::Write {
+                path: None,
+                quit: true,
+                force: true,
+            }
+        );
+        assert_eq!(
+            parse("sign place error line=3 group=lint").unwrap(),
+            Command::SignPlace {
+                name: "error".into(),
+                line: Some(3),
+                group: Some("lint".into()),
+                priority: None,
+            }
+        );
+        assert!(parse("sign place error line=0").is_err());
+        assert_eq!(
+            parse("grep 'fn main' src").unwrap(),
+            Command::Grep {
+                pattern: Some("fn main".into()),
+                dir: Some("src".into()),
+            }
+        );
+        assert_eq!(parse("cN").unwrap(), Command::Cprev);
+        assert_eq!(
+            parse("tabe src/main.rs").unwrap(),
+            Command::Split {
+                path: "src/main.rs".into(),
+                tab: true,
+            }
+        );
+        assert!(parse("sp").is_err());
+        assert!(parse("sign define bp txt=*").is_err());
+        assert_eq!(
+            parse("colo mocha").unwrap(),
+            Command::Colorscheme(Some("mocha".into()))
+        );
+        assert_eq!(
+            parse("nnoremap <leader>t :colo mocha<CR>").unwrap(),
+            Command::Map {
+                mode: Mode::Normal,
+                noremap: true,
+                lhs: Some("<leader>t".into()),
+                rhs: Some(":colo mocha<CR>".into()),
+            }
+        );
+        assert!(matches!(
+            parse("imap").unwrap(),
+            Command::Map {
+                mode: Mode::Insert,
+                lhs: None,
+                ..
+            }
+        ));
+        assert!(parse("iunmap").is_err());
+        assert!(parse("frobnicate").is_err());
+    }
+}
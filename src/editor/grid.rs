@@ -0,0 +1,286 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor::MoveTo,
+    style::{
+        Attribute, Color, ContentStyle, Print, SetAttribute, SetAttributes, SetBackgroundColor,
+        SetForegroundColor,
+    },
+    terminal::{BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate},
+    QueueableCommand,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::buffer::grapheme::grapheme_width;
+use crate::theme::depth::{ColorDepth, Palette};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub symbol: String, // empty for the cells covered by a wide char on their left
+    pub style: ContentStyle,
+    pub width: u8,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: String::from(" "),
+            style: ContentStyle::default(),
+            width: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Grid {
+    pub width: u16,
+    pub height: u16,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width as usize * height as usize],
+        }
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Option<&Cell> {
+        self.index(x, y).map(|i| &self.cells[i])
+    }
+
+    // a wide char that loses one of its cells loses the other ones too, they
+    // are left as blanks. a continuation is always written right after its
+    // own wide char, which stays.
+    fn set(&mut self, x: u16, y: u16, cell: Cell) {
+        let Some(i) = self.index(x, y) else {
+            return;
+        };
+        let row_start = i - x as usize;
+        if cell.width != 0 && self.cells[i].width == 0 {
+            let lead = (row_start..i).rev().find(|&j| self.cells[j].width != 0);
+            for j in lead.unwrap_or(i)..i {
+                self.blank(j);
+            }
+        }
+        let row_end = row_start + self.width as usize;
+        let old_end = (i + self.cells[i].width as usize).min(row_end);
+        for j in i + 1..old_end {
+            self.blank(j);
+        }
+        self.cells[i] = cell;
+    }
+
+    fn blank(&mut self, i: usize) {
+        self.cells[i] = Cell {
+            style: self.cells[i].style,
+            ..Default::default()
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    /// Writes `text` from (x, y) on, giving every grapheme as many cells as it
+    /// is wide. Text past the right edge is dropped. Returns the column right
+    /// after the text.
+    pub fn put_str(&mut self, x: u16, y: u16, text: &str, style: ContentStyle) -> u16 {
+        let mut x = x;
+        for g in text.graphemes(true) {
+            let width = grapheme_width(g) as u16;
+            if x + width > self.width {
+                break;
+            }
+            self.set(
+                x,
+                y,
+                Cell {
+                    symbol: g.to_string(),
+                    style,
+                    width: width as u8,
+                },
+            );
+            for i in 1..width {
+                self.set(
+                    x + i,
+                    y,
+                    Cell {
+                        symbol: String::new(),
+                        style,
+                        width: 0,
+                    },
+                );
+            }
+            x += width;
+        }
+        x
+    }
+
+    /// Gives the cell at (x, y) another background and keeps what it shows.
+    pub fn set_background(&mut self, x: u16, y: u16, color: Color) {
+        if let Some(i) = self.index(x, y) {
+            self.cells[i].style.background_color = Some(color);
+        }
+    }
+
+    pub fn fill(&mut self, x: u16, y: u16, width: u16, style: ContentStyle) {
+        for x in x..x.saturating_add(width).min(self.width) {
+            self.set(
+                x,
+                y,
+                Cell {
+                    style,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+/// Double buffered screen. Everything is drawn into `frame`, `flush` diffs it
+/// against the frame that was sent last and only writes the cells that changed.
+pub struct Screen {
+    pub frame: Grid,
+    last: Grid,
+    full_redraw: bool,
+    palette: Palette, // the frame keeps the theme colors, they are matched to the terminal on write
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            frame: Grid::new(width, height),
+            last: Grid::new(width, height),
+            full_redraw: true,
+            palette: Palette::new(ColorDepth::TrueColor),
+        }
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        let depth = self.palette.depth;
+        *self = Self::new(width, height);
+        self.set_color_depth(depth);
+    }
+
+    pub fn set_color_depth(&mut self, depth: ColorDepth) {
+        self.palette = Palette::new(depth);
+        self.full_redraw = true;
+    }
+
+    pub fn flush(&mut self, out: &mut impl Write) -> io::Result<()> {
+        out.queue(BeginSynchronizedUpdate)?;
+        if self.full_redraw {
+            out.queue(Clear(ClearType::All))?;
+        }
+
+        // where the terminal cursor and pen are after the last write, so moves
+        // and style changes are only sent when they are needed.
+        let mut pos = None;
+        let mut pen: Option<ContentStyle> = None;
+        for y in 0..self.frame.height {
+            for x in 0..self.frame.width {
+                let cell = self.frame.get(x, y).unwrap();
+                if cell.width == 0 || (!self.full_redraw && self.last.get(x, y) == Some(cell)) {
+                    continue;
+                }
+                if pos != Some((x, y)) {
+                    out.queue(MoveTo(x, y))?;
+                }
+                let style = self.palette.style(cell.style);
+                if pen != Some(style) {
+                    apply_style(out, &style, pen.as_ref())?;
+                    pen = Some(style);
+                }
+                out.queue(Print(&cell.symbol))?;
+                pos = Some((x + cell.width as u16, y));
+            }
+        }
+
+        out.queue(SetAttribute(Attribute::Reset))?;
+        out.queue(EndSynchronizedUpdate)?;
+        std::mem::swap(&mut self.frame, &mut self.last);
+        self.frame.reset();
+        self.full_redraw = false;
+        Ok(())
+    }
+}
+
+// attributes can only be turned off all at once, so a change in them resets
+// the pen and sets everything again. otherwise only the colors that differ are
+// sent.
+fn apply_style(
+    out: &mut impl Write,
+    next: &ContentStyle,
+    prev: Option<&ContentStyle>,
+) -> io::Result<()> {
+    let reset = prev.is_none_or(|p| p.attributes != next.attributes);
+    if reset {
+        out.queue(SetAttribute(Attribute::Reset))?;
+        out.queue(SetAttributes(next.attributes))?;
+    }
+    if reset || prev.is_some_and(|p| p.foreground_color != next.foreground_color) {
+        out.queue(SetForegroundColor(
+            next.foreground_color.unwrap_or(Color::Reset),
+        ))?;
+    }
+    if reset || prev.is_some_and(|p| p.background_color != next.background_color) {
+        out.queue(SetBackgroundColor(
+            next.background_color.unwrap_or(Color::Reset),
+        ))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changed_cells_are_written() {
+        let mut screen = Screen::new(10, 2);
+        screen.frame.put_str(0, 0, "hello", ContentStyle::default());
+        screen.flush(&mut Vec::new()).unwrap();
+
+        screen
+            .frame
+            .put_str(0, 0, "help日", ContentStyle::default());
+        let mut out = Vec::new();
+        screen.flush(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("p日"));
+        assert!(!out.contains("hel"));
+        assert_eq!(screen.last.get(5, 0).unwrap().width, 0);
+    }
+
+    #[test]
+    fn writing_over_half_a_wide_char_blanks_the_other_half() {
+        let mut grid = Grid::new(6, 1);
+        let style = ContentStyle::default();
+        grid.put_str(0, 0, "日本", style);
+        // over the right half of 日 and the left half of 本.
+        grid.put_str(1, 0, "ab", style);
+        let row: Vec<_> = (0..4).map(|x| grid.get(x, 0).unwrap().clone()).collect();
+        let symbols: Vec<_> = row.iter().map(|c| c.symbol.as_str()).collect();
+        assert_eq!(symbols, [" ", "a", "b", " "]);
+        assert!(row.iter().all(|c| c.width == 1));
+
+        // a wide char over a wide char shifted by one.
+        grid.put_str(0, 0, "日本", style);
+        grid.put_str(1, 0, "語", style);
+        let symbols: Vec<_> = (0..4)
+            .map(|x| grid.get(x, 0).unwrap().symbol.clone())
+            .collect();
+        assert_eq!(symbols, [" ", "語", "", " "]);
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use super::keys::{key_names, parse_keys, Key};
+use super::mode::Mode;
+
+/// What a key sequence does: run one of the editor's named commands, or act
+/// like other keys. Keys from a `noremap` binding only see the built-in
+/// bindings, the others go through the user's bindings again.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    Command(String),
+    Keys { keys: Vec<Key>, noremap: bool },
+}
+
+impl Binding {
+    /// Reads the right hand side of a mapping, a command name if `is_command`
+    /// knows it and keys otherwise.
+    pub fn parse(
+        rhs: &str,
+        noremap: bool,
+        leader: Key,
+        is_command: impl Fn(&str) -> bool,
+    ) -> anyhow::Result<Self> {
+        if is_command(rhs) {
+            return Ok(Self::Command(rhs.to_string()));
+        }
+        Ok(Self::Keys {
+            keys: parse_keys(rhs, leader)?,
+            noremap,
+        })
+    }
+}
+
+/// What `<leader>` stands for unless the config picks another key.
+pub const DEFAULT_LEADER: Key = Key {
+    code: KeyCode::Char('\\'),
+    modifiers: KeyModifiers::NONE,
+};
+
+/// Key sequences of one mode, one key per level.
+#[derive(Debug, Default)]
+struct KeyTrie {
+    children: HashMap<Key, KeyTrie>,
+    binding: Option<Binding>,
+}
+
+/// Where a sequence of keys ends up in the trie.
+#[derive(Debug, PartialEq)]
+pub enum Lookup<'a> {
+    Bound(&'a Binding),
+    Prefix,                 // longer sequences start with it
+    Ambiguous(&'a Binding), // bound, but longer sequences start with it too
+    Unbound,
+}
+
+impl KeyTrie {
+    fn insert(&mut self, keys: &[Key], binding: Binding) {
+        let mut node = self;
+        for key in keys {
+            node = node.children.entry(*key).or_default();
+        }
+        node.binding = Some(binding);
+    }
+
+    fn lookup(&self, keys: &[Key]) -> Lookup<'_> {
+        let mut node = self;
+        for key in keys {
+            match node.children.get(key) {
+                Some(child) => node = child,
+                None => return Lookup::Unbound,
+            }
+        }
+        match (&node.binding, node.children.is_empty()) {
+            (Some(binding), true) => Lookup::Bound(binding),
+            (Some(binding), false) => Lookup::Ambiguous(binding),
+            (None, false) => Lookup::Prefix,
+            (None, true) => Lookup::Unbound,
+        }
+    }
+
+    fn collect<'a>(&'a self, keys: &mut Vec<Key>, out: &mut Vec<(Vec<Key>, &'a Binding)>) {
+        if let Some(binding) = &self.binding {
+            out.push((keys.clone(), binding));
+        }
+        for (key, child) in &self.children {
+            keys.push(*key);
+            child.collect(keys, out);
+            keys.pop();
+        }
+    }
+}
+
+// the built-in normal mode bindings. insert and command mode have none, the
+// keys nobody bound are handled by the mode itself there.
+#[rustfmt::skip]
+const NORMAL: &[(&str, &str)] = &[
+    ("h", "move_left"), ("<Left>", "move_left"),
+    ("j", "move_down"), ("<Down>", "move_down"),
+    ("k", "move_up"), ("<Up>", "move_up"),
+    ("l", "move_right"), ("<Right>", "move_right"),
+    ("gj", "move_display_line_down"), ("gk", "move_display_line_up"),
+    ("0", "line_start"), ("$", "line_end"),
+    ("gg", "buffer_start"), ("G", "buffer_end"),
+    ("<C-f>", "page_down"), ("<C-b>", "page_up"),
+    ("<C-d>", "half_page_down"), ("<C-u>", "half_page_up"),
+    ("<C-e>", "scroll_line_down"), ("<C-y>", "scroll_line_up"),
+    ("zz", "center_line"), ("zt", "line_to_top"), ("z<CR>", "line_to_top_first_non_blank"),
+    ("zb", "line_to_bottom"), ("zh", "scroll_left"), ("zl", "scroll_right"),
+    ("zs", "scroll_cursor_to_start"), ("ze", "scroll_cursor_to_end"),
+    ("i", "insert_mode"), (":", "command_mode"),
+    ("o", "open_line_below"), ("x", "delete_char"), ("dd", "delete_line"),
+    (">>", "indent_line"), ("<<", "dedent_line"),
+    ("u", "undo"), ("q", "quit"),
+    ("<CR>", "open_entry"), ("-", "parent_directory"),
+    ("gh", "toggle_hidden"), ("gi", "toggle_ignored"), ("gs", "cycle_sort"),
+    ("<leader>f", "find_files"), ("<leader>/", "live_grep"),
+    ("<C-w>w", "next_window"), ("<C-w><C-w>", "next_window"), ("<C-w>W", "previous_window"),
+    ("<C-w>j", "window_below"), ("<C-w>k", "window_above"),
+    ("gt", "next_tab"), ("gT", "previous_tab"),
+];
+
+/// The bindings of every mode: the built-in ones with the user's on top.
+#[derive(Debug)]
+pub struct Keymaps {
+    pub leader: Key,
+    defaults: HashMap<Mode, KeyTrie>,
+    user: HashMap<Mode, Vec<(Vec<Key>, Binding)>>,
+    active: HashMap<Mode, KeyTrie>, // defaults and user bindings together
+}
+
+impl Keymaps {
+    pub fn new(leader: Key) -> Self {
+        let mut normal = KeyTrie::default();
+        for (keys, command) in NORMAL {
+            let keys = parse_keys(keys, leader).expect("built-in bindings are valid");
+            normal.insert(&keys, Binding::Command(command.to_string()));
+        }
+        let mut keymaps = Self {
+            leader,
+            defaults: HashMap::from([(Mode::Normal, normal)]),
+            user: HashMap::new(),
+            active: HashMap::new(),
+        };
+        for mode in [Mode::Normal, Mode::Insert, Mode::Command] {
+            keymaps.rebuild(mode);
+        }
+        keymaps
+    }
+
+    fn rebuild(&mut self, mode: Mode) {
+        let mut trie = KeyTrie::default();
+        if let Some(defaults) = self.defaults.get(&mode) {
+            let mut bindings = vec![];
+            defaults.collect(&mut vec![], &mut bindings);
+            for (keys, binding) in bindings {
+                trie.insert(&keys, binding.clone());
+            }
+        }
+        for (keys, binding) in self.user.get(&mode).into_iter().flatten() {
+            trie.insert(keys, binding.clone());
+        }
+        self.active.insert(mode, trie);
+    }
+
+    pub fn map(&mut self, mode: Mode, keys: Vec<Key>, binding: Binding) {
+        let user = self.user.entry(mode).or_default();
+        user.retain(|(k, _)| *k != keys);
+        user.push((keys, binding));
+        self.rebuild(mode);
+    }
+
+    /// Removes a user binding, the built-in ones can only be overridden.
+    pub fn unmap(&mut self, mode: Mode, keys: &[Key]) -> anyhow::Result<()> {
+        let user = self.user.entry(mode).or_default();
+        let before = user.len();
+        user.retain(|(k, _)| k != keys);
+        if user.len() == before {
+            anyhow::bail!("no such mapping: {}", key_names(keys));
+        }
+        self.rebuild(mode);
+        Ok(())
+    }
+
+    /// `remap` is false for keys that came from a noremap binding, those only
+    /// see the built-in bindings.
+    pub fn lookup(&self, mode: Mode, keys: &[Key], remap: bool) -> Lookup<'_> {
+        let tries = if remap { &self.active } else { &self.defaults };
+        match tries.get(&mode) {
+            Some(trie) => trie.lookup(keys),
+            None => Lookup::Unbound,
+        }
+    }
+
+    /// Every binding of `mode` that starts with `prefix`, one per line and
+    /// sorted by keys. noremap bindings are marked with a `*` like vim does.
+    pub fn list(&self, mode: Mode, prefix: &[Key]) -> Vec<String> {
+        let mut bindings = vec![];
+        if let Some(trie) = self.active.get(&mode) {
+            trie.collect(&mut vec![], &mut bindings);
+        }
+        let mut lines: Vec<(String, String)> = bindings
+            .into_iter()
+            .filter(|(keys, _)| keys.starts_with(prefix))
+            .map(|(keys, binding)| {
+                let rhs = match binding {
+                    Binding::Command(name) => name.clone(),
+                    Binding::Keys { keys, noremap } => {
+                        let star = if *noremap { "*" } else { " " };
+                        format!("{star} {}", key_names(keys))
+                    }
+                };
+                (key_names(&keys), rhs)
+            })
+            .collect();
+        lines.sort();
+        let width = lines.iter().map(|(lhs, _)| lhs.len()).max().unwrap_or(0);
+        lines
+            .into_iter()
+            .map(|(lhs, rhs)| format!("{}  {lhs:<width$}  {rhs}", mode_letter(mode)))
+            .collect()
+    }
+}
+
+fn mode_letter(mode: Mode) -> char {
+    match mode {
+        Mode::Normal => 'n',
+        Mode::Insert => 'i',
+        Mode::Command => 'c',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_bindings_go_over_the_built_in_ones() {
+        let leader = Key::new(KeyCode::Char(' '), KeyModifiers::NONE);
+        let keys = |s| parse_keys(s, leader).unwrap();
+        let mut keymaps = Keymaps::new(leader);
+        let command = |name: &str| Binding::Command(name.to_string());
+
+        assert_eq!(
+            keymaps.lookup(Mode::Normal, &keys("g"), true),
+            Lookup::Prefix
+        );
+        keymaps.map(Mode::Normal, keys("g"), command("buffer_end"));
+        assert_eq!(
+            keymaps.lookup(Mode::Normal, &keys("g"), true),
+            Lookup::Ambiguous(&command("buffer_end"))
+        );
+        // keys from a noremap binding don't see it.
+        assert_eq!(
+            keymaps.lookup(Mode::Normal, &keys("g"), false),
+            Lookup::Prefix
+        );
+
+        keymaps.map(Mode::Normal, keys("<leader>w"), command("quit"));
+        assert_eq!(
+            keymaps.lookup(Mode::Normal, &keys(" w"), true),
+            Lookup::Bound(&command("quit"))
+        );
+        assert_eq!(
+            keymaps.list(Mode::Normal, &keys("<Space>")),
+            [
+                "n  <Space>/  live_grep",
+                "n  <Space>f  find_files",
+                "n  <Space>w  quit"
+            ]
+        );
+
+        keymaps.unmap(Mode::Normal, &keys("g")).unwrap();
+        assert!(keymaps.unmap(Mode::Normal, &keys("gg")).is_err());
+        assert_eq!(
+            keymaps.lookup(Mode::Normal, &keys("gg"), true),
+            Lookup::Bound(&command("buffer_start"))
+        );
+    }
+}
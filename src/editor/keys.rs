@@ -0,0 +1,180 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// A key press without the details that don't matter for mappings. Shift on
+/// a char is dropped since the char itself is already upper case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(ev: KeyEvent) -> Self {
+        Self::new(ev.code, ev.modifiers)
+    }
+}
+
+impl From<Key> for KeyEvent {
+    fn from(key: Key) -> Self {
+        KeyEvent::new(key.code, key.modifiers)
+    }
+}
+
+// names used inside <...>, matched without case.
+const NAMES: &[(&str, KeyCode)] = &[
+    ("esc", KeyCode::Esc),
+    ("cr", KeyCode::Enter),
+    ("enter", KeyCode::Enter),
+    ("return", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("bs", KeyCode::Backspace),
+    ("backspace", KeyCode::Backspace),
+    ("del", KeyCode::Delete),
+    ("space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+    ("bar", KeyCode::Char('|')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("insert", KeyCode::Insert),
+];
+
+/// Reads keys in vim's notation: plain chars stand for themselves and
+/// `<...>` names a special key, optionally with `C-`, `A-`/`M-` and `S-`
+/// modifiers like `<C-s>` or `<S-Tab>`. `<leader>` is the leader key. A `<`
+/// that doesn't start a valid name is just the char.
+pub fn parse_keys(input: &str, leader: Key) -> anyhow::Result<Vec<Key>> {
+    let mut keys = vec![];
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        let special = rest
+            .strip_prefix('<')
+            .and_then(|r| r.split_once('>'))
+            .and_then(|(name, after)| {
+                let key = if name.eq_ignore_ascii_case("leader") {
+                    leader
+                } else {
+                    special_key(name)?
+                };
+                Some((key, after))
+            });
+        match special {
+            Some((key, after)) => {
+                keys.push(key);
+                rest = after;
+            }
+            None => {
+                keys.push(Key::new(KeyCode::Char(c), KeyModifiers::NONE));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if keys.is_empty() {
+        anyhow::bail!("no keys given");
+    }
+    Ok(keys)
+}
+
+fn special_key(name: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = name;
+    // a name of a single char can't be a modifier, so <C--> is ctrl and '-'.
+    while name.len() > 2 && name.as_bytes()[1] == b'-' {
+        modifiers |= match name.as_bytes()[0].to_ascii_lowercase() {
+            b'c' => KeyModifiers::CONTROL,
+            b'a' | b'm' => KeyModifiers::ALT,
+            b's' => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        name = &name[2..];
+    }
+    let lower = name.to_lowercase();
+    let code = if let Some((_, code)) = NAMES.iter().find(|(n, _)| *n == lower) {
+        *code
+    } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        KeyCode::F(n)
+    } else {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if !modifiers.is_empty() => KeyCode::Char(c),
+            _ => return None,
+        }
+    };
+    Some(Key::new(code, modifiers))
+}
+
+/// The notation `parse_keys` reads back to the same keys.
+pub fn key_names(keys: &[Key]) -> String {
+    keys.iter().map(|k| key_name(*k)).collect()
+}
+
+fn key_name(key: Key) -> String {
+    let name = match key.code {
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) if key.modifiers.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{n}"),
+        code => match NAMES.iter().find(|(_, c)| *c == code) {
+            Some((name, _)) => display_name(name),
+            None => format!("{code:?}"),
+        },
+    };
+    let mut prefix = String::new();
+    for (modifier, letter) in [
+        (KeyModifiers::CONTROL, "C-"),
+        (KeyModifiers::ALT, "A-"),
+        (KeyModifiers::SHIFT, "S-"),
+    ] {
+        if key.modifiers.contains(modifier) {
+            prefix.push_str(letter);
+        }
+    }
+    format!("<{prefix}{name}>")
+}
+
+// how vim's docs write the names.
+fn display_name(name: &str) -> String {
+    match name {
+        "cr" | "bs" => name.to_uppercase(),
+        "pageup" => "PageUp".to_string(),
+        "pagedown" => "PageDown".to_string(),
+        _ => name[..1].to_uppercase() + &name[1..],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_key_notation() {
+        let leader = Key::new(KeyCode::Char('\\'), KeyModifiers::NONE);
+        let keys = parse_keys("d<C-s><lt><<S-Tab><f5>x<Leader>", leader).unwrap();
+        let ctrl_s = Key::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert_eq!(keys[1], ctrl_s);
+        assert_eq!(keys[2].code, KeyCode::Char('<'));
+        assert_eq!(keys[3].code, KeyCode::Char('<'));
+        assert_eq!(keys[4], Key::new(KeyCode::Tab, KeyModifiers::SHIFT));
+        assert_eq!(keys[5].code, KeyCode::F(5));
+        assert_eq!(keys[7], leader);
+        assert_eq!(key_names(&keys), "d<C-s><lt><lt><S-Tab><F5>x\\");
+        assert_eq!(parse_keys("<nope>", leader).unwrap().len(), 6);
+        assert!(parse_keys("", leader).is_err());
+    }
+}
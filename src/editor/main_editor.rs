@@ -1,21 +1,216 @@
+use std::collections::{HashMap, VecDeque};
 use std::io::{stdout, Stdout, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use anyhow::Context;
 use crossterm::{
     cursor::{self, MoveTo, SetCursorStyle},
-    event::{self, read, KeyModifiers},
-    style::{self, Color, StyledContent, Stylize},
+    event::{self, read, KeyCode, KeyModifiers},
     terminal, ExecutableCommand, QueueableCommand,
 };
 
 use super::action::Action;
+use super::command::{self, Command};
+use super::grid::{Grid, Screen};
+use super::keymap::{Binding, Keymaps, Lookup, DEFAULT_LEADER};
+use super::keys::{parse_keys, Key};
 use super::mode::Mode;
+use super::options::{self, filetype_defaults, Assignment, Options, Scope};
+use super::picker::{self, Location, Picker};
+use super::viewport::Viewport;
+use super::window::{self, Open, Window};
+use super::wrap::{line_rows, row_of, showbreak_width, wrap_line, DisplayRow};
 use crate::{
+    buffer::grapheme::{
+        cell_width, col_to_grapheme, control_picture, grapheme_to_col, graphemes, indent_fill,
+        whitespace_start,
+    },
+    buffer::{
+        _buffer::Kind,
+        directory::{self, ListSettings},
+        signs::{Placed, SignDef, DEFAULT_PRIORITY},
+    },
+    config::{_config::Config, editorconfig},
     log,
-    theme::_theme::{Style, Theme},
+    theme::{
+        _theme::{Style, Theme},
+        depth::ColorDepth,
+        loader::{load_named, load_theme},
+        watch::ThemeWatcher,
+    },
     Buffer,
 };
-use tree_sitter::{Parser, Query, QueryCursor};
-use tree_sitter_rust::HIGHLIGHT_QUERY;
+use unicode_width::UnicodeWidthStr;
+
+// how often the theme file is checked while waiting for input.
+const THEME_POLL: Duration = Duration::from_millis(200);
+
+// how often an open picker looks for results while they are coming in.
+const PICKER_POLL: Duration = Duration::from_millis(30);
+
+// how many mappings may expand into each other before it counts as a loop.
+const MAX_MAPPING_DEPTH: usize = 100;
+
+type CommandFn = fn(&mut Editor) -> anyhow::Result<Option<Action>>;
+
+// the commands keys can be bound to, under the names keymaps use for them.
+#[rustfmt::skip]
+const COMMANDS: &[(&str, CommandFn)] = &[
+    ("move_left", |_| Ok(Some(Action::MoveLeft))),
+    ("move_down", |_| Ok(Some(Action::MoveDown))),
+    ("move_up", |_| Ok(Some(Action::MoveUp))),
+    ("move_right", |_| Ok(Some(Action::MoveRight))),
+    ("move_display_line_down", |_| Ok(Some(Action::MoveDisplayLineDown))),
+    ("move_display_line_up", |_| Ok(Some(Action::MoveDisplayLineUp))),
+    ("line_start", |_| Ok(Some(Action::MoveToBeginningOfLine))),
+    ("line_end", |_| Ok(Some(Action::MoveToEndOfLine))),
+    ("buffer_start", |_| Ok(Some(Action::GoToStartOfBuffer))),
+    ("buffer_end", |_| Ok(Some(Action::GoToEndOfBuffer))),
+    ("page_down", |_| Ok(Some(Action::PageDown))),
+    ("page_up", |_| Ok(Some(Action::PageUp))),
+    ("half_page_down", |_| Ok(Some(Action::HalfPageDown))),
+    ("half_page_up", |_| Ok(Some(Action::HalfPageUp))),
+    ("scroll_line_down", |_| Ok(Some(Action::ScrollLineDown))),
+    ("scroll_line_up", |_| Ok(Some(Action::ScrollLineUp))),
+    ("center_line", |_| Ok(Some(Action::CenterLineToViewport))),
+    ("line_to_top", |_| Ok(Some(Action::LineToTopOfViewport))),
+    ("line_to_top_first_non_blank", |_| Ok(Some(Action::LineToTopOfViewportFirstNonBlank))),
+    ("line_to_bottom", |_| Ok(Some(Action::LineToBottomOfViewport))),
+    ("scroll_left", |_| Ok(Some(Action::ScrollViewLeft))),
+    ("scroll_right", |_| Ok(Some(Action::ScrollViewRight))),
+    ("scroll_cursor_to_start", |_| Ok(Some(Action::ScrollCursorToStart))),
+    ("scroll_cursor_to_end", |_| Ok(Some(Action::ScrollCursorToEnd))),
+    ("insert_mode", Editor::enter_insert_mode),
+    ("command_mode", Editor::enter_command_mode),
+    ("open_line_below", |_| Ok(Some(Action::InsertLineBelowCursor))),
+    ("delete_char", |_| Ok(Some(Action::DeleteCharCursorPos))),
+    ("delete_line", |_| Ok(Some(Action::DeleteFullLine))),
+    ("indent_line", |_| Ok(Some(Action::IndentLine))),
+    ("dedent_line", |_| Ok(Some(Action::DedentLine))),
+    ("undo", |_| Ok(Some(Action::Undo))),
+    ("quit", |_| Ok(Some(Action::Quit))),
+    ("open_entry", Editor::open_entry),
+    ("parent_directory", Editor::open_parent),
+    ("toggle_hidden", |e| e.change_listing(|s| s.hidden = !s.hidden)),
+    ("toggle_ignored", |e| e.change_listing(|s| s.ignored = !s.ignored)),
+    ("cycle_sort", |e| e.change_listing(|s| s.sort = s.sort.next())),
+    ("find_files", |e| e.find_files(PathBuf::from("."))),
+    ("live_grep", |e| e.grep(String::new(), PathBuf::from("."))),
+    ("next_window", |e| e.move_window(1, true)),
+    ("previous_window", |e| e.move_window(-1, true)),
+    ("window_below", |e| e.move_window(1, false)),
+    ("window_above", |e| e.move_window(-1, false)),
+    ("next_tab", |e| e.move_tab(1)),
+    ("previous_tab", |e| e.move_tab(-1)),
+];
+
+// what the .editorconfig files say for the buffer's file, listings have
+// nothing to indent.
+fn editorconfig_args(buffer: &Buffer) -> Vec<String> {
+    match (&buffer.kind, &buffer.file) {
+        (Kind::File, Some(file)) => {
+            editorconfig::setlocal_args(&editorconfig::properties(file.as_ref()))
+        }
+        _ => vec![],
+    }
+}
+
+// whether `buffer` is the file at `path`.
+fn shows(buffer: &Buffer, path: &Path) -> bool {
+    let wanted = std::path::absolute(path).ok();
+    let file = buffer.file.as_deref().map(std::path::absolute);
+    wanted.is_some() && file.and_then(Result::ok) == wanted
+}
+
+// `text` cut down to the cells it may take up.
+fn clip(text: &str, cells: usize) -> String {
+    let mut used = 0;
+    graphemes(text)
+        .map(|(_, g)| g)
+        .take_while(|g| {
+            used += g.width();
+            used <= cells
+        })
+        .collect()
+}
+
+// a grapheme of a popup as it is drawn, a tab as the `width` blanks it
+// takes and other control chars as their pictures.
+fn shown(g: &str, width: usize) -> String {
+    match control_picture(g) {
+        _ if g == "\t" => " ".repeat(width),
+        Some(picture) => picture.to_string(),
+        None => g.to_string(),
+    }
+}
+
+// a file in the picker, highlighted on the popup's background. a line to
+// show is marked and put a third of the way down. the area is the left
+// column, top row, cells and rows.
+fn draw_preview(
+    frame: &mut Grid,
+    theme: &Theme,
+    buffer: &mut Buffer,
+    (left, top, cells, rows): (u16, u16, usize, usize),
+    marked: Option<usize>,
+    tabstop: usize,
+) {
+    let len = buffer.lines.len();
+    let first = marked
+        .map_or(0, |line| line.saturating_sub(rows / 3))
+        .min(len);
+    let last = len.min(first + rows);
+    let mut offset = buffer.line_offset(first);
+    let spans = buffer.highlights(offset..buffer.line_offset(last));
+    let language = buffer.filetype();
+    for (y, line_no) in (top..).zip(first..last) {
+        let base = match marked == Some(line_no) {
+            true => Style {
+                bg: theme.ui.popup_selected.bg,
+                ..theme.ui.popup.clone()
+            },
+            false => theme.ui.popup.clone(),
+        };
+        frame.fill(left, y, cells as u16, base.convert_to_style(&theme.style));
+        let line = &buffer.lines[line_no];
+        let (mut x, mut col) = (left, 0);
+        for (byte, g) in graphemes(line) {
+            let width = cell_width(g, col, tabstop);
+            col += width;
+            if col > cells {
+                break;
+            }
+            let scope = spans
+                .iter()
+                .find(|(span, _)| span.contains(&(offset + byte)));
+            let style = match scope.and_then(|(_, scope)| theme.get_style(scope, language)) {
+                Some(style) => Style {
+                    bg: base.bg,
+                    ..style
+                },
+                None => base.clone(),
+            };
+            x = frame.put_str(x, y, &shown(g, width), style.convert_to_style(&theme.style));
+        }
+        offset += line.len() + 1;
+    }
+}
+
+/// Whether keys can be bound to `name`, see the keymap module.
+pub fn is_command(name: &str) -> bool {
+    COMMANDS.iter().any(|(n, _)| *n == name)
+}
+
+// what waiting for input came back with.
+enum Input {
+    Key(Key, bool), // the key and whether user mappings apply to it
+    Event(event::Event),
+    Timeout, // the keys typed so far waited timeoutlen for the rest of a binding
+    ThemeReloaded,
+    PickerUpdated,
+}
 
 #[derive(Debug)]
 pub struct InsertModeTextAddInfo {
@@ -39,229 +234,763 @@ impl StyleInfo {
 
 pub struct Editor {
     theme: Theme,
+    options: Options,        // the values for the buffer and window, see Scope
+    global_options: Options, // what :setlocal leaves alone, buffers opened later start from it
+    filetype_options: HashMap<String, String>, // :setlocal arguments from the config
+    editorconfig: Vec<String>, // :setlocal arguments from .editorconfig files, after the filetype's
+    list_settings: ListSettings, // how directory listings are shown, kept from one to the next
     buffer: Buffer,
     stdout: Stdout,
+    screen: Screen,
     mode: Mode,
     size: (u16, u16),
-    vtop: u16,
+    viewport: Viewport,
+    tabs: Vec<Vec<Option<Window>>>, // every tab's windows from the top, None for the current one
+    tab: usize,
+    win: usize,
+    vtop: u16, // first screen row of the current window
     vleft: u16,
-    vheight: u16,
+    leftcol: usize, // first virtual column shown when wrap is off
     vwidth: u16,
     cursor_style: SetCursorStyle,
-    cx: u16,
-    cy: u16,
-    waiting_cmd: Option<char>,
+    cx: u16, // grapheme index into the current line, see cursor_screen_pos for the terminal cell
+    cy: u16, // buffer line of the cursor
+    curswant: usize, // column vertical moves try to return to, usize::MAX sticks to the line end
+    cmdline: String,          // what has been typed after ':' so far
+    completions: Vec<String>, // command lines Tab cycles through, empty when not completing
+    completion: usize,
+    message: Option<String>, // shown on the last row, errors from commands end up here
+    quit_requested: bool,    // :q and :wq, the main loop stops after the action
+    picker: Option<Picker>,  // drawn over the viewport and given the keys while open
+    quickfix: Vec<Location>, // what the last search found, see :cnext
+    quickfix_index: usize,
+    theme_watcher: Option<ThemeWatcher>,
+    keymaps: Keymaps,
+    pending_keys: VecDeque<(Key, bool)>, // what mappings expanded to, handled before new input
+    typed: Vec<Key>,                     // keys of a binding that isn't complete yet
+    typed_remap: bool,                   // whether they see user mappings
+    typed_at: Instant,                   // when the last of them came, for timeoutlen
+    mapping_depth: usize,                // mappings expanded since the last terminal key
     undo_actions_list: Vec<Action>,
     undo_cursor_pos: (u16, u16), // insert mode enter and exit cursor pos
     undo_buffer_list: Vec<(String, u16)>, // string and the index
 }
 
 impl Editor {
-    pub fn new(theme: Theme, file_buffer: Buffer) -> anyhow::Result<Self> {
+    pub fn new(theme: Theme, file_buffer: Buffer, config: &Config) -> anyhow::Result<Self> {
         let size = terminal::size()?;
-        let gutter_width = (file_buffer.lines.len().to_string().len() + 2) as u16;
-        Ok(Editor {
+        let mut editor = Editor {
             theme,
+            options: config.options.clone(),
+            global_options: config.options.clone(),
+            filetype_options: config.filetypes.clone(),
+            editorconfig: editorconfig_args(&file_buffer),
+            list_settings: ListSettings::default(),
             buffer: file_buffer,
             mode: Mode::Normal,
-            vtop: 0,
+            viewport: Viewport::new((size.1 - 2) as usize),
             cursor_style: SetCursorStyle::DefaultUserShape,
-            vleft: gutter_width,
-            cx: gutter_width,
+            tabs: vec![vec![None]],
+            tab: 0,
+            win: 0,
+            vtop: 0,
+            vleft: 0,
+            leftcol: 0,
+            cx: 0,
             cy: 0,
-            vheight: size.1 - 2,
+            curswant: 0,
             vwidth: size.0,
             undo_cursor_pos: (0, 0),
             size,
             undo_actions_list: vec![],
             undo_buffer_list: vec![],
-            waiting_cmd: None,
+            cmdline: String::new(),
+            completions: vec![],
+            completion: 0,
+            message: None,
+            quit_requested: false,
+            picker: None,
+            quickfix: vec![],
+            quickfix_index: 0,
+            theme_watcher: None,
+            keymaps: Keymaps::new(config.leader.unwrap_or(DEFAULT_LEADER)),
+            pending_keys: VecDeque::new(),
+            typed: vec![],
+            typed_remap: true,
+            typed_at: Instant::now(),
+            mapping_depth: 0,
             stdout: stdout(),
-        })
+            screen: Screen::new(size.0, size.1),
+        };
+        for (mode, keys, binding) in &config.keybindings {
+            editor.keymaps.map(*mode, keys.clone(), binding.clone());
+        }
+        for started in [editor.apply_filetype_options(), editor.watch_theme()] {
+            if let Err(e) = started {
+                editor.message = Some(format!("{e:#}"));
+            }
+        }
+        editor.vleft = editor.gutter_width();
+        Ok(editor)
+    }
+
+    /// Shows `message` on the last row until the next one replaces it.
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn set_color_depth(&mut self, depth: ColorDepth) {
+        self.screen.set_color_depth(depth);
     }
 
+    // the whole frame is drawn into the screen grid first, only the cells that
+    // differ from the previous frame reach the terminal, in a single write.
     fn draw(&mut self) -> anyhow::Result<()> {
-        self.stdout.execute(self.cursor_style)?;
-        self.stdout.execute(cursor::Hide)?;
-        self.draw_gutter()?;
-        self.draw_viewport()?;
-        self.draw_statusline()?;
-        log!("moving to :{} and :{} \n", self.cx, self.cy);
-        self.stdout.execute(cursor::Show)?;
-        self.stdout.queue(MoveTo(self.cx, self.cy))?;
+        self.stdout.queue(cursor::Hide)?;
+        self.draw_other_windows()?;
+        let layout = self.draw_window(true)?;
+        let prompt = self.draw_picker();
+        self.draw_cmdline();
+        self.screen.flush(&mut self.stdout)?;
+        let (cx, cy) = match (prompt, self.mode) {
+            (Some(prompt), _) => prompt,
+            (None, Mode::Command) => (
+                1 + self.cmdline.width() as u16,
+                self.size.1.saturating_sub(1),
+            ),
+            (None, _) => self.cursor_screen_pos(&layout),
+        };
+        log!("moving to :{} and :{} \n", cx, cy);
+        self.stdout.queue(self.cursor_style)?;
+        self.stdout.queue(MoveTo(cx, cy))?;
+        self.stdout.queue(cursor::Show)?;
         self.stdout.flush()?;
         Ok(())
     }
 
-    fn gutter_width(&self) -> usize {
-        let len = self.buffer.lines.len();
-        let len = len.to_string().len();
-        len + 1
+    // the text, gutter and statusline of the window whose state is in the
+    // editor's fields, at vtop.
+    fn draw_window(&mut self, current: bool) -> anyhow::Result<Vec<(usize, DisplayRow)>> {
+        // the buffer may have grown past another digit or got its first sign.
+        self.vleft = self.gutter_width();
+        let layout = self.layout();
+        self.draw_gutter(&layout);
+        self.draw_viewport(&layout)?;
+        self.draw_statusline(current)?;
+        Ok(layout)
     }
 
-    fn draw_gutter(&mut self) -> anyhow::Result<()> {
-        let width = self.gutter_width();
-
-        for i in 0..self.vheight {
-            log!("i is {} : vtop is :{}  \n", i, self.vtop);
-            self.stdout
-                .queue(MoveTo(0, i))?
-                .queue(style::PrintStyledContent(
-                    format!("{n:>width$} ", n = i + 1 + self.vtop, width = width)
-                        .on(self.theme.style.bg.unwrap())
-                        .with(self.theme.style.fg.unwrap()),
-                ))?;
+    // the tab's other windows are traded in one by one to be drawn where
+    // they are stacked.
+    fn draw_other_windows(&mut self) -> anyhow::Result<()> {
+        let (current_top, count) = (self.vtop, self.tabs[self.tab].len());
+        let rows = window::stack(self.size.1.saturating_sub(1), count);
+        for (i, (top, _)) in rows.into_iter().enumerate() {
+            let Some(mut window) = self.tabs[self.tab][i].take() else {
+                continue;
+            };
+            self.swap_window(&mut window);
+            self.vtop = top;
+            let drawn = self.draw_window(false);
+            self.swap_window(&mut window);
+            self.tabs[self.tab][i] = Some(window);
+            drawn?;
         }
-
+        self.vtop = current_top;
         Ok(())
     }
 
-    fn draw_viewport(&mut self) -> anyhow::Result<()> {
-        let vbuffer = self
+    // virtual column of the cursor inside its line, wide chars and tabs count
+    // for every cell they take.
+    fn cursor_col(&self) -> usize {
+        let line = self
             .buffer
-            .viewport_buf(self.vtop as usize, self.vheight as usize);
+            .get(self.get_buf_line() as usize)
+            .unwrap_or_default();
+        grapheme_to_col(&line, self.cx as usize, self.options.tabstop)
+    }
 
-        let color_info = self.highlight(&vbuffer)?;
+    fn text_width(&self) -> usize {
+        self.vwidth.saturating_sub(self.vleft) as usize
+    }
 
-        // for i in 0..self.vheight {
-        //     self.stdout.queue(cursor::MoveTo(0, i))?;
-        //     self.stdout
-        //         .queue(style::Print(" ".repeat(self.vwidth as usize)))?;
-        // }
-        log!("vleft: {} \n", self.vleft);
-        let mut x = self.vleft;
-        let mut y = 0;
+    // columns scrolled off to the left, wrapped lines never scroll sideways.
+    fn hidden_cols(&self) -> usize {
+        if self.options.wrap {
+            0
+        } else {
+            self.leftcol
+        }
+    }
+
+    fn sidescrolloff(&self) -> usize {
+        self.options
+            .sidescrolloff
+            .min(self.text_width().saturating_sub(1) / 2)
+    }
 
-        for (pos, ch) in vbuffer.chars().enumerate() {
-            let mut style_for_position = match color_info.iter().find(|si| si.contains(pos)) {
-                Some(val) => val.style.clone(),
-                None => self.theme.style.clone(),
+    // scrolls sideways until the cursor is at least sidescrolloff columns away
+    // from both edges. sidescroll 0 recenters the cursor like vim does.
+    fn scroll_horizontally(&mut self) {
+        let width = self.text_width();
+        if self.options.wrap || width == 0 {
+            self.leftcol = 0;
+            return;
+        }
+        let off = self.sidescrolloff();
+        let col = self.cursor_col();
+        let step = self.options.sidescroll;
+
+        if col < self.leftcol + off {
+            let wanted = col.saturating_sub(off);
+            self.leftcol = if step == 0 {
+                col.saturating_sub(width / 2)
+            } else {
+                wanted.min(self.leftcol.saturating_sub(step))
             };
-            if ch == '\n' {
-                y += 1;
-                if y >= self.vheight {
-                    break;
+        } else if col + off >= self.leftcol + width {
+            let wanted = col + off + 1 - width;
+            self.leftcol = if step == 0 {
+                col.saturating_sub(width / 2)
+            } else {
+                wanted.max(self.leftcol + step)
+            };
+        }
+    }
+
+    // zh and zl move the view, so the cursor is dragged along instead of the
+    // view being scrolled back to it.
+    fn keep_cursor_in_view(&mut self) {
+        let width = self.text_width();
+        let off = self.sidescrolloff();
+        let col = self.cursor_col();
+        if col < self.leftcol + off {
+            self.cx = self.col_to_cx(self.leftcol + off);
+        } else if col + off >= self.leftcol + width {
+            self.cx = self.col_to_cx((self.leftcol + width).saturating_sub(off + 1));
+        }
+    }
+
+    // one entry per screen row: the buffer line it shows and which part of it.
+    fn layout(&self) -> Vec<(usize, DisplayRow)> {
+        let width = self.text_width();
+        let mut layout = vec![];
+        for line_no in self.viewport.top..self.buffer.lines.len() {
+            for row in wrap_line(&self.buffer.lines[line_no], width, &self.options) {
+                if layout.len() >= self.viewport.height {
+                    return layout;
                 }
-                x = self.vleft;
-                self.fill_line(x, y, &mut style_for_position)?;
+                layout.push((line_no, row));
+            }
+        }
+        layout
+    }
+
+    fn cursor_screen_pos(&self, layout: &[(usize, DisplayRow)]) -> (u16, u16) {
+        let line_no = self.get_buf_line() as usize;
+        let cx = self.cx as usize;
+        let Some(y) = layout
+            .iter()
+            .rposition(|(l, row)| *l == line_no && row.start <= cx)
+        else {
+            return (self.vleft, self.vtop);
+        };
+        let row = &layout[y].1;
+        // zl can scroll past the end of a short line, the cursor then sticks
+        // to the left edge.
+        let col = self
+            .cursor_col()
+            .saturating_sub(row.start_col + self.hidden_cols());
+        let x = self.vleft as usize + row.indent + col;
+        (
+            x.min(self.vwidth.saturating_sub(1) as usize) as u16,
+            self.vtop + y as u16,
+        )
+    }
+
+    // grapheme on the current line under display column col, used to keep the
+    // cursor in the same screen column when moving across lines with wide chars.
+    fn col_to_cx(&self, col: usize) -> u16 {
+        let line = self
+            .buffer
+            .get(self.get_buf_line() as usize)
+            .unwrap_or_default();
+        col_to_grapheme(&line, col, self.options.tabstop) as u16
+    }
+
+    fn wrapped_rows(&self, line_no: usize) -> Vec<DisplayRow> {
+        let line = self.buffer.get(line_no).unwrap_or_default();
+        wrap_line(&line, self.text_width(), &self.options)
+    }
+
+    // gj and gk, moves by screen row inside wrapped lines and falls back to j/k
+    // on the first and last row.
+    fn move_display_line(&mut self, down: bool) {
+        let line_no = self.get_buf_line() as usize;
+        let rows = self.wrapped_rows(line_no);
+        let current = row_of(&rows, self.cx as usize);
+        let offset = self.cursor_col() - rows[current].start_col;
+
+        let target = if down && current + 1 < rows.len() {
+            rows[current + 1].clone()
+        } else if !down && current > 0 {
+            rows[current - 1].clone()
+        } else if down {
+            if line_no + 1 >= self.buffer.lines.len() {
+                return;
+            }
+            self.cy += 1;
+            self.wrapped_rows(line_no + 1)[0].clone()
+        } else {
+            if line_no == 0 {
+                return;
+            }
+            self.cy -= 1;
+            self.wrapped_rows(line_no - 1).last().unwrap().clone()
+        };
+
+        let cx = self.col_to_cx(target.start_col + offset) as usize;
+        // stay on the target row, a wide char at the edge can push the column
+        // into the next one.
+        let last = if target.end > target.start && target.end < self.get_line_length() as usize {
+            target.end - 1
+        } else {
+            target.end
+        };
+        self.cx = cx.clamp(target.start, last.max(target.start)) as u16;
+    }
+
+    // cells the sign column takes, signs are two cells wide.
+    fn sign_width(&self) -> usize {
+        match self.options.signcolumn.as_str() {
+            "yes" => 2,
+            "auto" if !self.buffer.signs.is_empty() => 2,
+            _ => 0,
+        }
+    }
+
+    // cells the numbers take, the space after them included. relative numbers
+    // never get longer than the line count.
+    fn number_width(&self) -> usize {
+        if !self.options.number && !self.options.relativenumber {
+            return 0;
+        }
+        let digits = self.buffer.lines.len().max(1).to_string().len();
+        self.options.numberwidth.max(digits + 1)
+    }
+
+    fn gutter_width(&self) -> u16 {
+        (self.sign_width() + self.number_width()) as u16
+    }
+
+    // the sign column and then the numbers, on the rows that show a line.
+    // the rows after the end of the buffer are left to draw_viewport.
+    fn draw_gutter(&mut self, layout: &[(usize, DisplayRow)]) {
+        let signs = self.sign_width();
+        let width = self.number_width().saturating_sub(1);
+        let ui = &self.theme.ui;
+        let gutter = ui.line_number.convert_to_style(&self.theme.style);
+        for (y, (line_no, row)) in layout.iter().enumerate() {
+            let y = self.vtop + y as u16;
+            let first_row = !row.is_continuation();
+            if signs > 0 {
+                let sign = self.buffer.signs.get(*line_no).filter(|_| first_row);
+                let (text, style) = match sign {
+                    Some(sign) => {
+                        let fg = ui.by_name(&sign.texthl).and_then(|style| style.fg);
+                        let style = Style {
+                            fg: fg.or(ui.line_number.fg),
+                            ..ui.line_number.clone()
+                        }
+                        .convert_to_style(&self.theme.style);
+                        (sign.text.as_str(), style)
+                    }
+                    None => ("", gutter),
+                };
+                self.screen.frame.fill(0, y, signs as u16, style);
+                self.screen.frame.put_str(0, y, text, style);
+            }
+            if width == 0 {
                 continue;
+            }
+            let current = *line_no == self.cy as usize;
+            let style = match current {
+                true => &ui.line_number_active,
+                false => &ui.line_number,
+            }
+            .convert_to_style(&self.theme.style);
+            // wrapped rows only get a number on the first row of the line, in
+            // hybrid mode the cursor line's own number sits on the left like
+            // vim does it.
+            let number = match first_row {
+                true => self.line_number(*line_no).to_string(),
+                false => String::new(),
             };
+            let text = match current && self.options.number && self.options.relativenumber {
+                true => format!("{number:<width$} "),
+                false => format!("{number:>width$} "),
+            };
+            self.screen.frame.put_str(signs as u16, y, &text, style);
+        }
+    }
 
-            if x < self.vwidth {
-                self.fill_line(x, y, &mut style_for_position)?;
-                self.print_char(x, y, ch, &mut style_for_position)?;
-            }
+    // with relativenumber the other lines count from the cursor line, which
+    // shows its own number if number is on too and 0 if not.
+    fn line_number(&self, line_no: usize) -> usize {
+        let cursor = self.cy as usize;
+        if !self.options.relativenumber || (line_no == cursor && self.options.number) {
+            line_no + 1
+        } else {
+            line_no.abs_diff(cursor)
+        }
+    }
 
-            x += 1;
+    fn draw_viewport(&mut self, layout: &[(usize, DisplayRow)]) -> anyhow::Result<()> {
+        // byte offset of every visible line in the document, the highlight
+        // spans are byte ranges so the lookup has to be done in bytes as well.
+        let top = self.viewport.top;
+        let bottom = (top + self.viewport.height).min(self.buffer.lines.len());
+        let mut line_offsets = vec![];
+        let start = self.buffer.line_offset(top);
+        let mut offset = start;
+        for line in &self.buffer.lines[top.min(bottom)..bottom] {
+            line_offsets.push(offset);
+            offset += line.len() + 1;
         }
+        let color_info = self.highlight(start..offset);
 
-        while y < self.vheight {
+        log!("vleft: {} \n", self.vleft);
+        let showbreak = self.options.showbreak.clone();
+        let showbreak_width = showbreak_width(&self.options);
+
+        for (y, (line_no, row)) in layout.iter().enumerate() {
+            let y = self.vtop + y as u16;
+            let line = self.buffer.lines[*line_no].clone();
+            let offset = line_offsets[*line_no - self.viewport.top];
+            // the cursor line keeps the token colors on its own background.
+            let mut base = self.theme.style.clone();
+            if self.options.cursorline && *line_no == self.cy as usize {
+                base.bg = self.theme.ui.cursor_line.bg;
+            }
+            let mut x = self.vleft;
+            if row.is_continuation() && row.indent > 0 {
+                let indent = " ".repeat(row.indent.saturating_sub(showbreak_width));
+                let prefix = format!("{indent}{showbreak}");
+                self.print_grapheme(x, y, &prefix, &base)?;
+                x += row.indent as u16;
+            }
+            let hidden = row.start_col + self.hidden_cols();
+            let text_x = x;
+            // trailing whitespace is marked from here on with list.
+            let trail = whitespace_start(&line, self.buffer.line_len(*line_no));
+            let mut col = row.start_col;
+            let row_graphemes = graphemes(&line).enumerate().skip(row.start);
+            for (idx, (byte, g)) in row_graphemes.take(row.end - row.start) {
+                let width = cell_width(g, col, self.options.tabstop) as u16;
+                col += width as usize;
+                if col <= hidden {
+                    continue;
+                }
+                // a wide char or tab cut by the left edge only shows the
+                // cells that are still on screen, as blanks.
+                if col - (width as usize) < hidden {
+                    let visible = col - hidden;
+                    self.print_grapheme(x, y, &" ".repeat(visible), &base)?;
+                    x += visible as u16;
+                    continue;
+                }
+                if x + width > self.vwidth {
+                    break;
+                }
+                let style_for_position =
+                    match color_info.iter().find(|si| si.contains(offset + byte)) {
+                        Some(val) => Style {
+                            bg: val.style.bg.or(base.bg),
+                            ..val.style.clone()
+                        },
+                        None => base.clone(),
+                    };
+                let whitespace = Style {
+                    fg: self.theme.ui.whitespace.fg,
+                    ..style_for_position.clone()
+                };
+                // tabs are drawn as the spaces they stand for, printing the
+                // raw char lets the terminal move the cursor on its own.
+                if g == "\t" && self.options.list {
+                    let tab = format!(">{}", " ".repeat(width as usize - 1));
+                    self.print_grapheme(x, y, &tab, &whitespace)?;
+                } else if g == "\t" {
+                    let spaces = " ".repeat(width as usize);
+                    self.print_grapheme(x, y, &spaces, &style_for_position)?;
+                } else if g == " " && self.options.list && idx >= trail {
+                    self.print_grapheme(x, y, "-", &whitespace)?;
+                } else if let Some(picture) = control_picture(g) {
+                    // a \r left in a unix file would move the terminal's cursor.
+                    self.print_grapheme(x, y, &picture.to_string(), &whitespace)?;
+                } else {
+                    self.print_grapheme(x, y, g, &style_for_position)?;
+                }
+                x += width;
+            }
+            self.fill_line(x, y, &base)?;
+            // past the end of the line the columns still show, wrapped rows
+            // before the last one stop where their text does.
+            let last_row = row.end == self.buffer.line_len(*line_no);
+            if let Some(ruler) = self.theme.ui.ruler.bg {
+                for column in self.options.colorcolumns() {
+                    let Some(c) = column.checked_sub(1) else {
+                        continue;
+                    };
+                    if c >= hidden && (c < col || last_row) {
+                        let x = text_x as usize + c - hidden;
+                        if x < self.vwidth as usize {
+                            self.screen.frame.set_background(x as u16, y, ruler);
+                        }
+                    }
+                }
+            }
+        }
+
+        // rows after the end of the buffer are marked with a `~`.
+        let filler = Style {
+            fg: self.theme.ui.whitespace.fg,
+            ..self.theme.style.clone()
+        };
+        for y in layout.len() as u16..self.viewport.height as u16 {
+            let y = self.vtop + y;
             self.fill_line(0, y, &self.theme.style.clone())?;
-            y += 1;
+            self.print_grapheme(0, y, "~", &filler)?;
         }
 
         Ok(())
     }
 
-    fn print_char(
+    // the picker over the viewport: the query and the files that match it on
+    // the left, the start of the selected file on the right when there is
+    // room for both. returns where the cursor goes, at the end of the query.
+    fn draw_picker(&mut self) -> Option<(u16, u16)> {
+        let picker = self.picker.as_mut()?;
+        let (theme, frame) = (&self.theme, &mut self.screen.frame);
+        let ui = &theme.ui;
+        let screen_height = self.size.1.saturating_sub(2);
+        let shrink = |cells: u16, min: u16| if cells >= min { cells * 9 / 10 } else { cells };
+        let (width, height) = (shrink(self.size.0, 40), shrink(screen_height, 10));
+        if width < 8 || height < 4 {
+            return None;
+        }
+        let (left, top) = ((self.size.0 - width) / 2, (screen_height - height) / 2);
+        let (right, bottom) = (left + width - 1, top + height - 1);
+        let split = (width >= 60).then_some(left + width / 2);
+        let list_right = split.unwrap_or(right);
+
+        let popup = ui.popup.convert_to_style(&theme.style);
+        for y in top..=bottom {
+            let (edge, fill) = match y {
+                _ if y == top => (["┌", "┬", "┐"], "─"),
+                _ if y == bottom => (["└", "┴", "┘"], "─"),
+                _ => (["│", "│", "│"], " "),
+            };
+            frame.put_str(left, y, &fill.repeat(width as usize), popup);
+            frame.put_str(left, y, edge[0], popup);
+            frame.put_str(right, y, edge[2], popup);
+            if let Some(split) = split {
+                frame.put_str(split, y, edge[1], popup);
+            }
+        }
+        let busy = if picker.is_busy() { "…" } else { "" };
+        let (matched, total) = (picker.matched, picker.total);
+        let title = match picker.kind {
+            picker::Kind::Files => format!(" Files {matched}/{total}{busy} "),
+            picker::Kind::Grep => format!(" Grep {matched} in {total} files{busy} "),
+            picker::Kind::Quickfix => format!(" Quickfix {matched}/{total} "),
+        };
+        let list_cells = (list_right - left - 1) as usize;
+        frame.put_str(left + 1, top, &clip(&title, list_cells), popup);
+        let prompt = clip(&format!("> {}", picker.query), list_cells);
+        let cursor_x = frame.put_str(left + 1, top + 1, &prompt, popup);
+        if let Some(error) = &picker.error {
+            let style = Style {
+                fg: ui.error.fg,
+                ..ui.popup.clone()
+            }
+            .convert_to_style(&theme.style);
+            let error = clip(error, list_cells.saturating_sub(2));
+            frame.put_str(left + 3, top + 2, &error, style);
+        }
+
+        let rows = (bottom - top - 2) as usize;
+        let first = (picker.selected + 1).saturating_sub(rows);
+        let matches = picker.matches.iter().enumerate().skip(first).take(rows);
+        for (y, (i, found)) in (top + 2..).zip(matches) {
+            let selected = i == picker.selected;
+            let row = match selected {
+                true => &ui.popup_selected,
+                false => &ui.popup,
+            };
+            let highlight = Style {
+                fg: ui.popup_match.fg,
+                ..row.clone()
+            }
+            .convert_to_style(&theme.style);
+            let row = row.convert_to_style(&theme.style);
+            frame.fill(left + 1, y, list_cells as u16, row);
+            let marker = if selected { "> " } else { "  " };
+            let mut x = frame.put_str(left + 1, y, marker, row);
+            // a path too long for the pane loses its start, the file name
+            // matters more. a line of text loses its end.
+            // grep labels hold a line of text, tabs and all.
+            let mut col = 0;
+            let label: Vec<_> = graphemes(&found.label)
+                .map(|(_, g)| {
+                    let width = cell_width(g, col, self.options.tabstop);
+                    col += width;
+                    shown(g, width)
+                })
+                .collect();
+            let cells = list_cells.saturating_sub(2);
+            let mut skip = 0;
+            while picker.kind == picker::Kind::Files
+                && label[skip..].iter().map(|g| g.width()).sum::<usize>() > cells
+            {
+                skip += 1;
+            }
+            if skip > 0 {
+                x = frame.put_str(x, y, "…", row);
+                skip += 1;
+            }
+            for (i, g) in label.iter().enumerate().skip(skip) {
+                if x as usize + g.width() > list_right as usize {
+                    break;
+                }
+                let style = match found.indices.binary_search(&i) {
+                    Ok(_) => highlight,
+                    Err(_) => row,
+                };
+                x = frame.put_str(x, y, g, style);
+            }
+        }
+
+        if let Some(split) = split {
+            let cells = (right - split - 1) as usize;
+            if let Some(location) = picker.selected_location() {
+                let name = format!(" {} ", location.path.display());
+                frame.put_str(split + 1, top, &clip(&name, cells), popup);
+                let area = (split + 1, top + 1, cells, (bottom - top - 1) as usize);
+                // grep results and quickfix entries show their line.
+                let line = (picker.kind != picker::Kind::Files).then_some(location.line);
+                let tabstop = self.options.tabstop;
+                match picker.preview() {
+                    Some(buffer) => draw_preview(frame, theme, buffer, area, line, tabstop),
+                    None => {
+                        frame.put_str(split + 2, top + 1, &clip("[no preview]", cells), popup);
+                    }
+                }
+            }
+        }
+        Some((cursor_x, top + 1))
+    }
+
+    fn print_grapheme(
         &mut self,
         x: u16,
         y: u16,
-        c: char,
-        style_info: &mut Style,
+        g: &str,
+        style_info: &Style,
     ) -> anyhow::Result<()> {
-        self.stdout.queue(MoveTo(x, y))?;
-        let content_style = style_info.convert_to_style(&self.theme.style);
-
-        let content = StyledContent::new(content_style, c);
-
-        self.stdout
-            .queue(MoveTo(x, y))?
-            .queue(style::PrintStyledContent(content))?;
-
+        let style = style_info.convert_to_style(&self.theme.style);
+        self.screen.frame.put_str(x, y, g, style);
         Ok(())
     }
 
     fn fill_line(&mut self, x: u16, y: u16, style_info: &Style) -> anyhow::Result<()> {
-        let width = (self.vwidth - x) as usize;
-        let line_fill_string = " ".repeat(width);
+        let width = self.vwidth.saturating_sub(x);
         let style = style_info.convert_to_style(&self.theme.style);
-
-        let styled_content = StyledContent::new(style, line_fill_string);
-
-        self.stdout
-            .queue(MoveTo(x, y))?
-            .queue(style::PrintStyledContent(styled_content))?;
-
+        self.screen.frame.fill(x, y, width, style);
         Ok(())
     }
 
-    fn draw_statusline(&mut self) -> anyhow::Result<()> {
-        self.stdout.execute(MoveTo(0, self.size.1 - 2))?;
+    // the statusline of a window that isn't the current one leaves the mode
+    // uncolored.
+    fn draw_statusline(&mut self, current: bool) -> anyhow::Result<()> {
+        let y = self.vtop + self.viewport.height as u16;
         let mode = self.get_mode().to_uppercase();
-        let pos = format!(" {}:{} ", self.cx, self.cy + self.vtop);
-        let file = format!(" {} ", self.buffer.file.as_deref().unwrap_or("No Name"));
-        let file_width = self.size.0 as usize - mode.len() - pos.len() - 2; // -2 for the
-                                                                            // seperators in mode
-        self.stdout
-            .queue(style::PrintStyledContent("█".with(Color::Rgb {
-                r: 184,
-                g: 144,
-                b: 243,
-            })))?;
-        self.stdout.queue(style::PrintStyledContent(
-            mode.with(Color::Rgb { r: 0, g: 0, b: 0 }).on(Color::Rgb {
-                r: 184,
-                g: 144,
-                b: 243,
-            }),
-        ))?;
-        self.stdout.queue(style::PrintStyledContent(
-            "█"
-                .with(Color::Rgb {
-                    r: 184,
-                    g: 144,
-                    b: 243,
-                })
-                .on(Color::Rgb {
-                    r: 255,
-                    g: 255,
-                    b: 255,
-                }),
-        ))?;
-        self.stdout.queue(style::PrintStyledContent(
-            format!("{:<width$}", file, width = file_width as usize)
-                .with(Color::Rgb {
-                    r: 255,
-                    g: 255,
-                    b: 255,
-                })
-                .on(Color::Rgb {
-                    r: 128,
-                    g: 128,
-                    b: 128,
-                }),
-        ))?;
-
-        self.stdout.queue(style::PrintStyledContent(
-            pos.with(Color::Rgb { r: 0, g: 0, b: 0 }).on(Color::Rgb {
-                r: 184,
-                g: 144,
-                b: 243,
-            }),
-        ))?;
+        // what the buffer will be written as, like utf-8-bom[dos].
+        let bom = if self.options.bomb { "-bom" } else { "" };
+        let format = format!(
+            " {}{bom}[{}]",
+            self.options.fileencoding, self.options.fileformat
+        );
+        let pos = format!(
+            " {}{format} {}:{} ",
+            self.buffer.filetype(),
+            self.cx,
+            self.cy
+        );
+        let readonly = if self.options.readonly { " [RO]" } else { "" };
+        let tabs = match self.tabs.len() {
+            1 => String::new(),
+            count => format!("[{}/{count}] ", self.tab + 1),
+        };
+        let file = format!(
+            " {tabs}{}{readonly} ",
+            self.buffer.file.as_deref().unwrap_or("No Name")
+        );
+        let file_width = (self.size.0 as usize).saturating_sub(mode.len() + pos.len() + 2); // -2 for the
+                                                                                            // seperators in mode
+        let ui = &self.theme.ui;
+        let mode_style = match self.mode {
+            _ if !current => &ui.statusline,
+            Mode::Normal => &ui.statusline_normal,
+            Mode::Insert => &ui.statusline_insert,
+            Mode::Command => &ui.statusline_command,
+        };
+        let text = &self.theme.style;
+        let styled = |style: &Style| style.convert_to_style(text);
+        // the blocks round off the mode, in its color over what is next to it.
+        let edge = |bg| Style {
+            fg: mode_style.bg,
+            bg,
+            ..Default::default()
+        };
+
+        let frame = &mut self.screen.frame;
+        let x = frame.put_str(0, y, "█", styled(&edge(text.bg)));
+        let x = frame.put_str(x, y, &mode, styled(mode_style));
+        let x = frame.put_str(x, y, "█", styled(&edge(ui.statusline.bg)));
+        let x = frame.put_str(
+            x,
+            y,
+            &format!("{:<width$}", file, width = file_width),
+            styled(&ui.statusline),
+        );
+        frame.put_str(x, y, &pos, styled(mode_style));
         Ok(())
     }
-    pub fn get_line_length(&self) -> u16 {
-        let line_in_buf = self.get_buf_line();
-        if let Some(val) = self.buffer.get(line_in_buf as usize) {
-            return val.len() as u16 + self.vleft;
+    // a message of several lines, like the :map listing, grows upwards over
+    // the text until the next key.
+    fn draw_cmdline(&mut self) {
+        let text = match self.mode {
+            Mode::Command => format!(":{}", self.cmdline),
+            _ => self.message.clone().unwrap_or_default(),
+        };
+        let lines: Vec<&str> = text.split('\n').collect();
+        let shown = &lines[lines.len().saturating_sub(self.size.1 as usize)..];
+        let top = self.size.1.saturating_sub(shown.len() as u16);
+        let style = self.theme.style.convert_to_style(&self.theme.style);
+        let frame = &mut self.screen.frame;
+        for (y, line) in (top..).zip(shown) {
+            let x = frame.put_str(0, y, line, style);
+            frame.fill(x, y, self.size.0, style);
         }
-        0
+    }
+
+    // length of the current line in grapheme clusters.
+    pub fn get_line_length(&self) -> u16 {
+        self.buffer.line_len(self.get_buf_line() as usize) as u16
     }
 
     pub fn get_buf_line(&self) -> u16 {
-        self.vtop + self.cy
+        self.cy
     }
 
     pub fn init_editor(&mut self) -> anyhow::Result<()> {
@@ -270,152 +999,217 @@ impl Editor {
         self.stdout
             .execute(terminal::Clear(terminal::ClearType::All))?;
         self.stdout.execute(self.cursor_style)?;
-        self.stdout.execute(MoveTo(self.cx, self.cy))?;
+        self.stdout.execute(MoveTo(self.vleft, 0))?;
 
         loop {
             let start = std::time::Instant::now();
             self.draw()?;
             log!("Draw time: {:?} \n", start.elapsed());
-            let event = self.handle_event(read()?)?;
-            if let Some(ev) = &event {
-                if matches!(ev, Action::Quit) {
-                    break;
-                }
+            let action = match self.wait_event()? {
+                Input::Key(key, remap) => self.feed_key(key, remap),
+                Input::Event(event) => self.handle_event(event),
+                Input::Timeout => self.resolve_typed(true),
+                Input::ThemeReloaded | Input::PickerUpdated => continue,
+            };
+            let action = action.unwrap_or_else(|e| {
+                self.message = Some(format!("{e:#}"));
+                None
+            });
+            if matches!(action, Some(Action::Quit)) {
+                break;
+            }
+            self.handle_action(&action);
+            if self.quit_requested {
+                break;
             }
-            let buf_end = self.buffer.lines.len() as u16;
-            self.handle_action(&event);
-            self.check_bounds(&event, buf_end)?;
+            self.check_bounds(&action)?;
         }
 
         Ok(())
     }
 
-    fn check_bounds(&mut self, action: &Option<Action>, buf_end: u16) -> anyhow::Result<()> {
-        let line_length = self.get_line_length();
-        if let Some(action) = action {
-            match action {
-                Action::MoveRight => {
-                    if self.cx >= line_length {
-                        self.cx = line_length.saturating_sub(1);
-                    }
+    // runs after every action: puts the cursor back on a valid position and
+    // lets the viewport follow it.
+    fn check_bounds(&mut self, action: &Option<Action>) -> anyhow::Result<()> {
+        let line_count = self.buffer.lines.len();
+        let scrolloff = self.options.scrolloff;
+        // actions that move the view on their own drag the cursor along.
+        if matches!(
+            action,
+            Some(
+                Action::PageUp
+                    | Action::PageDown
+                    | Action::HalfPageUp
+                    | Action::HalfPageDown
+                    | Action::ScrollLineUp
+                    | Action::ScrollLineDown
+            )
+        ) {
+            let rows = line_rows(&self.buffer.lines, self.text_width(), &self.options);
+            let (first, last) = self.viewport.cursor_range(line_count, scrolloff, &rows);
+            self.cy = (self.cy as usize).clamp(first, last) as u16;
+        }
+        self.cy = self.cy.min(line_count.saturating_sub(1) as u16);
+
+        let vertical = matches!(
+            action,
+            Some(
+                Action::MoveUp
+                    | Action::MoveDown
+                    | Action::PageUp
+                    | Action::PageDown
+                    | Action::HalfPageUp
+                    | Action::HalfPageDown
+                    | Action::ScrollLineUp
+                    | Action::ScrollLineDown
+                    | Action::GoToStartOfBuffer
+                    | Action::GoToEndOfBuffer
+            )
+        );
+        if vertical {
+            self.cx = self.col_to_cx(self.curswant);
+        }
 
-                    if self.cx >= self.vwidth {
-                        self.cx = self.vwidth;
-                    }
-                }
-                Action::InsertLineBelowCursor => {
-                    if self.cx > line_length {
-                        self.cx = line_length;
-                    }
-                }
-                Action::MoveLeft => {
-                    if self.cx <= self.vleft {
-                        self.cx = self.vleft;
-                    }
-                }
+        // normal mode sits on a grapheme, insert mode can sit one past the end.
+        let line_length = self.get_line_length();
+        let max_cx = match self.mode {
+            Mode::Insert => line_length,
+            Mode::Normal | Mode::Command => line_length.saturating_sub(1),
+        };
+        if self.cx > max_cx {
+            self.cx = max_cx;
+        }
 
-                Action::MoveUp => {
-                    if self.cx > line_length {
-                        self.cx = line_length;
-                    }
-                }
+        match action {
+            Some(Action::ScrollViewLeft | Action::ScrollViewRight) => self.keep_cursor_in_view(),
+            _ => self.scroll_horizontally(),
+        }
 
-                Action::MoveDown => {
-                    if self.cx > self.vleft {
-                        self.cx = self.vleft + 2;
-                    }
-                    if self.cx >= line_length {
-                        self.cx = line_length.saturating_sub(1);
-                    }
-                    if self.cy >= self.vheight as u16 {
-                        self.cy = self.vheight.saturating_sub(1) as u16;
-                        if self.vtop + self.vheight < buf_end {
-                            self.vtop += 1;
-                        }
-                    }
-                    if self.cy + self.vtop >= buf_end {
-                        self.cy = buf_end.saturating_sub(self.vtop);
-                        self.cy = self.cy.saturating_sub(1);
-                    }
-                }
-                Action::PageDown => {
-                    if self.cy + self.vtop >= buf_end {
-                        self.cy = buf_end.saturating_sub(self.vtop + 1);
-                    }
-                }
-                Action::PageUp => {
-                    if self.vtop == 0 {
-                        self.cy = 0;
-                    }
-                }
-                _ => (),
-            }
+        match action {
+            Some(Action::MoveToEndOfLine) => self.curswant = usize::MAX,
+            _ if vertical => (),
+            _ => self.curswant = self.cursor_col(),
         }
 
+        self.scroll_to_cursor();
         Ok(())
     }
 
+    fn scroll_to_cursor(&mut self) {
+        let line_count = self.buffer.lines.len();
+        let rows = line_rows(&self.buffer.lines, self.text_width(), &self.options);
+        let cursor_row = row_of(&self.wrapped_rows(self.cy as usize), self.cx as usize);
+        self.viewport.scroll_to(
+            self.cy as usize,
+            cursor_row,
+            line_count,
+            self.options.scrolloff,
+            &rows,
+        );
+    }
+
+    // everything but keys, those go through the keymaps.
     fn handle_event(&mut self, event: event::Event) -> anyhow::Result<Option<Action>> {
         if matches!(event, event::Event::Resize(_, _)) {
             self.size = terminal::size()?;
+            self.vwidth = self.size.0;
+            self.screen.resize(self.size.0, self.size.1);
+            self.layout_windows();
         }
-        match self.mode {
-            Mode::Normal => self.handle_normal_mode(event),
-            Mode::Insert => self.handle_insert_mode(event),
+        Ok(None)
+    }
+
+    fn feed_key(&mut self, key: Key, remap: bool) -> anyhow::Result<Option<Action>> {
+        // a message of several lines waits for a key to go away, the key
+        // does nothing else.
+        if self.message.as_ref().is_some_and(|m| m.contains('\n')) {
+            self.message = None;
+            return Ok(None);
+        }
+        if self.picker.is_some() {
+            return self.picker_key(key);
+        }
+        if self.typed.is_empty() {
+            self.typed_remap = remap;
+        }
+        self.typed.push(key);
+        self.typed_at = Instant::now();
+        let remap = self.typed_remap;
+        match self.keymaps.lookup(self.mode, &self.typed, remap) {
+            Lookup::Bound(binding) => {
+                let binding = binding.clone();
+                self.typed.clear();
+                self.run_binding(binding)
+            }
+            // wait for more keys, or for timeoutlen to run out.
+            Lookup::Prefix | Lookup::Ambiguous(_) => Ok(None),
+            Lookup::Unbound => self.resolve_typed(false),
         }
     }
 
-    fn handle_normal_mode(&mut self, event: event::Event) -> anyhow::Result<Option<Action>> {
-        if let Some(char) = self.waiting_cmd {
-            self.waiting_cmd = None;
-            return self.handle_wait_event(char, event);
+    // the typed keys won't become a longer binding. the longest start of them
+    // that is bound runs, or the mode itself gets the first key, and the keys
+    // after that are handled again.
+    fn resolve_typed(&mut self, timed_out: bool) -> anyhow::Result<Option<Action>> {
+        let typed = std::mem::take(&mut self.typed);
+        let Some(first) = typed.first().copied() else {
+            return Ok(None);
+        };
+        // without a timeout it was the last key that didn't fit.
+        let end = if timed_out {
+            typed.len()
+        } else {
+            typed.len() - 1
+        };
+        let remap = self.typed_remap;
+        let bound = (1..=end).rev().find_map(|len| {
+            match self.keymaps.lookup(self.mode, &typed[..len], remap) {
+                Lookup::Bound(binding) | Lookup::Ambiguous(binding) => Some((len, binding.clone())),
+                Lookup::Prefix | Lookup::Unbound => None,
+            }
+        });
+        let used = bound.as_ref().map_or(1, |(len, _)| *len);
+        for key in typed[used..].iter().rev() {
+            self.pending_keys.push_front((*key, remap));
         }
-        match event {
-            event::Event::Key(ev) => {
-                let code = ev.code;
-                let modifier = ev.modifiers;
-                match code {
-                    event::KeyCode::Char('q') => Ok(Some(Action::Quit)),
-                    event::KeyCode::Char('h') | event::KeyCode::Left => Ok(Some(Action::MoveLeft)),
-                    event::KeyCode::Char('j') | event::KeyCode::Down => Ok(Some(Action::MoveDown)),
-                    event::KeyCode::Char('k') | event::KeyCode::Up => Ok(Some(Action::MoveUp)),
-                    event::KeyCode::Char('u') => Ok(Some(Action::Undo)),
-                    event::KeyCode::Char('o') => Ok(Some(Action::InsertLineBelowCursor)),
-                    event::KeyCode::Char('G') => Ok(Some(Action::GoToEndOfBuffer)),
-
-                    event::KeyCode::Char('l') | event::KeyCode::Right => {
-                        Ok(Some(Action::MoveRight))
-                    }
-                    event::KeyCode::Char('i') => {
-                        return self.enter_insert_mode();
-                    }
-                    event::KeyCode::Char('$') => Ok(Some(Action::MoveToEndOfLine)),
-                    event::KeyCode::Char('0') => Ok(Some(Action::MoveToBeginningOfLine)),
-                    event::KeyCode::Char('f') => {
-                        if matches!(modifier, KeyModifiers::CONTROL) {
-                            Ok(Some(Action::PageDown))
-                        } else {
-                            Ok(None)
-                        }
-                    }
-                    event::KeyCode::Char('b') => {
-                        if matches!(modifier, KeyModifiers::CONTROL) {
-                            Ok(Some(Action::PageUp))
-                        } else {
-                            Ok(None)
-                        }
-                    }
+        match bound {
+            Some((_, binding)) => self.run_binding(binding),
+            None => self.handle_unbound(first),
+        }
+    }
 
-                    event::KeyCode::Char('d') => Ok(Some(Action::EnterWaitingMode('d'))),
-                    event::KeyCode::Char('x') => Ok(Some(Action::DeleteCharCursorPos)),
-                    event::KeyCode::Char('z') => Ok(Some(Action::EnterWaitingMode('z'))),
-                    event::KeyCode::Char('g') => Ok(Some(Action::EnterWaitingMode('g'))),
+    // insert and command mode type the keys nobody bound, normal mode
+    // ignores them.
+    fn handle_unbound(&mut self, key: Key) -> anyhow::Result<Option<Action>> {
+        let event = event::Event::Key(key.into());
+        match self.mode {
+            Mode::Normal => Ok(None),
+            Mode::Insert => self.handle_insert_mode(event),
+            Mode::Command => self.handle_command_mode(event),
+        }
+    }
 
-                    _ => Ok(None),
+    fn run_binding(&mut self, binding: Binding) -> anyhow::Result<Option<Action>> {
+        match binding {
+            Binding::Command(name) => self.run_command(&name),
+            Binding::Keys { keys, noremap } => {
+                self.mapping_depth += 1;
+                if self.mapping_depth > MAX_MAPPING_DEPTH {
+                    self.pending_keys.clear();
+                    anyhow::bail!("recursive mapping");
+                }
+                for key in keys.into_iter().rev() {
+                    self.pending_keys.push_front((key, !noremap));
                 }
+                Ok(None)
             }
+        }
+    }
 
-            _ => Ok(None),
+    fn run_command(&mut self, name: &str) -> anyhow::Result<Option<Action>> {
+        match COMMANDS.iter().find(|(n, _)| *n == name) {
+            Some((_, command)) => command(self),
+            None => anyhow::bail!("unknown command: {name}"),
         }
     }
 
@@ -429,13 +1223,10 @@ impl Editor {
 
     fn enter_normal_mode(&mut self) -> anyhow::Result<Option<Action>> {
         self.cursor_style = SetCursorStyle::DefaultUserShape;
-        self.undo_cursor_pos.1 = if self.cx == 1 {
-            self.cx
-        } else {
-            self.cx.saturating_sub(1)
-        };
+        // inclusive grapheme range of the text typed during this insert.
+        self.undo_cursor_pos.1 = self.cx.saturating_sub(1);
 
-        if self.undo_cursor_pos.0 != self.undo_cursor_pos.1 {
+        if self.cx > self.undo_cursor_pos.0 {
             let insert_changes = InsertModeTextAddInfo {
                 index: self.undo_cursor_pos,
                 line_no: self.get_buf_line(),
@@ -447,40 +1238,763 @@ impl Editor {
         Ok(Some(Action::EnterMode(Mode::Normal)))
     }
 
-    fn highlight(&self, code: &str) -> anyhow::Result<Vec<StyleInfo>> {
-        let mut parser = Parser::new();
+    fn enter_command_mode(&mut self) -> anyhow::Result<Option<Action>> {
+        self.cmdline.clear();
+        self.message = None;
+        self.mode = Mode::Command;
+        Ok(Some(Action::EnterMode(Mode::Command)))
+    }
 
-        let language = &tree_sitter_rust::language();
-        parser.set_language(*language)?;
+    fn handle_command_mode(&mut self, event: event::Event) -> anyhow::Result<Option<Action>> {
+        let event::Event::Key(key) = event else {
+            return Ok(None);
+        };
+        if !matches!(key.code, event::KeyCode::Tab | event::KeyCode::BackTab) {
+            self.completions.clear();
+        }
+        match key.code {
+            event::KeyCode::Tab | event::KeyCode::BackTab => {
+                self.complete_cmdline(key.code == event::KeyCode::BackTab);
+                Ok(None)
+            }
+            event::KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                Ok(Some(Action::EnterMode(Mode::Normal)))
+            }
+            // backspace on an empty line gives up on the command, like vim.
+            event::KeyCode::Backspace if self.cmdline.is_empty() => {
+                self.mode = Mode::Normal;
+                Ok(Some(Action::EnterMode(Mode::Normal)))
+            }
+            event::KeyCode::Backspace => {
+                self.cmdline.pop();
+                Ok(None)
+            }
+            event::KeyCode::Enter => {
+                self.mode = Mode::Normal;
+                Ok(Some(Action::ExecuteCommand(std::mem::take(
+                    &mut self.cmdline,
+                ))))
+            }
+            event::KeyCode::Char(c) => {
+                self.cmdline.push(c);
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
 
-        let tree = parser.parse(&code, None).expect("parsing code");
-        let query = Query::new(*language, HIGHLIGHT_QUERY)?;
-        let mut cursor = QueryCursor::new();
-        let mut color_vec: Vec<StyleInfo> = Vec::new();
+    // the first Tab collects the candidates, the ones after it cycle through
+    // them.
+    fn complete_cmdline(&mut self, backwards: bool) {
+        if self.completions.is_empty() {
+            self.completions = command::complete(&self.cmdline);
+            self.completion = 0;
+        } else if backwards {
+            self.completion =
+                (self.completion + self.completions.len() - 1) % self.completions.len();
+        } else {
+            self.completion = (self.completion + 1) % self.completions.len();
+        }
+        if let Some(line) = self.completions.get(self.completion) {
+            self.cmdline = line.clone();
+        }
+    }
 
-        let matches = cursor.matches(&query, tree.root_node(), code.as_bytes());
+    fn execute_command(&mut self, input: &str) -> anyhow::Result<()> {
+        match command::parse(input)? {
+            Command::Set { args, .. } if args.is_empty() => {
+                let changed = options::changed(&mut self.options);
+                self.message = Some(changed.join("  "));
+            }
+            Command::Set { args, local } => {
+                let mut shown = vec![];
+                for arg in args {
+                    shown.extend(self.set_option(&arg, local)?);
+                }
+                if !shown.is_empty() {
+                    self.message = Some(shown.join("  "));
+                }
+            }
+            Command::Colorscheme(Some(name)) => {
+                self.theme = load_named(&name)?;
+                self.watch_theme()?;
+            }
+            Command::Colorscheme(None) => self.message = Some(self.theme.name.clone()),
+            Command::Map {
+                mode,
+                noremap,
+                lhs: Some(lhs),
+                rhs: Some(rhs),
+            } => {
+                let leader = self.keymaps.leader;
+                let keys = parse_keys(&lhs, leader)?;
+                let binding = Binding::parse(&rhs, noremap, leader, is_command)?;
+                self.keymaps.map(mode, keys, binding);
+            }
+            Command::Map { mode, lhs, .. } => {
+                let prefix = match lhs {
+                    Some(lhs) => parse_keys(&lhs, self.keymaps.leader)?,
+                    None => vec![],
+                };
+                let lines = self.keymaps.list(mode, &prefix);
+                if lines.is_empty() {
+                    anyhow::bail!("no mapping found");
+                }
+                self.message = Some(lines.join("\n"));
+            }
+            Command::Unmap { mode, lhs } => {
+                let keys = parse_keys(&lhs, self.keymaps.leader)?;
+                self.keymaps.unmap(mode, &keys)?;
+            }
+            Command::Write { path, quit, force } => {
+                self.write(path, force)?;
+                if quit {
+                    self.close_window();
+                }
+            }
+            Command::Quit { force } => {
+                if self.buffer.modified && !force {
+                    anyhow::bail!("no write since last change (add ! to override)");
+                }
+                self.close_window();
+            }
+            Command::Edit { path, force } => {
+                let Some(path) = path.or_else(|| self.buffer.file.clone()) else {
+                    anyhow::bail!("no file name");
+                };
+                self.open(Path::new(&path), force)?;
+            }
+            Command::Explore(dir) => {
+                let dir = match dir {
+                    Some(dir) => PathBuf::from(dir),
+                    None => self.buffer_dir(),
+                };
+                self.open(&dir, false)?;
+            }
+            Command::Split { path, tab } => self.open_window(Path::new(&path), tab)?,
+            Command::Tabnext => {
+                self.move_tab(1)?;
+            }
+            Command::Tabprev => {
+                self.move_tab(-1)?;
+            }
+            Command::Files(dir) => {
+                self.find_files(PathBuf::from(dir.as_deref().unwrap_or(".")))?;
+            }
+            Command::Grep { pattern, dir } => {
+                let dir = PathBuf::from(dir.as_deref().unwrap_or("."));
+                self.grep(pattern.unwrap_or_default(), dir)?;
+            }
+            Command::Cnext => self.quickfix_move(1)?,
+            Command::Cprev => self.quickfix_move(-1)?,
+            Command::Copen => {
+                if self.quickfix.is_empty() {
+                    anyhow::bail!("the quickfix list is empty");
+                }
+                let mut picker = Picker::quickfix(self.quickfix.clone());
+                picker.selected = self.quickfix_index;
+                self.picker = Some(picker);
+            }
+            Command::SignDefine { name, text, texthl } => {
+                let texthl = texthl.unwrap_or_else(|| String::from("info"));
+                if self.theme.ui.by_name(&texthl).is_none() {
+                    anyhow::bail!("unknown texthl: {texthl}");
+                }
+                self.buffer.signs.define(&name, SignDef { text, texthl })?;
+            }
+            Command::SignPlace {
+                name,
+                line,
+                group,
+                priority,
+            } => {
+                let line = match line {
+                    Some(line) if line > self.buffer.lines.len() => {
+                        anyhow::bail!("invalid line: {line}")
+                    }
+                    Some(line) => line - 1,
+                    None => self.cy as usize,
+                };
+                self.buffer.signs.place(Placed {
+                    line,
+                    name,
+                    group: group.unwrap_or_default(),
+                    priority: priority.unwrap_or(DEFAULT_PRIORITY),
+                })?;
+            }
+            Command::SignUnplace { line, group } => {
+                let line = line.map(|line| line - 1);
+                if self.buffer.signs.unplace(line, group.as_deref()) == 0 {
+                    anyhow::bail!("no sign found");
+                }
+            }
+        }
+        Ok(())
+    }
 
-        for mat in matches {
-            for capt in mat.captures {
-                let node = capt.node;
-                let start = node.start_byte();
-                let end = node.end_byte();
+    // :w, a buffer without a name is named after the file it is written to.
+    // readonly only guards the buffer's own file.
+    fn write(&mut self, path: Option<String>, force: bool) -> anyhow::Result<()> {
+        if let Kind::Directory(_) = self.buffer.kind {
+            if path.is_some() {
+                anyhow::bail!("a directory listing is saved to its directory");
+            }
+            return self.save_listing(force);
+        }
+        let Some(path) = path.or_else(|| self.buffer.file.clone()) else {
+            anyhow::bail!("no file name");
+        };
+        let own_file = self.buffer.file.as_ref().is_none_or(|file| *file == path);
+        if own_file && self.options.readonly && !force {
+            anyhow::bail!("'readonly' option is set (add ! to override)");
+        }
+        let new = !std::path::Path::new(&path).exists();
+        let bytes = self.buffer.write(&path, &self.options)?;
+        if self.buffer.file.is_none() {
+            self.buffer.file = Some(path.clone());
+        }
+        let new = if new { " [New]" } else { "" };
+        let lines = self.buffer.lines.len();
+        self.message = Some(format!("\"{path}\"{new} {lines}L, {bytes}B written"));
+        Ok(())
+    }
 
-                let scope = query.capture_names()[capt.index as usize].as_str();
+    // :w in a directory listing renames, creates and deletes what the edits
+    // ask for. deleting needs a :w! to go through.
+    fn save_listing(&mut self, force: bool) -> anyhow::Result<()> {
+        let Kind::Directory(listing) = &self.buffer.kind else {
+            return Ok(());
+        };
+        let changes = listing.changes(&self.buffer.lines)?;
+        let deletes = changes
+            .iter()
+            .any(|change| matches!(change, directory::Change::Delete(_)));
+        if deletes && !force {
+            anyhow::bail!("{} (add ! to confirm)", directory::describe(&changes));
+        }
+        let applied = listing.apply(&changes);
+        // what did go through shows either way.
+        self.buffer.reload_directory(self.list_settings)?;
+        applied?;
+        self.message = match changes.is_empty() {
+            true => Some(String::from("no changes")),
+            false => Some(directory::describe(&changes)),
+        };
+        Ok(())
+    }
 
-                let style = self.theme.get_style(&scope);
+    // replaces the buffer with the file or directory at `path`. unsaved
+    // changes are kept from being lost unless `force` says otherwise.
+    // a file another window shows is gone to instead, windows don't share
+    // buffers.
+    fn open(&mut self, path: &Path, force: bool) -> anyhow::Result<()> {
+        if let Some((tab, win)) = self.window_showing(path) {
+            self.enter_window(tab, win);
+            return Ok(());
+        }
+        if self.buffer.modified && !force {
+            anyhow::bail!("no write since last change (add ! to override)");
+        }
+        let buffer = self.load(path)?;
+        self.editorconfig = editorconfig_args(&buffer);
+        self.buffer = buffer;
+        self.cx = 0;
+        self.cy = 0;
+        self.curswant = 0;
+        self.leftcol = 0;
+        self.viewport.top = 0;
+        self.undo_actions_list.clear();
+        self.undo_buffer_list.clear();
+        self.apply_filetype_options()
+    }
 
-                if let Some(fetch_style) = style {
-                    color_vec.push(StyleInfo {
-                        start,
-                        end,
-                        style: fetch_style,
-                    })
+    fn load(&mut self, path: &Path) -> anyhow::Result<Buffer> {
+        if path.is_dir() {
+            return Buffer::directory(path, self.list_settings);
+        }
+        let (buffer, message) = Buffer::open(Some(path.display().to_string()));
+        self.message = message;
+        Ok(buffer)
+    }
+
+    // the directory of the buffer's file, or the working directory.
+    fn buffer_dir(&self) -> PathBuf {
+        if let Kind::Directory(listing) = &self.buffer.kind {
+            return listing.path.clone();
+        }
+        let file = self.buffer.file.as_deref().map(std::path::absolute);
+        match file {
+            Some(Ok(file)) => file.parent().map(Path::to_path_buf).unwrap_or(file),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    // Enter opens the entry under the cursor in a listing and moves down
+    // anywhere else.
+    fn open_entry(&mut self) -> anyhow::Result<Option<Action>> {
+        let Kind::Directory(listing) = &self.buffer.kind else {
+            return Ok(Some(Action::MoveDown));
+        };
+        let Some(path) = listing.entry_path(self.cy as usize) else {
+            anyhow::bail!("save the listing to create this first");
+        };
+        self.open(&path, false)?;
+        Ok(None)
+    }
+
+    // `-` lists the directory above a listing, or the one a file is in, with
+    // the cursor on where it came from.
+    fn open_parent(&mut self) -> anyhow::Result<Option<Action>> {
+        let (dir, from) = match &self.buffer.kind {
+            Kind::Directory(listing) => match listing.path.parent() {
+                Some(parent) => (parent.to_path_buf(), listing.path.clone()),
+                None => return Ok(None),
+            },
+            Kind::File => {
+                let file = self.buffer.file.as_deref().map(std::path::absolute);
+                let file = file.and_then(Result::ok).unwrap_or_default();
+                (self.buffer_dir(), file)
+            }
+        };
+        self.open(&dir, false)?;
+        let from = from.file_name().unwrap_or_default().to_string_lossy();
+        let lines = &self.buffer.lines;
+        let line = lines
+            .iter()
+            .position(|line| line.trim_end_matches('/') == from);
+        self.cy = line.unwrap_or(0) as u16;
+        Ok(None)
+    }
+
+    // sorting, hidden files and ignored files, for the listings from now on
+    // and the one that is open.
+    fn change_listing(&mut self, change: fn(&mut ListSettings)) -> anyhow::Result<Option<Action>> {
+        let Kind::Directory(_) = self.buffer.kind else {
+            return Ok(None);
+        };
+        if self.buffer.modified {
+            anyhow::bail!("save the listing first");
+        }
+        change(&mut self.list_settings);
+        self.buffer.reload_directory(self.list_settings)?;
+        let settings = self.list_settings;
+        let shown = |on: bool| if on { "shown" } else { "hidden" };
+        self.message = Some(format!(
+            "sorted by {}, dotfiles {}, ignored files {}",
+            settings.sort.name(),
+            shown(settings.hidden),
+            shown(settings.ignored)
+        ));
+        Ok(None)
+    }
+
+    // opens the picker on the files under `root`.
+    fn find_files(&mut self, root: PathBuf) -> anyhow::Result<Option<Action>> {
+        if !root.is_dir() {
+            anyhow::bail!("not a directory: {}", root.display());
+        }
+        self.picker = Some(Picker::files(root));
+        Ok(None)
+    }
+
+    // opens the picker that searches the files under `root` for a regex,
+    // `pattern` is searched for right away.
+    fn grep(&mut self, pattern: String, root: PathBuf) -> anyhow::Result<Option<Action>> {
+        if !root.is_dir() {
+            anyhow::bail!("not a directory: {}", root.display());
+        }
+        let (ignorecase, smartcase) = (self.options.ignorecase, self.options.smartcase);
+        self.picker = Some(Picker::grep(root, pattern, ignorecase, smartcase));
+        Ok(None)
+    }
+
+    // opens the file of `location` unless it is the one shown, and puts the
+    // cursor on it.
+    fn jump_to(&mut self, location: &Location) -> anyhow::Result<()> {
+        if !shows(&self.buffer, &location.path) {
+            self.open(&location.path, false)?;
+        }
+        let last = self.buffer.lines.len().saturating_sub(1);
+        self.cy = location.line.min(last) as u16;
+        self.cx = location.col as u16;
+        Ok(())
+    }
+
+    // :cnext and :cprev, `by` entries on from the current one.
+    fn quickfix_move(&mut self, by: isize) -> anyhow::Result<()> {
+        if self.quickfix.is_empty() {
+            anyhow::bail!("the quickfix list is empty");
+        }
+        let index = self.quickfix_index as isize + by;
+        let Some(location) = usize::try_from(index)
+            .ok()
+            .and_then(|index| self.quickfix.get(index))
+        else {
+            anyhow::bail!("no more items");
+        };
+        let location = location.clone();
+        self.jump_to(&location)?;
+        self.quickfix_index = index as usize;
+        let count = self.quickfix.len();
+        self.message = Some(format!("({} of {count}): {}", index + 1, location.text));
+        Ok(())
+    }
+
+    // a grep closed before its search is done leaves what it found so far as
+    // the quickfix list, one that is done has left all of it already.
+    fn close_picker(&mut self) {
+        let Some(mut picker) = self.picker.take() else {
+            return;
+        };
+        picker.poll();
+        match picker.finished() {
+            Some(found) => self.set_quickfix(found),
+            None if picker.is_busy() => self.set_quickfix(picker.found()),
+            None => (),
+        }
+    }
+
+    // a search that found nothing keeps the list there was.
+    fn set_quickfix(&mut self, found: Vec<Location>) {
+        if !found.is_empty() {
+            self.quickfix = found;
+            self.quickfix_index = 0;
+        }
+    }
+
+    // trades the current window's state in the editor's fields for the one
+    // in `window`.
+    fn swap_window(&mut self, window: &mut Window) {
+        std::mem::swap(&mut self.buffer, &mut window.buffer);
+        std::mem::swap(&mut self.options, &mut window.options);
+        std::mem::swap(&mut self.editorconfig, &mut window.editorconfig);
+        std::mem::swap(&mut self.viewport, &mut window.viewport);
+        std::mem::swap(&mut self.cx, &mut window.cx);
+        std::mem::swap(&mut self.cy, &mut window.cy);
+        std::mem::swap(&mut self.curswant, &mut window.curswant);
+        std::mem::swap(&mut self.leftcol, &mut window.leftcol);
+        std::mem::swap(&mut self.undo_actions_list, &mut window.undo_actions_list);
+        std::mem::swap(&mut self.undo_cursor_pos, &mut window.undo_cursor_pos);
+        std::mem::swap(&mut self.undo_buffer_list, &mut window.undo_buffer_list);
+        self.vleft = self.gutter_width();
+    }
+
+    // shares the rows out between the windows of the tab. the ones that
+    // aren't current scroll to their cursor here, the current one does after
+    // every action anyway.
+    fn layout_windows(&mut self) {
+        let count = self.tabs[self.tab].len();
+        let rows = window::stack(self.size.1.saturating_sub(1), count);
+        for (i, (top, height)) in rows.into_iter().enumerate() {
+            let Some(mut window) = self.tabs[self.tab][i].take() else {
+                self.vtop = top;
+                self.viewport.height = height as usize;
+                continue;
+            };
+            self.swap_window(&mut window);
+            self.viewport.height = height as usize;
+            self.scroll_to_cursor();
+            self.swap_window(&mut window);
+            self.tabs[self.tab][i] = Some(window);
+        }
+    }
+
+    fn enter_window(&mut self, tab: usize, win: usize) {
+        if (tab, win) == (self.tab, self.win) {
+            return;
+        }
+        let mut window = self.tabs[tab][win]
+            .take()
+            .expect("only the current window is out of its slot");
+        self.swap_window(&mut window);
+        self.tabs[self.tab][self.win] = Some(window);
+        (self.tab, self.win) = (tab, win);
+        self.layout_windows();
+    }
+
+    // `by` windows down the tab, going round at the ends with `wrap` and
+    // stopping there without.
+    fn move_window(&mut self, by: isize, wrap: bool) -> anyhow::Result<Option<Action>> {
+        let count = self.tabs[self.tab].len() as isize;
+        let win = match wrap {
+            true => (self.win as isize + by).rem_euclid(count),
+            false => (self.win as isize + by).clamp(0, count - 1),
+        };
+        self.enter_window(self.tab, win as usize);
+        Ok(None)
+    }
+
+    // `by` tabs on, going round at the ends, to the tab's top window.
+    fn move_tab(&mut self, by: isize) -> anyhow::Result<Option<Action>> {
+        let count = self.tabs.len() as isize;
+        let tab = (self.tab as isize + by).rem_euclid(count) as usize;
+        if tab != self.tab {
+            self.enter_window(tab, 0);
+        }
+        Ok(None)
+    }
+
+    // opens `path` in a new window below the current one, or in a new tab
+    // after the current one. a file a window shows already is gone to.
+    fn open_window(&mut self, path: &Path, tab: bool) -> anyhow::Result<()> {
+        if shows(&self.buffer, path) {
+            return Ok(());
+        }
+        if let Some((tab, win)) = self.window_showing(path) {
+            self.enter_window(tab, win);
+            return Ok(());
+        }
+        let buffer = self.load(path)?;
+        let editorconfig = editorconfig_args(&buffer);
+        let mut window = Window::new(buffer, self.global_options.clone(), editorconfig);
+        self.swap_window(&mut window);
+        self.tabs[self.tab][self.win] = Some(window);
+        if tab {
+            self.tab += 1;
+            self.win = 0;
+            self.tabs.insert(self.tab, vec![None]);
+        } else {
+            self.win += 1;
+            self.tabs[self.tab].insert(self.win, None);
+        }
+        self.layout_windows();
+        self.apply_filetype_options()
+    }
+
+    // :q closes the window, the one above takes over or the one below if
+    // it was the first. closing the last window of a tab closes the tab and
+    // closing the very last one quits.
+    fn close_window(&mut self) {
+        if self.tabs.len() == 1 && self.tabs[0].len() == 1 {
+            self.quit_requested = true;
+            return;
+        }
+        let windows = &mut self.tabs[self.tab];
+        windows.remove(self.win);
+        let (tab, win) = match windows.is_empty() {
+            true => {
+                self.tabs.remove(self.tab);
+                (self.tab.min(self.tabs.len() - 1), 0)
+            }
+            false => (self.tab, self.win.saturating_sub(1)),
+        };
+        let mut window = self.tabs[tab][win]
+            .take()
+            .expect("the other windows are in their slots");
+        self.swap_window(&mut window);
+        (self.tab, self.win) = (tab, win);
+        self.layout_windows();
+    }
+
+    // the tab and window of another window that shows the file at `path`.
+    fn window_showing(&self, path: &Path) -> Option<(usize, usize)> {
+        self.tabs.iter().enumerate().find_map(|(tab, windows)| {
+            let win = windows.iter().position(|window| {
+                window
+                    .as_ref()
+                    .is_some_and(|window| shows(&window.buffer, path))
+            })?;
+            Some((tab, win))
+        })
+    }
+
+    // the keys of an open picker: typing narrows the files down, Enter opens
+    // the selected one and Esc closes the picker.
+    fn picker_key(&mut self, key: Key) -> anyhow::Result<Option<Action>> {
+        let Some(picker) = &mut self.picker else {
+            return Ok(None);
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.close_picker(),
+            KeyCode::Char('c' | 'g') if ctrl => self.close_picker(),
+            KeyCode::Enter => self.open_picked(Open::Here)?,
+            KeyCode::Char('x') if ctrl => self.open_picked(Open::Split)?,
+            KeyCode::Char('t') if ctrl => self.open_picked(Open::Tab)?,
+            KeyCode::Down | KeyCode::Tab => picker.move_selection(1),
+            KeyCode::Char('n' | 'j') if ctrl => picker.move_selection(1),
+            KeyCode::Up | KeyCode::BackTab => picker.move_selection(-1),
+            KeyCode::Char('p' | 'k') if ctrl => picker.move_selection(-1),
+            KeyCode::Char('u') if ctrl => picker.clear(),
+            KeyCode::Backspace => picker.pop(),
+            KeyCode::Char(c) if !ctrl => picker.push(c),
+            _ => (),
+        }
+        Ok(None)
+    }
+
+    // goes to the location the picker has selected, in the current window or
+    // a new one, and closes the picker.
+    fn open_picked(&mut self, open: Open) -> anyhow::Result<()> {
+        let Some(picker) = &self.picker else {
+            return Ok(());
+        };
+        let Some(location) = picker.selected_location().cloned() else {
+            return Ok(());
+        };
+        let kind = picker.kind;
+        match open {
+            Open::Here => (),
+            Open::Split => self.open_window(&location.path, false)?,
+            Open::Tab => self.open_window(&location.path, true)?,
+        }
+        self.jump_to(&location)?;
+        self.close_picker();
+        // :cnext goes on from the match picked.
+        if kind != picker::Kind::Files {
+            let index = self.quickfix.iter().position(|l| *l == location);
+            self.quickfix_index = index.unwrap_or(self.quickfix_index);
+        }
+        Ok(())
+    }
+
+    // one argument of :set or :setlocal, what a query shows comes back.
+    fn set_option(&mut self, arg: &str, local: bool) -> anyhow::Result<Option<String>> {
+        // the filetype belongs to the buffer rather than the options.
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.trim_end_matches('?'), None),
+        };
+        match (name, value) {
+            ("filetype" | "ft", Some(value)) => {
+                self.buffer.set_filetype(value)?;
+                self.apply_filetype_options()?;
+                return Ok(None);
+            }
+            ("filetype" | "ft", None) => {
+                return Ok(Some(format!("filetype={}", self.buffer.filetype())))
+            }
+            _ => (),
+        }
+
+        let assignment = Assignment::parse(arg)?;
+        if let Some(shown) = assignment.apply(&mut self.options)? {
+            return Ok(Some(shown));
+        }
+        if !local || assignment.def.scope == Scope::Global {
+            assignment.copy_value(&mut self.options, &mut self.global_options);
+        }
+        if assignment.def.name == "watchtheme" {
+            self.watch_theme()?;
+        }
+        Ok(None)
+    }
+
+    // a buffer that gets a filetype starts over from the global options, with
+    // what the filetype wants on top: the built-in defaults, then the config,
+    // then how the file was written, then the .editorconfig files of the
+    // project. a new file is written the way the global options say.
+    fn apply_filetype_options(&mut self) -> anyhow::Result<()> {
+        self.options = self.global_options.clone();
+        let filetype = self.buffer.filetype();
+        let config = self
+            .filetype_options
+            .get(filetype)
+            .map_or("", String::as_str);
+        for arg in filetype_defaults(filetype)
+            .split_whitespace()
+            .chain(config.split_whitespace())
+        {
+            Assignment::parse(arg)?.apply(&mut self.options)?;
+        }
+        if let Some(format) = &self.buffer.format {
+            self.options.fileformat = format.fileformat.clone();
+            self.options.fileencoding = format.fileencoding.clone();
+            self.options.bomb = format.bomb;
+        }
+        self.options.readonly = self.buffer.readonly;
+        for arg in &self.editorconfig {
+            Assignment::parse(arg)
+                .and_then(|assignment| assignment.apply(&mut self.options))
+                .context("editorconfig")?;
+        }
+        Ok(())
+    }
+
+    // keeps a watcher on the file of the current theme while `watchtheme` is
+    // on.
+    fn watch_theme(&mut self) -> anyhow::Result<()> {
+        self.theme_watcher = None;
+        if let (true, Some(path)) = (self.options.watchtheme, &self.theme.path) {
+            self.theme_watcher = Some(ThemeWatcher::new(path)?);
+        }
+        Ok(())
+    }
+
+    // a theme file that doesn't load anymore leaves the current theme alone.
+    fn reload_theme(&mut self) {
+        let Some(path) = self.theme.path.clone() else {
+            return;
+        };
+        match load_theme(&path) {
+            Ok(theme) => {
+                self.message = Some(format!("reloaded {}", theme.name));
+                self.theme = theme;
+            }
+            Err(e) => self.message = Some(format!("{e:#}")),
+        }
+    }
+
+    // keys mappings expanded to come first, then the terminal. while a theme
+    // is watched it wakes up now and then to look for changes, while a picker
+    // waits for results it looks for them more often, and while a binding is
+    // half typed it gives up after timeoutlen.
+    fn wait_event(&mut self) -> anyhow::Result<Input> {
+        if let Some((key, remap)) = self.pending_keys.pop_front() {
+            return Ok(Input::Key(key, remap));
+        }
+        self.mapping_depth = 0;
+        let timeoutlen = Duration::from_millis(self.options.timeoutlen as u64);
+        loop {
+            if let Some(watcher) = &self.theme_watcher {
+                if watcher.changed() {
+                    self.reload_theme();
+                    return Ok(Input::ThemeReloaded);
                 }
             }
+            if let Some(picker) = &mut self.picker {
+                if picker.poll() {
+                    if let Some(found) = picker.finished() {
+                        self.set_quickfix(found);
+                    }
+                    return Ok(Input::PickerUpdated);
+                }
+            }
+            let mut wait = self.theme_watcher.as_ref().map(|_| THEME_POLL);
+            if self.picker.as_ref().is_some_and(Picker::is_busy) {
+                wait = Some(wait.map_or(PICKER_POLL, |wait| wait.min(PICKER_POLL)));
+            }
+            if !self.typed.is_empty() {
+                let left = timeoutlen.saturating_sub(self.typed_at.elapsed());
+                if left.is_zero() {
+                    return Ok(Input::Timeout);
+                }
+                wait = Some(wait.map_or(left, |wait| wait.min(left)));
+            }
+            if wait.map_or(Ok(true), event::poll)? {
+                return Ok(match read()? {
+                    event::Event::Key(ev) => Input::Key(ev.into(), true),
+                    event => Input::Event(event),
+                });
+            }
         }
+    }
 
-        Ok(color_vec)
+    fn highlight(&mut self, range: Range<usize>) -> Vec<StyleInfo> {
+        let theme = &self.theme;
+        let language = self.buffer.filetype().to_string();
+        self.buffer
+            .highlights(range)
+            .into_iter()
+            .filter_map(|(span, scope)| {
+                theme.get_style(scope, &language).map(|style| StyleInfo {
+                    start: span.start,
+                    end: span.end,
+                    style,
+                })
+            })
+            .collect()
     }
 
     fn handle_insert_mode(&mut self, event: event::Event) -> anyhow::Result<Option<Action>> {
@@ -488,6 +2002,7 @@ impl Editor {
             event::Event::Key(key) => match key.code {
                 event::KeyCode::Esc => self.enter_normal_mode(),
                 event::KeyCode::Backspace => Ok(Some(Action::Backspace)),
+                event::KeyCode::Tab => Ok(Some(Action::InsertTab)),
                 event::KeyCode::Char(c) => Ok(Some(Action::InsertCharCursorPos(c))),
                 _ => Ok(None),
             },
@@ -495,35 +2010,6 @@ impl Editor {
         }
     }
 
-    fn handle_wait_event(&mut self, c: char, ev: event::Event) -> anyhow::Result<Option<Action>> {
-        match c {
-            'd' => match ev {
-                event::Event::Key(key) => match key.code {
-                    event::KeyCode::Char('d') => Ok(Some(Action::DeleteFullLine)),
-                    _ => Ok(None),
-                },
-                _ => Ok(None),
-            },
-            'z' => match ev {
-                event::Event::Key(key) => match key.code {
-                    event::KeyCode::Char('z') => Ok(Some(Action::CenterLineToViewport)),
-                    _ => Ok(None),
-                },
-
-                _ => Ok(None),
-            },
-            'g' => match ev {
-                event::Event::Key(key) => match key.code {
-                    event::KeyCode::Char('g') => Ok(Some(Action::GoToStartOfBuffer)),
-                    _ => Ok(None),
-                },
-                _ => Ok(None),
-            },
-
-            _ => Ok(None),
-        }
-    }
-
     pub fn handle_action(&mut self, event: &Option<Action>) {
         let buf_end = self.buffer.lines.len() as u16;
         let line_length = self.get_line_length();
@@ -532,7 +2018,7 @@ impl Editor {
             match event {
                 Action::Quit => {}
                 Action::InsertLineBelowCursor => {
-                    let idx = self.vtop + self.cy + 1;
+                    let idx = self.cy + 1;
                     log!("the idx is {} \n", idx);
                     self.buffer.insert_line(idx);
                     self.cy += 1;
@@ -540,14 +2026,9 @@ impl Editor {
                     let _ = self.enter_insert_mode();
                 }
                 Action::GoToEndOfBuffer => {
-                    self.vtop = buf_end.saturating_sub(self.vheight);
-                    self.cy = buf_end.saturating_sub(self.vtop);
-                    self.cy = self.cy.saturating_sub(1);
+                    self.cy = buf_end.saturating_sub(1);
                 }
                 Action::GoToStartOfBuffer => {
-                    if self.vtop > 0 {
-                        self.vtop = 0;
-                    }
                     self.cy = 0;
                 }
                 Action::Undo => {
@@ -563,24 +2044,29 @@ impl Editor {
                 Action::MoveDown => {
                     self.cy = self.cy.saturating_add(1);
                 }
+                Action::MoveDisplayLineDown => self.move_display_line(true),
+                Action::ScrollViewLeft if !self.options.wrap => {
+                    self.leftcol = self.leftcol.saturating_sub(1);
+                }
+                Action::ScrollViewRight if !self.options.wrap => {
+                    self.leftcol += 1;
+                }
+                Action::ScrollCursorToStart if !self.options.wrap => {
+                    self.leftcol = self.cursor_col().saturating_sub(self.sidescrolloff());
+                }
+                Action::ScrollCursorToEnd if !self.options.wrap => {
+                    self.leftcol = (self.cursor_col() + self.sidescrolloff() + 1)
+                        .saturating_sub(self.text_width());
+                }
+                Action::MoveDisplayLineUp => self.move_display_line(false),
                 Action::MoveUp => {
-                    if self.cy == 0 {
-                        if self.vtop > 0 {
-                            self.vtop = self.vtop.saturating_sub(1);
-                        }
-                    }
                     self.cy = self.cy.saturating_sub(1);
                 }
                 Action::MoveToEndOfLine => {
-                    self.cx = line_length - 1;
+                    self.cx = line_length.saturating_sub(1);
                 }
                 Action::InsertCharCursorPos(c) => {
-                    // let idx = self.cx.saturating_sub(self.vleft + 1);
-                    self.buffer.insert_char(self.cx, line_no, *c);
-                    self.cx += 1;
-                }
-                Action::EnterWaitingMode(char) => {
-                    self.waiting_cmd = Some(*char);
+                    self.cx = self.buffer.insert_char(self.cx, line_no, *c);
                 }
                 Action::DeleteFullLine => {
                     self.undo_actions_list.push(Action::DeleteFullLine);
@@ -597,27 +2083,16 @@ impl Editor {
                 Action::MoveToBeginningOfLine => {
                     self.cx = 0;
                 }
-                Action::PageUp => {
-                    if self.vtop > 0 {
-                        self.vtop = self.vtop.saturating_sub(self.vheight);
-                    }
-                    if self.vtop == 0 {
-                        self.cy = 1;
-                    }
-                }
-                Action::PageDown => {
-                    if self.vtop + self.vheight < buf_end {
-                        self.vtop += self.vheight;
-                    }
-                    if self.vtop + self.vheight > buf_end {
-                        self.cy = buf_end.saturating_sub(self.vtop);
-                    }
-                }
-                Action::CenterLineToViewport => {
-                    let index = self.get_buf_line();
-                    self.vtop = index.saturating_sub(self.vheight / 2);
-                    self.cy = index.saturating_sub(self.vtop);
-                }
+                Action::PageUp
+                | Action::PageDown
+                | Action::HalfPageUp
+                | Action::HalfPageDown
+                | Action::ScrollLineUp
+                | Action::ScrollLineDown => self.scroll_view(event),
+                Action::CenterLineToViewport
+                | Action::LineToTopOfViewport
+                | Action::LineToTopOfViewportFirstNonBlank
+                | Action::LineToBottomOfViewport => self.reposition_view(event),
                 Action::EnterMode(mode) => match mode {
                     Mode::Insert => {
                         self.cursor_style = SetCursorStyle::BlinkingBar;
@@ -627,23 +2102,143 @@ impl Editor {
                         self.cursor_style = SetCursorStyle::DefaultUserShape;
                         self.mode = Mode::Normal;
                     }
+                    Mode::Command => self.mode = Mode::Command,
                 },
-                Action::Backspace => {
-                    if self.cx > 0 {
-                        let y = self.get_buf_line();
-                        self.buffer.delete_char(self.cx.saturating_sub(1), y);
-                        self.cx = self.cx.saturating_sub(1);
+                Action::ExecuteCommand(input) => {
+                    if let Err(e) = self.execute_command(input) {
+                        self.message = Some(format!("{e:#}"));
+                    }
+                }
+                Action::Backspace if self.cx > 0 => {
+                    let line = self.buffer.get(line_no as usize).unwrap_or_default();
+                    let start = whitespace_start(&line, self.cx as usize);
+                    match self.options.softtabstop() {
+                        // inside leading or trailing whitespace softtabstop makes
+                        // backspace eat back to the previous stop.
+                        Some(sts) if start < self.cx as usize => {
+                            let tabstop = self.options.tabstop;
+                            let col = grapheme_to_col(&line, self.cx as usize, tabstop);
+                            let from = grapheme_to_col(&line, start, tabstop);
+                            let target = ((col - 1) / sts * sts).max(from);
+                            let fill = indent_fill(from, target, tabstop, self.options.expandtab);
+                            self.cx = self.buffer.replace_graphemes(
+                                line_no,
+                                start as u16,
+                                self.cx,
+                                &fill,
+                            );
+                        }
+                        _ => {
+                            self.buffer.delete_char(self.cx.saturating_sub(1), line_no);
+                            self.cx = self.cx.saturating_sub(1);
+                        }
                     }
                 }
+                Action::InsertTab => self.insert_tab(line_no),
+                Action::IndentLine => self.shift_line(line_no, true),
+                Action::DedentLine => self.shift_line(line_no, false),
                 _ => (),
             };
         }
     }
 
+    // ctrl-f/b/d/u/e/y. the view moves first, check_bounds then pulls the
+    // cursor back inside it.
+    fn scroll_view(&mut self, action: &Action) {
+        let line_count = self.buffer.lines.len();
+        let rows = line_rows(&self.buffer.lines, self.text_width(), &self.options);
+        let height = self.viewport.height;
+        let page = height.saturating_sub(2).max(1) as isize;
+        let half = (height / 2).max(1);
+        match action {
+            Action::PageDown => self.viewport.scroll_by(page, line_count.saturating_sub(1)),
+            Action::PageUp => self.viewport.scroll_by(-page, line_count.saturating_sub(1)),
+            Action::ScrollLineDown => self.viewport.scroll_by(1, line_count.saturating_sub(1)),
+            Action::ScrollLineUp => self.viewport.scroll_by(-1, line_count.saturating_sub(1)),
+            // half page scrolls move the cursor by the same amount, and stop
+            // scrolling once the last line is on screen.
+            Action::HalfPageDown => {
+                let max_top = self.viewport.max_top(line_count, &rows);
+                self.viewport
+                    .scroll_by(half as isize, max_top.max(self.viewport.top));
+                self.cy = (self.cy as usize + half).min(line_count.saturating_sub(1)) as u16;
+            }
+            Action::HalfPageUp => {
+                self.viewport.scroll_by(-(half as isize), line_count);
+                self.cy = self.cy.saturating_sub(half as u16);
+            }
+            _ => (),
+        }
+    }
+
+    // zz, zt, z<CR> and zb. the cursor stays where it is.
+    fn reposition_view(&mut self, action: &Action) {
+        let line = self.cy as usize;
+        let scrolloff = self.options.scrolloff;
+        let rows = line_rows(&self.buffer.lines, self.text_width(), &self.options);
+        match action {
+            Action::CenterLineToViewport => self.viewport.center_on(line, &rows),
+            Action::LineToTopOfViewport => self.viewport.top_on(line, scrolloff, &rows),
+            Action::LineToTopOfViewportFirstNonBlank => {
+                self.viewport.top_on(line, scrolloff, &rows);
+                let text = self.buffer.get(line).unwrap_or_default();
+                self.cx = graphemes(&text)
+                    .take_while(|(_, g)| *g == " " || *g == "\t")
+                    .count() as u16;
+            }
+            Action::LineToBottomOfViewport => self.viewport.bottom_on(line, scrolloff, &rows),
+            _ => (),
+        }
+    }
+
+    fn insert_tab(&mut self, line_no: u16) {
+        let tabstop = self.options.tabstop;
+        let expandtab = self.options.expandtab;
+        let sts = self.options.softtabstop();
+        if !expandtab && sts.is_none() {
+            self.cx = self.buffer.insert_char(self.cx, line_no, '\t');
+            return;
+        }
+
+        let line = self.buffer.get(line_no as usize).unwrap_or_default();
+        let col = grapheme_to_col(&line, self.cx as usize, tabstop);
+        let step = sts.unwrap_or(tabstop).max(1);
+        // only the whitespace typed in this insert gets rewritten, anything
+        // before it is outside the range undo knows about.
+        let start = whitespace_start(&line, self.cx as usize).max(self.undo_cursor_pos.0 as usize);
+        let from = grapheme_to_col(&line, start, tabstop);
+        let fill = indent_fill(from, (col / step + 1) * step, tabstop, expandtab);
+        self.cx = self
+            .buffer
+            .replace_graphemes(line_no, start as u16, self.cx, &fill);
+    }
+
+    // >> and <<, moves the line by shiftwidth and puts the cursor on the first
+    // non blank like vim does.
+    fn shift_line(&mut self, line_no: u16, indent: bool) {
+        let Some(line) = self.buffer.get(line_no as usize) else {
+            return;
+        };
+        let tabstop = self.options.tabstop;
+        let sw = self.options.shiftwidth();
+        let end = graphemes(&line)
+            .take_while(|(_, g)| *g == " " || *g == "\t")
+            .count();
+        let width = grapheme_to_col(&line, end, tabstop);
+        let width = if indent {
+            width + sw
+        } else {
+            width.saturating_sub(sw)
+        };
+        let fill = indent_fill(0, width, tabstop, self.options.expandtab);
+        self.cx = self.buffer.replace_graphemes(line_no, 0, end as u16, &fill);
+    }
+
     fn get_mode(&mut self) -> String {
         match self.mode {
             Mode::Insert => String::from("Insert"),
             Mode::Normal => String::from("Normal"),
+            Mode::Command => String::from("Command"),
         }
     }
 
@@ -654,30 +2249,15 @@ impl Editor {
                 Action::DeleteFullLine => {
                     let tuple = self.undo_buffer_list.pop();
                     if let Some((deleted_string, index)) = tuple {
-                        // idk how i thought this out but this works.
-                        if self.vtop <= index && index <= self.vtop + self.vheight - 1 {
-                            // inside the viewport
-                            self.cy = index.saturating_sub(self.vtop);
-                        } else {
-                            // outside the viewport
-                            self.vtop = index.saturating_sub(self.vheight / 2);
-                            self.cy = index.saturating_sub(self.vtop);
-                        }
+                        // the viewport recenters on its own if the line is
+                        // far off screen.
+                        self.cy = index;
                         self.buffer.restore_line(deleted_string, index);
                     }
                 }
                 Action::UndoInsertModeTextAdd(insert_changes) => {
-                    let index = insert_changes.line_no;
-
-                    if self.vtop <= index && index <= self.vtop + self.vheight - 1 {
-                        self.cy = index.saturating_sub(self.vtop);
-                        self.cx = insert_changes.index.0;
-                    } else {
-                        // outside the viewport
-                        self.vtop = index.saturating_sub(self.vheight / 2);
-                        self.cy = index.saturating_sub(self.vtop);
-                        self.cx = insert_changes.index.0;
-                    }
+                    self.cy = insert_changes.line_no;
+                    self.cx = insert_changes.index.0;
                     self.buffer.remove_insert_changes(insert_changes);
                 }
 
@@ -1,6 +1,6 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Mode {
     Insert,
     Normal,
+    Command,
 }
-
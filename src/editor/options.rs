@@ -0,0 +1,465 @@
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub number: bool,         // line numbers in the gutter
+    pub relativenumber: bool, // count lines from the cursor line in the gutter
+    pub numberwidth: usize,   // least cells the numbers take, the space after them included
+    pub signcolumn: String,   // yes, no, or auto to show it while the buffer has signs
+    pub tabstop: usize,
+    pub shiftwidth: usize,  // 0 means use tabstop
+    pub softtabstop: isize, // 0 disables it, negative means use shiftwidth
+    pub expandtab: bool,
+    pub wrap: bool,
+    pub linebreak: bool,   // wrap at word boundaries instead of the last cell
+    pub breakindent: bool, // continuation rows keep the indent of the line
+    pub showbreak: String, // drawn at the start of every continuation row
+    pub sidescroll: usize, // minimal columns to scroll sideways, 0 recenters the cursor
+    pub sidescrolloff: usize,
+    pub scrolloff: usize,         // rows kept visible above and below the cursor
+    pub cursorline: bool,         // highlight the line the cursor is on
+    pub colorcolumn: Vec<String>, // columns drawn in the ruler color, +N and -N count from textwidth
+    pub list: bool,               // show tabs and trailing spaces
+    pub ignorecase: bool,
+    pub smartcase: bool, // an upper case letter in the pattern turns ignorecase off
+    pub fileformat: String, // unix, dos or mac line endings
+    pub fileencoding: String, // utf-8, latin1, utf-16le or utf-16be
+    pub bomb: bool,      // write a byte order mark
+    pub fixendofline: bool, // end the last line with a newline even if it had none
+    pub trimwhitespace: bool, // drop trailing whitespace when writing
+    pub readonly: bool,  // :w refuses to write the buffer's file without a !
+    pub textwidth: usize, // longest line wanted, 0 for no limit
+    pub watchtheme: bool, // reload the theme when its file changes
+    pub timeoutlen: usize, // ms to wait for the rest of a mapped key sequence
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            number: true,
+            relativenumber: false,
+            numberwidth: 4,
+            signcolumn: String::from("auto"),
+            tabstop: 4,
+            shiftwidth: 0,
+            softtabstop: 0,
+            expandtab: false,
+            wrap: false,
+            linebreak: false,
+            breakindent: false,
+            showbreak: String::new(),
+            sidescroll: 0,
+            sidescrolloff: 0,
+            scrolloff: 0,
+            cursorline: false,
+            colorcolumn: vec![],
+            list: false,
+            ignorecase: false,
+            smartcase: false,
+            fileformat: String::from("unix"),
+            fileencoding: String::from("utf-8"),
+            bomb: false,
+            fixendofline: true,
+            trimwhitespace: false,
+            readonly: false,
+            textwidth: 0,
+            watchtheme: false,
+            timeoutlen: 1000,
+        }
+    }
+}
+
+impl Options {
+    pub fn shiftwidth(&self) -> usize {
+        match self.shiftwidth {
+            0 => self.tabstop.max(1),
+            sw => sw,
+        }
+    }
+
+    /// The 1-based columns colorcolumn stands for. The ones relative to
+    /// textwidth are left out while it is 0.
+    pub fn colorcolumns(&self) -> Vec<usize> {
+        self.colorcolumn
+            .iter()
+            .filter_map(|column| {
+                let n: isize = column.parse().ok()?;
+                if column.starts_with(['+', '-']) {
+                    let textwidth = (self.textwidth > 0).then_some(self.textwidth)?;
+                    textwidth.checked_add_signed(n)
+                } else {
+                    Some(n as usize)
+                }
+            })
+            .collect()
+    }
+
+    /// Width the Tab and Backspace keys move by in insert mode, None if they
+    /// should insert and delete single chars.
+    pub fn softtabstop(&self) -> Option<usize> {
+        match self.softtabstop {
+            0 => None,
+            sts if sts < 0 => Some(self.shiftwidth()),
+            sts => Some(sts as usize),
+        }
+    }
+
+    // values the types alone don't rule out.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.tabstop == 0 {
+            anyhow::bail!("tabstop must be positive");
+        }
+        if self.numberwidth == 0 {
+            anyhow::bail!("numberwidth must be positive");
+        }
+        if !["auto", "yes", "no"].contains(&self.signcolumn.as_str()) {
+            anyhow::bail!("invalid signcolumn: {}", self.signcolumn);
+        }
+        if !["unix", "dos", "mac"].contains(&self.fileformat.as_str()) {
+            anyhow::bail!("invalid fileformat: {}", self.fileformat);
+        }
+        if !["utf-8", "latin1", "utf-16le", "utf-16be"].contains(&self.fileencoding.as_str()) {
+            anyhow::bail!("invalid fileencoding: {}", self.fileencoding);
+        }
+        if let Some(column) = self
+            .colorcolumn
+            .iter()
+            .find(|c| c.parse::<isize>().is_err())
+        {
+            anyhow::bail!("invalid colorcolumn: {column}");
+        }
+        Ok(())
+    }
+}
+
+/// Which values an option has. There is one window showing one buffer, so
+/// window and buffer options both live in the editor's local copy, and
+/// :setlocal leaves the global copy alone for buffers opened later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Window,
+    Buffer,
+}
+
+// an option's value borrowed out of Options, with the type it has.
+enum Value<'a> {
+    Bool(&'a mut bool),
+    Number(&'a mut usize),
+    Signed(&'a mut isize),
+    String(&'a mut String),
+    List(&'a mut Vec<String>), // written comma separated
+}
+
+pub struct OptionDef {
+    pub name: &'static str,
+    short: &'static str, // empty when there is none
+    pub scope: Scope,
+    value: fn(&mut Options) -> Value<'_>,
+}
+
+#[rustfmt::skip]
+const OPTIONS: &[OptionDef] = &[
+    OptionDef { name: "number", short: "nu", scope: Scope::Window, value: |o| Value::Bool(&mut o.number) },
+    OptionDef { name: "relativenumber", short: "rnu", scope: Scope::Window, value: |o| Value::Bool(&mut o.relativenumber) },
+    OptionDef { name: "numberwidth", short: "nuw", scope: Scope::Window, value: |o| Value::Number(&mut o.numberwidth) },
+    OptionDef { name: "signcolumn", short: "scl", scope: Scope::Window, value: |o| Value::String(&mut o.signcolumn) },
+    OptionDef { name: "wrap", short: "", scope: Scope::Window, value: |o| Value::Bool(&mut o.wrap) },
+    OptionDef { name: "linebreak", short: "lbr", scope: Scope::Window, value: |o| Value::Bool(&mut o.linebreak) },
+    OptionDef { name: "breakindent", short: "bri", scope: Scope::Window, value: |o| Value::Bool(&mut o.breakindent) },
+    OptionDef { name: "list", short: "", scope: Scope::Window, value: |o| Value::Bool(&mut o.list) },
+    OptionDef { name: "cursorline", short: "cul", scope: Scope::Window, value: |o| Value::Bool(&mut o.cursorline) },
+    OptionDef { name: "colorcolumn", short: "cc", scope: Scope::Window, value: |o| Value::List(&mut o.colorcolumn) },
+    OptionDef { name: "tabstop", short: "ts", scope: Scope::Buffer, value: |o| Value::Number(&mut o.tabstop) },
+    OptionDef { name: "shiftwidth", short: "sw", scope: Scope::Buffer, value: |o| Value::Number(&mut o.shiftwidth) },
+    OptionDef { name: "softtabstop", short: "sts", scope: Scope::Buffer, value: |o| Value::Signed(&mut o.softtabstop) },
+    OptionDef { name: "expandtab", short: "et", scope: Scope::Buffer, value: |o| Value::Bool(&mut o.expandtab) },
+    OptionDef { name: "fileformat", short: "ff", scope: Scope::Buffer, value: |o| Value::String(&mut o.fileformat) },
+    OptionDef { name: "fileencoding", short: "fenc", scope: Scope::Buffer, value: |o| Value::String(&mut o.fileencoding) },
+    OptionDef { name: "bomb", short: "", scope: Scope::Buffer, value: |o| Value::Bool(&mut o.bomb) },
+    OptionDef { name: "fixendofline", short: "fixeol", scope: Scope::Buffer, value: |o| Value::Bool(&mut o.fixendofline) },
+    OptionDef { name: "trimwhitespace", short: "", scope: Scope::Buffer, value: |o| Value::Bool(&mut o.trimwhitespace) },
+    OptionDef { name: "readonly", short: "ro", scope: Scope::Buffer, value: |o| Value::Bool(&mut o.readonly) },
+    OptionDef { name: "textwidth", short: "tw", scope: Scope::Buffer, value: |o| Value::Number(&mut o.textwidth) },
+    OptionDef { name: "showbreak", short: "sbr", scope: Scope::Global, value: |o| Value::String(&mut o.showbreak) },
+    OptionDef { name: "scrolloff", short: "so", scope: Scope::Global, value: |o| Value::Number(&mut o.scrolloff) },
+    OptionDef { name: "sidescroll", short: "ss", scope: Scope::Global, value: |o| Value::Number(&mut o.sidescroll) },
+    OptionDef { name: "sidescrolloff", short: "siso", scope: Scope::Global, value: |o| Value::Number(&mut o.sidescrolloff) },
+    OptionDef { name: "ignorecase", short: "ic", scope: Scope::Global, value: |o| Value::Bool(&mut o.ignorecase) },
+    OptionDef { name: "smartcase", short: "scs", scope: Scope::Global, value: |o| Value::Bool(&mut o.smartcase) },
+    OptionDef { name: "timeoutlen", short: "tm", scope: Scope::Global, value: |o| Value::Number(&mut o.timeoutlen) },
+    OptionDef { name: "watchtheme", short: "", scope: Scope::Global, value: |o| Value::Bool(&mut o.watchtheme) },
+];
+
+// options the filetypes want for themselves, set like :setlocal when a
+// buffer gets its filetype.
+const FILETYPE_DEFAULTS: &[(&str, &str)] = &[
+    ("rust", "expandtab shiftwidth=4 softtabstop=4"),
+    ("python", "expandtab shiftwidth=4 softtabstop=4"),
+    ("yaml", "expandtab shiftwidth=2 softtabstop=2"),
+    ("json", "expandtab shiftwidth=2"),
+    ("markdown", "wrap linebreak"),
+];
+
+pub fn filetype_defaults(filetype: &str) -> &'static str {
+    FILETYPE_DEFAULTS
+        .iter()
+        .find(|(name, _)| *name == filetype)
+        .map_or("", |(_, args)| args)
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    OPTIONS.iter().map(|def| def.name)
+}
+
+fn find(name: &str) -> Option<&'static OptionDef> {
+    OPTIONS
+        .iter()
+        .find(|def| def.name == name || (!def.short.is_empty() && def.short == name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Replace,  // opt=val
+    Add,      // opt+=val
+    Subtract, // opt-=val
+    Prepend,  // opt^=val, multiplies numbers
+}
+
+#[derive(Debug, PartialEq)]
+enum Op {
+    Show, // opt? or a non bool opt alone
+    On,
+    Off,
+    Toggle,
+    Default,
+    Assign(Operator, String),
+}
+
+/// One argument of :set, parsed against the option it names.
+pub struct Assignment {
+    pub def: &'static OptionDef,
+    op: Op,
+}
+
+impl Assignment {
+    /// Reads the forms vim has: `opt`, `noopt`, `invopt`, `opt!`, `opt?`,
+    /// `opt&` and `opt=val` with `:`, `+=`, `-=` or `^=` in place of `=`.
+    pub fn parse(arg: &str) -> anyhow::Result<Self> {
+        let (name, op) = if let Some(at) = arg.find(['=', ':']) {
+            let value = arg[at + 1..].to_string();
+            match arg[..at].char_indices().last() {
+                Some((i, '+')) => (&arg[..i], Op::Assign(Operator::Add, value)),
+                Some((i, '-')) => (&arg[..i], Op::Assign(Operator::Subtract, value)),
+                Some((i, '^')) => (&arg[..i], Op::Assign(Operator::Prepend, value)),
+                _ => (&arg[..at], Op::Assign(Operator::Replace, value)),
+            }
+        } else if let Some(name) = arg.strip_suffix('?') {
+            (name, Op::Show)
+        } else if let Some(name) = arg.strip_suffix('!') {
+            (name, Op::Toggle)
+        } else if let Some(name) = arg.strip_suffix('&') {
+            (name, Op::Default)
+        } else {
+            (arg, Op::On)
+        };
+        if let Some(def) = find(name) {
+            let op = match (op, (def.value)(&mut Options::default())) {
+                (Op::On, Value::Bool(_)) => Op::On,
+                (Op::On, _) => Op::Show,
+                (Op::Toggle, Value::Bool(_)) => Op::Toggle,
+                (Op::Assign(..), Value::Bool(_)) | (Op::Toggle, _) => {
+                    anyhow::bail!("invalid argument: {arg}")
+                }
+                (op, _) => op,
+            };
+            return Ok(Self { def, op });
+        }
+        // noopt and invopt only go with bool options.
+        let negated =
+            [("no", Op::Off), ("inv", Op::Toggle)]
+                .into_iter()
+                .find_map(|(prefix, negated)| {
+                    let def = find(name.strip_prefix(prefix)?)?;
+                    let is_bool = matches!((def.value)(&mut Options::default()), Value::Bool(_));
+                    Some((def, is_bool, negated))
+                });
+        match (negated, op) {
+            (Some((def, true, negated)), Op::On) => Ok(Self { def, op: negated }),
+            (Some(_), _) => anyhow::bail!("invalid argument: {arg}"),
+            (None, _) => anyhow::bail!("unknown option: {name}"),
+        }
+    }
+
+    /// Changes the value in `options`, or returns it as vim shows it for a
+    /// query. A value the option can't take leaves everything as it was.
+    pub fn apply(&self, options: &mut Options) -> anyhow::Result<Option<String>> {
+        let name = self.def.name;
+        let before = options.clone();
+        let value = (self.def.value)(options);
+        match &self.op {
+            Op::Show => return Ok(Some(show(name, value))),
+            Op::Default => {
+                let default = show(name, (self.def.value)(&mut Options::default()));
+                copy(value, &default);
+            }
+            Op::On | Op::Off | Op::Toggle => {
+                if let Value::Bool(flag) = value {
+                    *flag = match self.op {
+                        Op::On => true,
+                        Op::Off => false,
+                        _ => !*flag,
+                    };
+                }
+            }
+            Op::Assign(operator, text) => {
+                assign(value, *operator, text).map_err(|e| anyhow::anyhow!("{name}: {e}"))?
+            }
+        }
+        if let Err(e) = options.check() {
+            *options = before;
+            return Err(e);
+        }
+        Ok(None)
+    }
+
+    /// Sets the option in `to` to the value it has in `from`.
+    pub fn copy_value(&self, from: &mut Options, to: &mut Options) {
+        let value = show(self.def.name, (self.def.value)(from));
+        copy((self.def.value)(to), &value);
+    }
+}
+
+/// The options that differ from their defaults, like :set alone shows them.
+pub fn changed(options: &mut Options) -> Vec<String> {
+    OPTIONS
+        .iter()
+        .map(|def| {
+            let default = show(def.name, (def.value)(&mut Options::default()));
+            (default, show(def.name, (def.value)(options)))
+        })
+        .filter(|(default, current)| default != current)
+        .map(|(_, current)| current)
+        .collect()
+}
+
+fn show(name: &str, value: Value) -> String {
+    match value {
+        Value::Bool(true) => name.to_string(),
+        Value::Bool(false) => format!("no{name}"),
+        Value::Number(n) => format!("{name}={n}"),
+        Value::Signed(n) => format!("{name}={n}"),
+        Value::String(s) => format!("{name}={s}"),
+        Value::List(items) => format!("{name}={}", items.join(",")),
+    }
+}
+
+// puts back a value show wrote, it always reads.
+fn copy(value: Value, shown: &str) {
+    if let Value::Bool(flag) = value {
+        *flag = !shown.starts_with("no");
+    } else if let Some((_, text)) = shown.split_once('=') {
+        let _ = assign(value, Operator::Replace, text);
+    }
+}
+
+fn assign(value: Value, operator: Operator, text: &str) -> anyhow::Result<()> {
+    let number = |text: &str| {
+        text.parse::<usize>()
+            .map_err(|_| anyhow::anyhow!("number required: {text}"))
+    };
+    match value {
+        Value::Bool(_) => anyhow::bail!("takes no value"),
+        Value::Number(n) => {
+            let arg = number(text)?;
+            *n = match operator {
+                Operator::Replace => arg,
+                Operator::Add => n.saturating_add(arg),
+                Operator::Subtract => n.saturating_sub(arg),
+                Operator::Prepend => n.saturating_mul(arg),
+            };
+        }
+        Value::Signed(n) => {
+            let arg = text
+                .parse::<isize>()
+                .map_err(|_| anyhow::anyhow!("number required: {text}"))?;
+            *n = match operator {
+                Operator::Replace => arg,
+                Operator::Add => n.saturating_add(arg),
+                Operator::Subtract => n.saturating_sub(arg),
+                Operator::Prepend => n.saturating_mul(arg),
+            };
+        }
+        Value::String(s) => match operator {
+            Operator::Replace => *s = text.to_string(),
+            Operator::Add => s.push_str(text),
+            Operator::Subtract => *s = s.replacen(text, "", 1),
+            Operator::Prepend => s.insert_str(0, text),
+        },
+        Value::List(items) => {
+            // what the items may be is up to Options::check.
+            let args: Vec<String> = text
+                .split(',')
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect();
+            match operator {
+                Operator::Replace => *items = args,
+                Operator::Add => items.extend(args),
+                Operator::Subtract => items.retain(|item| !args.contains(item)),
+                Operator::Prepend => {
+                    items.splice(0..0, args);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(options: &mut Options, arg: &str) -> anyhow::Result<Option<String>> {
+        Assignment::parse(arg)?.apply(options)
+    }
+
+    #[test]
+    fn sets_options_like_vim() {
+        let mut options = Options::default();
+        set(&mut options, "nonu").unwrap();
+        set(&mut options, "wrap!").unwrap();
+        set(&mut options, "ts=8").unwrap();
+        set(&mut options, "ts+=2").unwrap();
+        set(&mut options, "cc=80").unwrap();
+        set(&mut options, "cc+=120").unwrap();
+        assert!(!options.number && options.wrap);
+        assert_eq!(options.tabstop, 10);
+        assert_eq!(
+            set(&mut options, "cc?").unwrap().unwrap(),
+            "colorcolumn=80,120"
+        );
+        assert_eq!(set(&mut options, "ts").unwrap().unwrap(), "tabstop=10");
+        assert_eq!(
+            set(&mut options, "invwrap?").unwrap_err().to_string(),
+            "invalid argument: invwrap?"
+        );
+
+        set(&mut options, "ts&").unwrap();
+        assert_eq!(options.tabstop, 4);
+        assert!(set(&mut options, "ts=0").is_err());
+        assert!(set(&mut options, "ff=amiga").is_err());
+        assert_eq!(options.tabstop, 4);
+        assert_eq!(options.fileformat, "unix");
+        assert!(set(&mut options, "nots").is_err());
+        assert!(set(&mut options, "wrap=1").is_err());
+        assert!(set(&mut options, "frobnicate").is_err());
+        assert_eq!(
+            changed(&mut options),
+            ["nonumber", "wrap", "colorcolumn=80,120"]
+        );
+        assert!(set(&mut options, "cc+=x").is_err());
+        set(&mut options, "cc^=+1").unwrap();
+        assert_eq!(options.colorcolumns(), [80, 120]);
+        set(&mut options, "tw=79").unwrap();
+        assert_eq!(options.colorcolumns(), [80, 80, 120]);
+    }
+}
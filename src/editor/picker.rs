@@ -0,0 +1,817 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use ignore::{WalkBuilder, WalkState};
+use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Config, Matcher, Utf32Str};
+use regex::{Regex, RegexBuilder};
+
+use crate::buffer::encoding::{decode, is_binary, split_lines};
+use crate::buffer::grapheme::{byte_to_grapheme, graphemes};
+use crate::Buffer;
+
+// how many matches the picker holds, the best ones or for grep the first by
+// path. many more than fit on a screen.
+const MAX_SHOWN: usize = 1000;
+
+// a search stops after this many matching lines.
+const MAX_GREP: usize = 100_000;
+
+// a grep result shows this many graphemes of its line.
+const MAX_TEXT: usize = 200;
+
+// how long the workers wait for a query before they look for what was found
+// in the meantime.
+const RESCAN: Duration = Duration::from_millis(30);
+
+// a preview shows the start of a file, this much of it is read.
+const PREVIEW_BYTES: u64 = 256 * 1024;
+
+/// What a picker lists and what its query does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Files,    // the query filters the files under the root
+    Grep,     // the query is a regex searched for in the files
+    Quickfix, // the query filters the quickfix list
+}
+
+/// A place in a file, what grep results and the quickfix list are made of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: PathBuf, // as it can be opened from the working directory
+    pub line: usize,   // from 0, like the cursor
+    pub col: usize,    // grapheme index into the line
+    pub text: String,  // the line, without its indent
+}
+
+impl Location {
+    fn file(path: PathBuf) -> Self {
+        Self {
+            path,
+            line: 0,
+            col: 0,
+            text: String::new(),
+        }
+    }
+
+    /// How it is listed, like `src/main.rs:12:5: text` with the line and
+    /// column counted from 1.
+    pub fn label(&self) -> String {
+        format!("{}: {}", self.prefix(), self.text)
+    }
+
+    fn prefix(&self) -> String {
+        format!("{}:{}:{}", self.path.display(), self.line + 1, self.col + 1)
+    }
+}
+
+/// An entry of a picker, with the graphemes of its label the query matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub label: String,
+    pub indices: Vec<usize>, // sorted
+    pub location: Location,
+}
+
+// what the workers send back after every query and whenever they found more.
+struct Results {
+    query: String,
+    matches: Vec<Match>,
+    append: bool, // grep results add to the ones sent before for the query
+    matched: usize,
+    total: usize,
+    busy: bool,
+    error: Option<String>,
+}
+
+// what the fuzzy matcher filters: the files the walker has found so far, or
+// the quickfix list.
+struct Items {
+    labels: Vec<String>,
+    loading: bool,
+}
+
+/// A list over the viewport that the query narrows down. The files are
+/// walked, matched and searched on other threads, the picker only sends
+/// queries and takes the results that come back, so typing never waits for
+/// any of it.
+pub struct Picker {
+    pub kind: Kind,
+    pub query: String,
+    pub selected: usize,
+    pub matches: Vec<Match>, // the best ones first, grep results by file and line
+    pub matched: usize,      // how many match, not all of them come back
+    pub total: usize,        // how many there are to match, files searched for grep
+    pub error: Option<String>, // the query isn't a regex
+    shown: String,           // the query the matches are for
+    busy: bool,
+    found: Vec<Location>, // every grep match for the query, not only the ones listed
+    finished: bool,       // the search for the query is done and found not taken yet
+    queries: Sender<String>,
+    results: Receiver<Results>,
+    cancelled: Arc<AtomicBool>,
+    preview: Option<(PathBuf, Option<Buffer>)>, // None when the file can't be shown
+}
+
+impl Picker {
+    fn new(
+        kind: Kind,
+        query: String,
+        start: impl FnOnce(Receiver<String>, Sender<Results>, Arc<AtomicBool>),
+    ) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (queries, queries_rx) = mpsc::channel();
+        let (results_tx, results) = mpsc::channel();
+        start(queries_rx, results_tx, cancelled.clone());
+        let picker = Self {
+            kind,
+            query,
+            selected: 0,
+            matches: vec![],
+            matched: 0,
+            total: 0,
+            error: None,
+            shown: String::new(),
+            busy: true,
+            found: vec![],
+            finished: false,
+            queries,
+            results,
+            cancelled,
+            preview: None,
+        };
+        picker.send_query();
+        picker
+    }
+
+    /// Finds files under `root`, skipping hidden files and what .gitignore
+    /// and friends leave out.
+    pub fn files(root: PathBuf) -> Self {
+        Self::new(Kind::Files, String::new(), |queries, results, cancelled| {
+            let items = Arc::new(Mutex::new(Items {
+                labels: vec![],
+                loading: true,
+            }));
+            {
+                let (root, items) = (root.clone(), items.clone());
+                thread::spawn(move || walk_files(&root, &items, &cancelled));
+            }
+            let location = move |_, label: &str| Location::file(openable(&root.join(label)));
+            thread::spawn(move || filter(&items, &queries, &results, location, false));
+        })
+    }
+
+    /// Searches the files under `root` for the regex typed, starting with
+    /// `query`. The case is ignored the way ignorecase and smartcase say.
+    pub fn grep(root: PathBuf, query: String, ignorecase: bool, smartcase: bool) -> Self {
+        Self::new(Kind::Grep, query, move |queries, results, _| {
+            let case = move |query: &str| ignorecase && !(smartcase && has_upper(query));
+            thread::spawn(move || search(&root, &queries, &results, case));
+        })
+    }
+
+    /// Filters a quickfix list, kept in its own order.
+    pub fn quickfix(list: Vec<Location>) -> Self {
+        Self::new(Kind::Quickfix, String::new(), |queries, results, _| {
+            let items = Arc::new(Mutex::new(Items {
+                labels: list.iter().map(Location::label).collect(),
+                loading: false,
+            }));
+            let location = move |i: usize, _: &str| list[i].clone();
+            thread::spawn(move || filter(&items, &queries, &results, location, true));
+        })
+    }
+
+    fn send_query(&self) {
+        // the worker only goes away with the picker.
+        let _ = self.queries.send(self.query.clone());
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.selected = 0;
+        self.send_query();
+    }
+
+    pub fn pop(&mut self) {
+        if self.query.pop().is_some() {
+            self.selected = 0;
+            self.send_query();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.query.clear();
+        self.selected = 0;
+        self.send_query();
+    }
+
+    /// Moves the selection by `by` matches, wrapping around at either end.
+    pub fn move_selection(&mut self, by: isize) {
+        let len = self.matches.len() as isize;
+        if len > 0 {
+            self.selected = (self.selected as isize + by).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Takes the results the workers sent since the last call, true if
+    /// there were any.
+    pub fn poll(&mut self) -> bool {
+        let mut updated = false;
+        while let Ok(results) = self.results.try_recv() {
+            if results.append {
+                let locations = results.matches.iter().map(|m| m.location.clone());
+                self.found.extend(locations);
+                self.finished = !results.busy;
+                let shown = std::mem::take(&mut self.matches);
+                self.matches = merge(shown, results.matches);
+            } else {
+                self.found.clear();
+                self.finished = false;
+                self.matches = results.matches;
+            }
+            self.shown = results.query;
+            self.matched = results.matched;
+            self.total = results.total;
+            self.busy = results.busy;
+            self.error = results.error;
+            updated = true;
+        }
+        if updated {
+            self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+        }
+        updated
+    }
+
+    /// Every match of a grep, sorted by file and line, once the search for
+    /// the query is done. It comes back only the once.
+    pub fn finished(&mut self) -> Option<Vec<Location>> {
+        match std::mem::take(&mut self.finished) {
+            true => Some(self.found()),
+            false => None,
+        }
+    }
+
+    /// Every match a grep found so far, sorted by file and line.
+    pub fn found(&mut self) -> Vec<Location> {
+        // the batches come in sorted, which the sort is quick with.
+        self.found
+            .sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+        self.found.clone()
+    }
+
+    /// Whether more results are on their way, for the query typed or for
+    /// files not walked or searched yet.
+    pub fn is_busy(&self) -> bool {
+        self.busy || self.shown != self.query
+    }
+
+    pub fn selected_location(&self) -> Option<&Location> {
+        Some(&self.matches.get(self.selected)?.location)
+    }
+
+    /// The selected file read into a buffer for the preview, None when there
+    /// is nothing selected or the file is binary or can't be read. The file
+    /// is read again only when the selection moves to another one.
+    pub fn preview(&mut self) -> Option<&mut Buffer> {
+        let path = self.selected_location()?.path.clone();
+        if self
+            .preview
+            .as_ref()
+            .is_none_or(|(shown, _)| *shown != path)
+        {
+            let buffer = read_preview(&path);
+            self.preview = Some((path, buffer));
+        }
+        self.preview.as_mut()?.1.as_mut()
+    }
+}
+
+impl Drop for Picker {
+    // the workers stop when the queries channel closes, the walker has to be
+    // told.
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// `./src/main.rs` is opened as `src/main.rs`.
+fn openable(path: &Path) -> PathBuf {
+    match path.strip_prefix(".") {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// an upper case letter that isn't part of an escape like \S, what smartcase
+// looks for.
+fn has_upper(pattern: &str) -> bool {
+    let mut escaped = false;
+    pattern.chars().any(|c| {
+        let upper = !escaped && c.is_uppercase();
+        escaped = !escaped && c == '\\';
+        upper
+    })
+}
+
+// the start of the file, cut at a line end so no char is cut in half.
+fn read_preview(path: &Path) -> Option<Buffer> {
+    let mut bytes = vec![];
+    let file = std::fs::File::open(path).ok()?;
+    file.take(PREVIEW_BYTES).read_to_end(&mut bytes).ok()?;
+    if bytes.len() as u64 == PREVIEW_BYTES {
+        let end = bytes
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .unwrap_or(bytes.len());
+        bytes.truncate(end);
+    }
+    let buffer = Buffer::new(Some(path.display().to_string()), Some(&bytes));
+    (!buffer.binary).then_some(buffer)
+}
+
+// calls `visit` with every file under `root` on as many threads as there are
+// cpus, which is what makes large trees fast to go through. stops early when
+// `visit` says so.
+fn walk(root: &Path, visit: &(impl Fn(&Path) -> WalkState + Sync)) {
+    let walker = WalkBuilder::new(root)
+        .threads(num_cpus::get())
+        .build_parallel();
+    walker.run(|| {
+        Box::new(move |found| match found {
+            Ok(found) if found.file_type().is_some_and(|t| t.is_file()) => visit(found.path()),
+            _ => WalkState::Continue,
+        })
+    });
+}
+
+fn walk_files(root: &Path, items: &Mutex<Items>, cancelled: &AtomicBool) {
+    walk(root, &|path| {
+        if cancelled.load(Ordering::Relaxed) {
+            return WalkState::Quit;
+        }
+        let path = path.strip_prefix(root).unwrap_or(path);
+        let path = path.to_string_lossy().into_owned();
+        items.lock().unwrap().labels.push(path);
+        WalkState::Continue
+    });
+    items.lock().unwrap().loading = false;
+}
+
+// scores the items against the latest query, fzf style. while the query
+// stays the same only the items found since the last round are scored. ties
+// go to the shorter label, or to the earlier one with `keep_order`.
+fn filter(
+    items: &Mutex<Items>,
+    queries: &Receiver<String>,
+    results: &Sender<Results>,
+    location: impl Fn(usize, &str) -> Location,
+    keep_order: bool,
+) {
+    let mut matcher = Matcher::new(Config::DEFAULT.match_paths());
+    let mut pattern = Pattern::default();
+    let mut query = None;
+    let mut labels: Vec<String> = vec![];
+    let mut scored = vec![];
+    let mut loading = true;
+    loop {
+        let mut changed = match queries.recv_timeout(RESCAN) {
+            Ok(q) => Some(q),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        // only the last of the queries typed meanwhile matters.
+        while let Ok(q) = queries.try_recv() {
+            changed = Some(q);
+        }
+        let (found, still_loading) = {
+            let items = items.lock().unwrap();
+            (items.labels[labels.len()..].to_vec(), items.loading)
+        };
+        let from = match changed {
+            Some(q) => {
+                pattern.reparse(&q, CaseMatching::Smart, Normalization::Smart);
+                query = Some(q);
+                scored.clear();
+                0
+            }
+            None if found.is_empty() && still_loading == loading => continue,
+            None => labels.len(),
+        };
+        let Some(query) = &query else {
+            continue;
+        };
+        labels.extend(found);
+        loading = still_loading;
+        score(&pattern, &mut matcher, &labels, from, &mut scored);
+        let best = best(&pattern, &mut matcher, &labels, &mut scored, keep_order);
+        let sent = results.send(Results {
+            query: query.clone(),
+            matches: best
+                .into_iter()
+                .map(|(i, indices)| Match {
+                    label: labels[i].clone(),
+                    indices,
+                    location: location(i, &labels[i]),
+                })
+                .collect(),
+            append: false,
+            matched: scored.len(),
+            total: labels.len(),
+            busy: loading,
+            error: None,
+        });
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+// scores labels[from..] and adds the ones that match to `scored`, as their
+// score and index.
+fn score(
+    pattern: &Pattern,
+    matcher: &mut Matcher,
+    labels: &[String],
+    from: usize,
+    scored: &mut Vec<(u32, usize)>,
+) {
+    let mut buf = vec![];
+    for (i, label) in labels.iter().enumerate().skip(from) {
+        if let Some(score) = pattern.score(Utf32Str::new(label, &mut buf), matcher) {
+            scored.push((score, i));
+        }
+    }
+}
+
+// the index and matched graphemes of the best MAX_SHOWN of the scored
+// labels, best first.
+fn best(
+    pattern: &Pattern,
+    matcher: &mut Matcher,
+    labels: &[String],
+    scored: &mut [(u32, usize)],
+    keep_order: bool,
+) -> Vec<(usize, Vec<usize>)> {
+    let order = |a: &(u32, usize), b: &(u32, usize)| {
+        let (la, lb) = (&labels[a.1], &labels[b.1]);
+        let tie = match keep_order {
+            true => a.1.cmp(&b.1),
+            false => la.len().cmp(&lb.len()).then_with(|| la.cmp(lb)),
+        };
+        b.0.cmp(&a.0).then(tie)
+    };
+    let shown = scored.len().min(MAX_SHOWN);
+    if shown < scored.len() {
+        scored.select_nth_unstable_by(shown, order);
+    }
+    let best = &mut scored[..shown];
+    best.sort_unstable_by(order);
+    let mut buf = vec![];
+    best.iter()
+        .map(|(_, i)| {
+            let mut indices = vec![];
+            pattern.indices(Utf32Str::new(&labels[*i], &mut buf), matcher, &mut indices);
+            indices.sort_unstable();
+            indices.dedup();
+            (*i, indices.into_iter().map(|i| i as usize).collect())
+        })
+        .collect()
+}
+
+// what one search found so far, the walker threads add to it and the
+// searcher takes the matches out.
+#[derive(Default)]
+struct Found {
+    matches: Vec<Match>,
+    count: usize,
+    files: usize,
+    done: bool,
+}
+
+// a search under way: its query, what it found and how to stop it.
+type Running = (String, Arc<Mutex<Found>>, Arc<AtomicBool>);
+
+// runs a search for every query on threads of its own, the one for the
+// query before is cancelled. what is found is sent on every RESCAN.
+fn search(
+    root: &Path,
+    queries: &Receiver<String>,
+    results: &Sender<Results>,
+    ignore_case: impl Fn(&str) -> bool,
+) {
+    let mut running: Option<Running> = None;
+    let cancel = |running: &Option<Running>| {
+        if let Some((_, _, cancelled)) = running {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    };
+    loop {
+        let mut changed = match queries.recv_timeout(RESCAN) {
+            Ok(q) => Some(q),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        while let Ok(q) = queries.try_recv() {
+            changed = Some(q);
+        }
+        let sent = match changed {
+            Some(query) => {
+                cancel(&running);
+                running = None;
+                let regex = RegexBuilder::new(&query)
+                    .case_insensitive(ignore_case(&query))
+                    .build();
+                // regex errors point at the pattern over several lines, the
+                // last one says what is wrong.
+                let error = match regex {
+                    _ if query.is_empty() => None,
+                    Err(e) => e.to_string().lines().last().map(String::from),
+                    Ok(regex) => {
+                        let found = Arc::new(Mutex::new(Found::default()));
+                        let cancelled = Arc::new(AtomicBool::new(false));
+                        let (root, searched, stop) =
+                            (root.to_path_buf(), found.clone(), cancelled.clone());
+                        thread::spawn(move || search_files(&root, &regex, &searched, &stop));
+                        running = Some((query.clone(), found, cancelled));
+                        None
+                    }
+                };
+                results.send(Results {
+                    query,
+                    matches: vec![],
+                    append: false,
+                    matched: 0,
+                    total: 0,
+                    busy: running.is_some(),
+                    error,
+                })
+            }
+            None => {
+                let Some((query, found, _)) = &running else {
+                    continue;
+                };
+                let (mut matches, count, files, done) = {
+                    let mut found = found.lock().unwrap();
+                    let matches = std::mem::take(&mut found.matches);
+                    (matches, found.count, found.files, found.done)
+                };
+                // the files are searched in no particular order, the picker
+                // merges what comes in sorted.
+                matches.sort_by(|a, b| place(a).cmp(&place(b)));
+                let sent = results.send(Results {
+                    query: query.clone(),
+                    matches,
+                    append: true,
+                    matched: count,
+                    total: files,
+                    busy: !done,
+                    error: None,
+                });
+                if done {
+                    running = None;
+                }
+                sent
+            }
+        };
+        if sent.is_err() {
+            break;
+        }
+    }
+    cancel(&running);
+}
+
+fn place(found: &Match) -> (&Path, usize) {
+    (&found.location.path, found.location.line)
+}
+
+// two lists of matches sorted by place as one, no longer than MAX_SHOWN.
+fn merge(a: Vec<Match>, b: Vec<Match>) -> Vec<Match> {
+    let mut merged = Vec::with_capacity((a.len() + b.len()).min(MAX_SHOWN));
+    let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+    while merged.len() < MAX_SHOWN {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if place(y) < place(x) => b.next(),
+            (Some(_), _) => a.next(),
+            (None, _) => b.next(),
+        };
+        let Some(next) = next else {
+            break;
+        };
+        merged.push(next);
+    }
+    merged
+}
+
+fn search_files(root: &Path, regex: &Regex, found: &Mutex<Found>, cancelled: &AtomicBool) {
+    walk(root, &|path| {
+        if cancelled.load(Ordering::Relaxed) {
+            return WalkState::Quit;
+        }
+        let matches = search_file(&openable(path), regex);
+        let mut found = found.lock().unwrap();
+        found.files += 1;
+        found.count += matches.len();
+        found.matches.extend(matches);
+        match found.count >= MAX_GREP {
+            true => WalkState::Quit,
+            false => WalkState::Continue,
+        }
+    });
+    found.lock().unwrap().done = true;
+}
+
+// the lines of the file the regex matches, read the way a buffer would read
+// them. binary files are skipped.
+fn search_file(path: &Path, regex: &Regex) -> Vec<Match> {
+    let Ok(bytes) = std::fs::read(path) else {
+        return vec![];
+    };
+    let (text, encoding, _) = decode(&bytes);
+    if is_binary(&bytes, encoding) {
+        return vec![];
+    }
+    let (lines, _, _) = split_lines(&text);
+    let mut matches = vec![];
+    for (line_no, line) in lines.iter().enumerate() {
+        let Some(first) = regex.find(line) else {
+            continue;
+        };
+        let indent = line.len() - line.trim_start().len();
+        let text: String = graphemes(&line[indent..])
+            .take(MAX_TEXT)
+            .map(|(_, g)| g)
+            .collect();
+        let location = Location {
+            path: path.to_path_buf(),
+            line: line_no,
+            col: byte_to_grapheme(line, first.start()),
+            text,
+        };
+        // every match on the line is highlighted, after the path and numbers.
+        let before = graphemes(&location.prefix()).count() + 2;
+        let spans: Vec<_> = regex.find_iter(line).map(|m| m.range()).collect();
+        let indices = graphemes(&location.text)
+            .enumerate()
+            .filter(|(_, (byte, _))| spans.iter().any(|s| s.contains(&(indent + byte))))
+            .map(|(i, _)| before + i)
+            .collect();
+        matches.push(Match {
+            label: location.label(),
+            indices,
+            location,
+        });
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::time::Instant;
+
+    // polls until the picker has all its results.
+    fn settle(picker: &mut Picker) {
+        let started = Instant::now();
+        while picker.is_busy() && started.elapsed() < Duration::from_secs(10) {
+            picker.poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn finds_and_ranks_files() {
+        let dir = TestDir::new("picker");
+        for path in [
+            "src/main.rs",
+            "src/editor/main_editor.rs",
+            "README.md",
+            "target/main.rs",
+            ".hidden/main.rs",
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        // .ignore is read outside of git repositories too.
+        std::fs::write(dir.join(".ignore"), "target/\n").unwrap();
+
+        let mut picker = Picker::files(dir.to_path_buf());
+        for c in "mainrs".chars() {
+            picker.push(c);
+        }
+        settle(&mut picker);
+        let labels: Vec<_> = picker.matches.iter().map(|m| m.label.as_str()).collect();
+        assert_eq!(labels, ["src/main.rs", "src/editor/main_editor.rs"]);
+        assert_eq!(picker.matches[0].indices, [4, 5, 6, 7, 9, 10]);
+        assert_eq!(picker.total, 3);
+
+        // smart case: an upper case letter has to match one.
+        let mut matcher = Matcher::new(Config::DEFAULT.match_paths());
+        let paths = ["README.md".to_string(), "src/readme.rs".to_string()];
+        let pattern = Pattern::parse("READ", CaseMatching::Smart, Normalization::Smart);
+        let mut scored = vec![];
+        score(&pattern, &mut matcher, &paths, 0, &mut scored);
+        assert_eq!(
+            best(&pattern, &mut matcher, &paths, &mut scored, false).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn greps_files() {
+        let dir = TestDir::new("grep");
+        for (path, text) in [
+            ("src/main.rs", "fn main() {\n    run();\n}\n"),
+            (
+                "src/editor/main_editor.rs",
+                "pub fn Run() {}\n\tfn run() { run() }\n",
+            ),
+            ("README.md", "run it\n"),
+            ("target/main.rs", "fn run() {}\n"),
+            (".hidden/main.rs", "fn run() {}\n"),
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        std::fs::write(dir.join(".ignore"), "target/\n").unwrap();
+
+        let mut picker = Picker::grep(dir.to_path_buf(), String::from(r"fn \w+"), true, true);
+        settle(&mut picker);
+        let lines: Vec<_> = picker.matches.iter().map(|m| m.location.line).collect();
+        assert_eq!(lines, [0, 1, 0]);
+        let found = picker.finished().unwrap();
+        assert_eq!(found.iter().map(|l| l.line).collect::<Vec<_>>(), lines);
+        assert_eq!(picker.finished(), None);
+        let second = &picker.matches[1];
+        assert_eq!(second.location.col, 1);
+        assert!(second
+            .label
+            .ends_with("main_editor.rs:2:2: fn run() { run() }"));
+        // the highlight starts after the path, line, column and colon.
+        let at = second.label.chars().count() - "fn run() { run() }".len();
+        assert_eq!(second.indices, (at..at + 6).collect::<Vec<_>>());
+
+        // smartcase: an upper case letter has to match one, \w doesn't count.
+        for c in "Run".chars() {
+            picker.push(c);
+        }
+        settle(&mut picker);
+        assert!(picker.matches.is_empty());
+        picker.clear();
+        for c in r"fn Run\(".chars() {
+            picker.push(c);
+        }
+        settle(&mut picker);
+        assert_eq!(picker.matches.len(), 1);
+        picker.push('[');
+        settle(&mut picker);
+        assert!(picker.error.is_some());
+
+        // the picker lists MAX_SHOWN, the quickfix list gets every match.
+        std::fs::write(dir.join("many.txt"), "fn x\n".repeat(MAX_SHOWN + 200)).unwrap();
+        let mut picker = Picker::grep(dir.to_path_buf(), String::from("fn x"), false, false);
+        settle(&mut picker);
+        assert_eq!(picker.matches.len(), MAX_SHOWN);
+        let found = picker.finished().unwrap();
+        assert_eq!(found.len(), MAX_SHOWN + 200);
+        assert!(found.windows(2).all(|w| w[0].line < w[1].line));
+
+        // batches come in sorted and stay that way, up to MAX_SHOWN.
+        let at = |path: &str, line| Match {
+            label: String::new(),
+            indices: vec![],
+            location: Location {
+                line,
+                ..Location::file(PathBuf::from(path))
+            },
+        };
+        let places = |matches: &[Match]| -> Vec<(PathBuf, usize)> {
+            let places = matches
+                .iter()
+                .map(|m| (m.location.path.clone(), m.location.line));
+            places.collect()
+        };
+        let merged = merge(vec![at("a", 1), at("b", 0)], vec![at("a", 0), at("c", 0)]);
+        assert_eq!(
+            places(&merged),
+            [
+                ("a".into(), 0),
+                ("a".into(), 1),
+                ("b".into(), 0),
+                ("c".into(), 0)
+            ]
+        );
+        let many = (0..MAX_SHOWN).map(|line| at("b", line)).collect();
+        let merged = merge(many, vec![at("a", 0)]);
+        assert_eq!(merged.len(), MAX_SHOWN);
+        assert_eq!(merged[0].location.path, Path::new("a"));
+    }
+}
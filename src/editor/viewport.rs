@@ -0,0 +1,180 @@
+/// The window onto the buffer: which line is drawn on the first screen row and
+/// how many rows there are. Every vertical scroll goes through here so that
+/// scrolloff and the ends of the buffer are handled in one place.
+///
+/// `rows` gives the number of screen rows a buffer line takes, which is 1
+/// unless wrap is on.
+#[derive(Debug)]
+pub struct Viewport {
+    pub top: usize,
+    pub height: usize,
+}
+
+impl Viewport {
+    pub fn new(height: usize) -> Self {
+        Self { top: 0, height }
+    }
+
+    // scrolloff can't be more than half the screen or the cursor would have
+    // nowhere to go.
+    fn margin(&self, scrolloff: usize) -> usize {
+        scrolloff.min(self.height.saturating_sub(1) / 2)
+    }
+
+    fn rows_above(&self, line: usize, rows: &dyn Fn(usize) -> usize) -> usize {
+        (self.top..line).map(rows).sum()
+    }
+
+    /// Last buffer line that fits on screen completely.
+    pub fn bottom(&self, line_count: usize, rows: &dyn Fn(usize) -> usize) -> usize {
+        let mut used = 0;
+        for line in self.top..line_count {
+            used += rows(line);
+            if used > self.height {
+                return line.saturating_sub(1).max(self.top);
+            }
+        }
+        line_count.saturating_sub(1).max(self.top)
+    }
+
+    /// Smallest top that still shows the last line on the last screen row.
+    pub fn max_top(&self, line_count: usize, rows: &dyn Fn(usize) -> usize) -> usize {
+        let mut used = 0;
+        let mut top = line_count;
+        while top > 0 && used + rows(top - 1) <= self.height {
+            top -= 1;
+            used += rows(top);
+        }
+        top.min(line_count.saturating_sub(1))
+    }
+
+    // topmost line that keeps `line` with at most `wanted` rows above it.
+    fn top_for(&self, line: usize, wanted: usize, rows: &dyn Fn(usize) -> usize) -> usize {
+        let mut top = line;
+        let mut above = 0;
+        while top > 0 && above + rows(top - 1) <= wanted {
+            top -= 1;
+            above += rows(top);
+        }
+        top
+    }
+
+    /// Scrolls the least amount needed to show the cursor, which is on screen
+    /// row `row` of buffer line `line`, with `scrolloff` rows around it. A jump
+    /// further than half a screen away recenters instead.
+    pub fn scroll_to(
+        &mut self,
+        line: usize,
+        row: usize,
+        line_count: usize,
+        scrolloff: usize,
+        rows: &dyn Fn(usize) -> usize,
+    ) {
+        let so = self.margin(scrolloff);
+        let half = self.height / 2;
+        if line + half < self.top || line > self.bottom(line_count, rows) + half {
+            self.center_on(line, rows);
+            self.top = self.top.min(self.max_top(line_count, rows));
+        }
+
+        if line < self.top {
+            self.top = line;
+        }
+        while self.top > 0 && self.rows_above(line, rows) + row < so {
+            self.top -= 1;
+        }
+        // the end of the buffer counts as margin, the view never scrolls to
+        // show empty rows just to honour scrolloff.
+        loop {
+            let above = self.rows_above(line, rows) + row;
+            let mut below = rows(line).saturating_sub(row + 1);
+            let mut next = line + 1;
+            while below < so && next < line_count {
+                below += rows(next);
+                next += 1;
+            }
+            if above + 1 + below.min(so) <= self.height || self.top >= line {
+                break;
+            }
+            self.top += 1;
+        }
+    }
+
+    /// Moves the view by `delta` lines without looking at the cursor.
+    pub fn scroll_by(&mut self, delta: isize, max_top: usize) {
+        self.top = self.top.saturating_add_signed(delta).min(max_top);
+    }
+
+    /// Lines the cursor can be on without the view having to scroll, used to
+    /// drag the cursor along when the view moves on its own.
+    pub fn cursor_range(
+        &self,
+        line_count: usize,
+        scrolloff: usize,
+        rows: &dyn Fn(usize) -> usize,
+    ) -> (usize, usize) {
+        let so = self.margin(scrolloff);
+        let bottom = self.bottom(line_count, rows);
+        let first = if self.top == 0 { 0 } else { self.top + so };
+        let last = if bottom + 1 >= line_count {
+            bottom
+        } else {
+            bottom.saturating_sub(so)
+        };
+        (first.min(last), last)
+    }
+
+    pub fn center_on(&mut self, line: usize, rows: &dyn Fn(usize) -> usize) {
+        let wanted = self.height.saturating_sub(rows(line)) / 2;
+        self.top = self.top_for(line, wanted, rows);
+    }
+
+    /// zt, puts `line` at the top with scrolloff rows above it.
+    pub fn top_on(&mut self, line: usize, scrolloff: usize, rows: &dyn Fn(usize) -> usize) {
+        self.top = self.top_for(line, self.margin(scrolloff), rows);
+    }
+
+    /// zb, puts `line` at the bottom with scrolloff rows below it.
+    pub fn bottom_on(&mut self, line: usize, scrolloff: usize, rows: &dyn Fn(usize) -> usize) {
+        let wanted = self
+            .height
+            .saturating_sub(rows(line) + self.margin(scrolloff));
+        self.top = self.top_for(line, wanted, rows);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one(_: usize) -> usize {
+        1
+    }
+
+    #[test]
+    fn keeps_scrolloff_around_the_cursor() {
+        let mut view = Viewport::new(10);
+        view.scroll_to(7, 0, 100, 3, &one);
+        assert_eq!(view.top, 1);
+        view.scroll_to(4, 0, 100, 3, &one);
+        assert_eq!(view.top, 1);
+        view.scroll_to(3, 0, 100, 3, &one);
+        assert_eq!(view.top, 0);
+        // the end of the buffer doesn't need a margin
+        view.scroll_to(99, 0, 100, 3, &one);
+        assert_eq!(view.top, 90);
+        assert_eq!(view.cursor_range(100, 3, &one), (93, 99));
+    }
+
+    #[test]
+    fn positions_a_line_at_top_center_and_bottom() {
+        let mut view = Viewport::new(10);
+        view.top_on(20, 2, &one);
+        assert_eq!(view.top, 18);
+        view.bottom_on(20, 2, &one);
+        assert_eq!(view.top, 13);
+        view.center_on(20, &one);
+        assert_eq!(view.top, 16);
+        assert_eq!(view.max_top(100, &one), 90);
+    }
+}
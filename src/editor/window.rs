@@ -0,0 +1,75 @@
+use super::action::Action;
+use super::options::Options;
+use super::viewport::Viewport;
+use crate::Buffer;
+
+/// Where the picker opens the file it picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Open {
+    Here,
+    Split, // a new window below the current one
+    Tab,   // a new tab after the current one
+}
+
+/// A window that isn't the current one. The editor keeps the buffer, cursor
+/// and view of the current window in its own fields, and trades them with one
+/// of these when another window becomes current or is drawn.
+pub struct Window {
+    pub buffer: Buffer,
+    pub options: Options,
+    pub editorconfig: Vec<String>,
+    pub viewport: Viewport,
+    pub cx: u16,
+    pub cy: u16,
+    pub curswant: usize,
+    pub leftcol: usize,
+    pub undo_actions_list: Vec<Action>,
+    pub undo_cursor_pos: (u16, u16),
+    pub undo_buffer_list: Vec<(String, u16)>,
+}
+
+impl Window {
+    pub fn new(buffer: Buffer, options: Options, editorconfig: Vec<String>) -> Self {
+        Self {
+            buffer,
+            options,
+            editorconfig,
+            viewport: Viewport::new(0),
+            cx: 0,
+            cy: 0,
+            curswant: 0,
+            leftcol: 0,
+            undo_actions_list: vec![],
+            undo_cursor_pos: (0, 0),
+            undo_buffer_list: vec![],
+        }
+    }
+}
+
+/// First row and number of text rows of `count` windows stacked in `height`
+/// rows, each with its statusline below it. The last one gets the rows that
+/// don't divide evenly.
+pub fn stack(height: u16, count: usize) -> Vec<(u16, u16)> {
+    let count = count.max(1) as u16;
+    let text = height.saturating_sub(count);
+    let each = text / count;
+    (0..count)
+        .map(|i| match i + 1 == count {
+            true => (i * (each + 1), text - each * i),
+            false => (i * (each + 1), each),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacks_windows_over_their_statuslines() {
+        assert_eq!(stack(23, 1), [(0, 22)]);
+        assert_eq!(stack(23, 2), [(0, 10), (11, 11)]);
+        assert_eq!(stack(23, 3), [(0, 6), (7, 6), (14, 8)]);
+        assert_eq!(stack(2, 3), [(0, 0), (1, 0), (2, 0)]);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::buffer::grapheme::{cell_width, grapheme_width};
+
+use super::options::Options;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// One screen row of a buffer line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayRow {
+    pub start: usize,     // first grapheme on the row
+    pub end: usize,       // one past the last grapheme on the row
+    pub start_col: usize, // virtual column of `start` inside the whole line
+    pub indent: usize,    // cells taken by breakindent and showbreak before the text
+}
+
+impl DisplayRow {
+    pub fn is_continuation(&self) -> bool {
+        self.start > 0
+    }
+}
+
+/// Splits `line` into the rows it takes on a `width` cell wide screen. With
+/// wrap off the whole line is a single row and anything past `width` is clipped
+/// by the caller.
+pub fn wrap_line(line: &str, width: usize, options: &Options) -> Vec<DisplayRow> {
+    let gs: Vec<&str> = line.graphemes(true).collect();
+    if !options.wrap || width == 0 {
+        return vec![DisplayRow {
+            start: 0,
+            end: gs.len(),
+            start_col: 0,
+            indent: 0,
+        }];
+    }
+
+    let tabstop = options.tabstop;
+    let mut cols = Vec::with_capacity(gs.len() + 1);
+    let mut col = 0;
+    for g in &gs {
+        cols.push(col);
+        col += cell_width(g, col, tabstop);
+    }
+    cols.push(col);
+
+    let is_blank = |g: &str| g == " " || g == "\t";
+    let lead = if options.breakindent {
+        cols[gs.iter().take_while(|g| is_blank(g)).count()]
+    } else {
+        0
+    };
+    // leave at least one cell for text on continuation rows no matter how deep
+    // the indent is.
+    let cont_indent = (lead + showbreak_width(options)).min(width - 1);
+
+    let mut rows = vec![];
+    let mut row = DisplayRow {
+        start: 0,
+        end: 0,
+        start_col: 0,
+        indent: 0,
+    };
+    let mut avail = width;
+    let mut last_break = None;
+    let mut i = 0;
+    while i < gs.len() {
+        if cols[i + 1] - row.start_col > avail && i > row.start {
+            let brk = match last_break {
+                Some(b) if options.linebreak && b > row.start => b,
+                _ => i,
+            };
+            row.end = brk;
+            rows.push(row);
+            row = DisplayRow {
+                start: brk,
+                end: brk,
+                start_col: cols[brk],
+                indent: cont_indent,
+            };
+            avail = width - cont_indent;
+            last_break = None;
+            continue;
+        }
+        if is_blank(gs[i]) {
+            last_break = Some(i + 1);
+        }
+        i += 1;
+    }
+    row.end = gs.len();
+    rows.push(row);
+    rows
+}
+
+/// Screen rows each buffer line takes, in the shape the viewport wants.
+pub fn line_rows<'a>(
+    lines: &'a [String],
+    width: usize,
+    options: &'a Options,
+) -> impl Fn(usize) -> usize + 'a {
+    move |line_no| match lines.get(line_no) {
+        Some(line) if options.wrap => wrap_line(line, width, options).len(),
+        _ => 1,
+    }
+}
+
+pub fn showbreak_width(options: &Options) -> usize {
+    options.showbreak.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Index of the row the grapheme `idx` is drawn on.
+pub fn row_of(rows: &[DisplayRow], idx: usize) -> usize {
+    rows.iter().rposition(|r| r.start <= idx).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapping() -> Options {
+        Options {
+            wrap: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn breaks_at_width_or_word_boundary() {
+        let mut options = wrapping();
+        let rows = wrap_line("hello wide world", 8, &options);
+        let spans: Vec<_> = rows.iter().map(|r| (r.start, r.end)).collect();
+        assert_eq!(spans, vec![(0, 8), (8, 16)]);
+
+        options.linebreak = true;
+        let rows = wrap_line("hello wide world", 8, &options);
+        let spans: Vec<_> = rows.iter().map(|r| (r.start, r.end)).collect();
+        assert_eq!(spans, vec![(0, 6), (6, 11), (11, 16)]);
+        assert_eq!(row_of(&rows, 12), 2);
+    }
+
+    #[test]
+    fn continuation_rows_are_indented() {
+        let mut options = wrapping();
+        options.breakindent = true;
+        options.showbreak = String::from("> ");
+        let rows = wrap_line("  abcdefghij", 8, &options);
+        let spans: Vec<_> = rows.iter().map(|r| (r.start, r.end, r.indent)).collect();
+        assert_eq!(spans, vec![(0, 8, 0), (8, 12, 4)]);
+    }
+}
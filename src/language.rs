@@ -0,0 +1,2 @@
+pub mod _language;
+pub mod detect;
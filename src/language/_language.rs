@@ -0,0 +1,360 @@
+use std::sync::OnceLock;
+
+use tree_sitter::{Language, Query};
+
+use super::detect;
+use crate::log;
+
+// additions to the injections the grammars ship with.
+const RUST_INJECTIONS: &str = include_str!("queries/rust/injections.scm");
+const MARKDOWN_INJECTIONS: &str = include_str!("queries/markdown/injections.scm");
+
+/// A filetype the editor knows how to parse, with the queries that go with
+/// its grammar.
+pub struct LanguageConfig {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub extensions: &'static [&'static str],
+    pub filenames: &'static [&'static str],
+    pub interpreters: &'static [&'static str], // matched against the shebang
+    pub language: Language,
+    pub highlights: String,
+    pub injections: String,
+    pub locals: String,
+    query: OnceLock<Result<Query, String>>,
+    injection_query: OnceLock<Option<Query>>,
+}
+
+impl LanguageConfig {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        name: &'static str,
+        aliases: &'static [&'static str],
+        extensions: &'static [&'static str],
+        filenames: &'static [&'static str],
+        interpreters: &'static [&'static str],
+        language: impl Into<Language>,
+        highlights: &[&str],
+        injections: &[&str],
+        locals: &str,
+    ) -> Self {
+        Self {
+            name,
+            aliases,
+            extensions,
+            filenames,
+            interpreters,
+            language: language.into(),
+            // some grammars only ship the queries for what they add on top of
+            // the grammar they extend, those get concatenated.
+            highlights: highlights.join("\n"),
+            injections: injections.join("\n"),
+            locals: locals.to_string(),
+            query: OnceLock::new(),
+            injection_query: OnceLock::new(),
+        }
+    }
+
+    /// The compiled highlight query, built the first time a buffer of this
+    /// language needs it and shared by all of them after that. The locals
+    /// patterns go first the way tree-sitter expects them.
+    pub fn highlight_query(&'static self) -> anyhow::Result<&'static Query> {
+        self.query
+            .get_or_init(|| {
+                let source = format!("{}\n{}", self.locals, self.highlights);
+                Query::new(&self.language, &source)
+                    .map_err(|e| format!("{} highlight query: {e}", self.name))
+            })
+            .as_ref()
+            .map_err(|e| anyhow::anyhow!("{e}"))
+    }
+
+    /// The compiled injections query, None when the language doesn't embed
+    /// others.
+    pub fn injection_query(&'static self) -> Option<&'static Query> {
+        self.injection_query
+            .get_or_init(|| {
+                if self.injections.trim().is_empty() {
+                    return None;
+                }
+                match Query::new(&self.language, &self.injections) {
+                    Ok(query) => Some(query),
+                    Err(e) => {
+                        log!("{} injection query: {e} \n", self.name);
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+
+    fn is_called(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+pub struct Registry {
+    languages: Vec<LanguageConfig>,
+}
+
+/// The languages compiled into the editor.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::builtin)
+}
+
+impl Registry {
+    fn builtin() -> Self {
+        let languages = vec![
+            LanguageConfig::new(
+                "rust",
+                &["rs"],
+                &["rs"],
+                &[],
+                &[],
+                tree_sitter_rust::LANGUAGE,
+                &[tree_sitter_rust::HIGHLIGHTS_QUERY],
+                &[tree_sitter_rust::INJECTIONS_QUERY, RUST_INJECTIONS],
+                "",
+            ),
+            LanguageConfig::new(
+                "python",
+                &["py"],
+                &["py", "pyi", "pyw"],
+                &[],
+                &["python"],
+                tree_sitter_python::LANGUAGE,
+                &[tree_sitter_python::HIGHLIGHTS_QUERY],
+                &[],
+                "",
+            ),
+            LanguageConfig::new(
+                "javascript",
+                &["js", "jsx"],
+                &["js", "mjs", "cjs", "jsx"],
+                &[],
+                &["node", "nodejs"],
+                tree_sitter_javascript::LANGUAGE,
+                &[
+                    tree_sitter_javascript::HIGHLIGHT_QUERY,
+                    tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
+                ],
+                &[tree_sitter_javascript::INJECTIONS_QUERY],
+                tree_sitter_javascript::LOCALS_QUERY,
+            ),
+            LanguageConfig::new(
+                "typescript",
+                &["ts"],
+                &["ts", "mts", "cts"],
+                &[],
+                &["deno", "ts-node"],
+                tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+                &[
+                    tree_sitter_typescript::HIGHLIGHTS_QUERY,
+                    tree_sitter_javascript::HIGHLIGHT_QUERY,
+                ],
+                &[tree_sitter_javascript::INJECTIONS_QUERY],
+                tree_sitter_typescript::LOCALS_QUERY,
+            ),
+            LanguageConfig::new(
+                "tsx",
+                &[],
+                &["tsx"],
+                &[],
+                &[],
+                tree_sitter_typescript::LANGUAGE_TSX,
+                &[
+                    tree_sitter_typescript::HIGHLIGHTS_QUERY,
+                    tree_sitter_javascript::HIGHLIGHT_QUERY,
+                    tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
+                ],
+                &[tree_sitter_javascript::INJECTIONS_QUERY],
+                tree_sitter_typescript::LOCALS_QUERY,
+            ),
+            LanguageConfig::new(
+                "go",
+                &["golang"],
+                &["go"],
+                &[],
+                &[],
+                tree_sitter_go::LANGUAGE,
+                &[tree_sitter_go::HIGHLIGHTS_QUERY],
+                &[],
+                "",
+            ),
+            LanguageConfig::new(
+                "c",
+                &[],
+                &["c", "h"],
+                &[],
+                &["tcc"],
+                tree_sitter_c::LANGUAGE,
+                &[tree_sitter_c::HIGHLIGHT_QUERY],
+                &[],
+                "",
+            ),
+            LanguageConfig::new(
+                "cpp",
+                &["c++", "cxx"],
+                &["cc", "cpp", "cxx", "c++", "hh", "hpp", "hxx", "h++"],
+                &[],
+                &[],
+                tree_sitter_cpp::LANGUAGE,
+                &[
+                    tree_sitter_cpp::HIGHLIGHT_QUERY,
+                    tree_sitter_c::HIGHLIGHT_QUERY,
+                ],
+                &[],
+                "",
+            ),
+            LanguageConfig::new(
+                "toml",
+                &[],
+                &["toml"],
+                &["Cargo.lock", "Pipfile"],
+                &[],
+                tree_sitter_toml_ng::LANGUAGE,
+                &[tree_sitter_toml_ng::HIGHLIGHTS_QUERY],
+                &[],
+                "",
+            ),
+            LanguageConfig::new(
+                "json",
+                &[],
+                &["json", "jsonc"],
+                &[".prettierrc", ".eslintrc"],
+                &[],
+                tree_sitter_json::LANGUAGE,
+                &[tree_sitter_json::HIGHLIGHTS_QUERY],
+                &[],
+                "",
+            ),
+            LanguageConfig::new(
+                "yaml",
+                &["yml"],
+                &["yaml", "yml"],
+                &[".clang-format", ".clangd"],
+                &[],
+                tree_sitter_yaml::LANGUAGE,
+                &[tree_sitter_yaml::HIGHLIGHTS_QUERY],
+                &[],
+                "",
+            ),
+            LanguageConfig::new(
+                "markdown",
+                &["md"],
+                &["md", "markdown", "mkd"],
+                &[],
+                &[],
+                tree_sitter_md::LANGUAGE,
+                &[tree_sitter_md::HIGHLIGHT_QUERY_BLOCK],
+                &[tree_sitter_md::INJECTION_QUERY_BLOCK, MARKDOWN_INJECTIONS],
+                "",
+            ),
+            // only reachable through injections from markdown.
+            LanguageConfig::new(
+                "markdown_inline",
+                &[],
+                &[],
+                &[],
+                &[],
+                tree_sitter_md::INLINE_LANGUAGE,
+                &[tree_sitter_md::HIGHLIGHT_QUERY_INLINE],
+                &[tree_sitter_md::INJECTION_QUERY_INLINE],
+                "",
+            ),
+            LanguageConfig::new(
+                "bash",
+                &["sh", "shell", "zsh"],
+                &["sh", "bash"],
+                &[".bashrc", ".bash_profile", ".bash_aliases", ".profile"],
+                &["sh", "bash", "zsh", "dash", "ksh"],
+                tree_sitter_bash::LANGUAGE,
+                &[tree_sitter_bash::HIGHLIGHT_QUERY],
+                &[],
+                "",
+            ),
+        ];
+        Self { languages }
+    }
+
+    pub fn languages(&self) -> &[LanguageConfig] {
+        &self.languages
+    }
+
+    /// Looks a language up by its filetype name or one of its aliases.
+    pub fn by_name(&self, name: &str) -> Option<&LanguageConfig> {
+        let name = name.to_lowercase();
+        self.languages.iter().find(|l| l.is_called(&name))
+    }
+
+    pub fn by_path(&self, path: &str) -> Option<&LanguageConfig> {
+        let path = std::path::Path::new(path);
+        let file_name = path.file_name()?.to_str()?;
+        if let Some(lang) = self
+            .languages
+            .iter()
+            .find(|l| l.filenames.contains(&file_name))
+        {
+            return Some(lang);
+        }
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.languages
+            .iter()
+            .find(|l| l.extensions.contains(&ext.as_str()))
+    }
+
+    pub fn by_interpreter(&self, interpreter: &str) -> Option<&LanguageConfig> {
+        self.languages
+            .iter()
+            .find(|l| l.interpreters.contains(&interpreter))
+    }
+
+    /// Picks the language of a file. A modeline wins over the file name,
+    /// which wins over the shebang. None means plain text.
+    pub fn detect(&self, path: Option<&str>, lines: &[String]) -> Option<&LanguageConfig> {
+        detect::modeline(lines)
+            .and_then(|ft| self.by_name(&ft))
+            .or_else(|| path.and_then(|p| self.by_path(p)))
+            .or_else(|| {
+                let interpreter = detect::shebang(lines.first()?)?;
+                self.by_interpreter(&interpreter)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_query_compiles() {
+        for lang in &registry().languages {
+            if let Err(e) = lang.highlight_query() {
+                panic!("{e}");
+            }
+            if let Err(e) = Query::new(&lang.language, &lang.injections) {
+                panic!("{} injection query: {e}", lang.name);
+            }
+        }
+    }
+
+    #[test]
+    fn detection_order() {
+        let reg = registry();
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<_>>();
+        let name = |l: Option<&LanguageConfig>| l.map(|l| l.name);
+
+        assert_eq!(name(reg.detect(Some("src/main.rs"), &[])), Some("rust"));
+        assert_eq!(name(reg.detect(Some("Cargo.lock"), &[])), Some("toml"));
+        assert_eq!(
+            name(reg.detect(Some("run"), &lines("#!/usr/bin/env python3\n"))),
+            Some("python")
+        );
+        assert_eq!(
+            name(reg.detect(Some("a.txt"), &lines("x\n# vim: set ft=yaml:"))),
+            Some("yaml")
+        );
+        assert_eq!(name(reg.detect(Some("notes.txt"), &lines("hi"))), None);
+    }
+}
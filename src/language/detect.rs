@@ -0,0 +1,86 @@
+// how many lines at the start and the end of a file are searched for a
+// modeline, the same as vim's default 'modelines'.
+const MODELINES: usize = 5;
+
+/// Filetype named by a vim (`vim: set ft=python:`) or emacs
+/// (`-*- mode: python -*-`) modeline in the first or last lines.
+pub fn modeline(lines: &[String]) -> Option<String> {
+    let head = lines.iter().take(MODELINES);
+    let tail = lines.iter().skip(MODELINES).rev().take(MODELINES);
+    head.chain(tail)
+        .find_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+}
+
+fn vim_modeline(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let at = line.find(marker)?;
+        // the marker has to start the line or follow whitespace, so that
+        // "savvi:" doesn't count.
+        let starts_word = line[..at].chars().last().is_none_or(char::is_whitespace);
+        starts_word.then_some(at + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|opt| {
+            opt.strip_prefix("ft=")
+                .or_else(|| opt.strip_prefix("filetype="))
+        })
+        .filter(|ft| !ft.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn emacs_modeline(line: &str) -> Option<String> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let inner = line[start..end].trim();
+    let mode = match inner.split(';').find_map(|var| {
+        let (key, value) = var.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode")).then_some(value)
+    }) {
+        Some(mode) => mode,
+        None if !inner.contains(':') => inner,
+        None => return None,
+    };
+    let mode = mode.trim().to_lowercase();
+    (!mode.is_empty()).then_some(mode)
+}
+
+/// Interpreter named by a `#!` line, without its version, so
+/// `#!/usr/bin/env python3.11` gives "python".
+pub fn shebang(line: &str) -> Option<String> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // skip flags like -S and VAR=value assignments.
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    (!program.is_empty()).then(|| program.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modelines_and_shebangs() {
+        let lines = |s: &str| s.lines().map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            modeline(&lines("# vim: set ft=python :")),
+            Some("python".into())
+        );
+        assert_eq!(modeline(&lines("// vi:ts=4:filetype=c")), Some("c".into()));
+        assert_eq!(modeline(&lines("savvi: ft=c")), None);
+        assert_eq!(
+            modeline(&lines("/* -*- mode: C++; tab-width: 4 -*- */")),
+            Some("c++".into())
+        );
+        assert_eq!(modeline(&lines("# -*- sh -*-")), Some("sh".into()));
+        assert_eq!(modeline(&lines("# -*- coding: utf-8 -*-")), None);
+
+        assert_eq!(shebang("#!/bin/bash"), Some("bash".into()));
+        assert_eq!(shebang("#!/usr/bin/env -S deno run"), Some("deno".into()));
+        assert_eq!(shebang("#!/usr/bin/python3.11 -u"), Some("python".into()));
+        assert_eq!(shebang("fn main() {}"), None);
+    }
+}
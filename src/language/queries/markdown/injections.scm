@@ -0,0 +1,4 @@
+; code blocks without a language take the default the host set, they are left
+; alone when there is none.
+(fenced_code_block
+  (code_fence_content) @injection.content)
@@ -0,0 +1,6 @@
+; doc comments are markdown, rustdoc reads the code blocks in them that don't
+; name a language as rust.
+((doc_comment) @injection.content
+ (#set! injection.language "markdown")
+ (#set! injection.default-language "rust")
+ (#set! injection.combined))
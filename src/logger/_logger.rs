@@ -12,16 +12,12 @@ pub static LOGGER: OnceLock<Logger> = OnceLock::new();
 
 impl Logger {
     pub fn new(path: &str) -> anyhow::Result<Self> {
-        let file = OpenOptions::new()
-            .append(true)
-            .write(true)
-            .create(true)
-            .open(path)?;
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
 
         Ok(Self { file })
     }
     pub fn log(&self, message: String) -> anyhow::Result<()> {
-        (&self.file).write(message.as_bytes())?;
+        (&self.file).write_all(message.as_bytes())?;
         Ok(())
     }
 }
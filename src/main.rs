@@ -1,17 +1,65 @@
 mod buffer;
+mod config;
 mod editor;
+mod language;
 mod logger;
+#[cfg(test)]
+mod test_dir;
 mod theme;
 
 use buffer::_buffer::Buffer;
+use config::_config::Config;
 use editor::main_editor::Editor;
-use theme::vscode::parse_theme;
+use theme::depth::ColorDepth;
+use theme::loader::{default_theme, load_named, DEFAULT_THEME};
 
 fn main() -> anyhow::Result<()> {
-    let arg = std::env::args().nth(1);
-    let file_buffer = Buffer::from_file(arg);
-    let theme = parse_theme("./latte.json")?;
-    let mut editor = Editor::new(theme, file_buffer)?;
+    let mut file = None;
+    let mut theme_name = None;
+    let mut depth = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--theme" {
+            let Some(name) = args.next() else {
+                anyhow::bail!("--theme needs the name of a theme");
+            };
+            theme_name = Some(name);
+        } else if let Some(name) = arg.strip_prefix("--theme=") {
+            theme_name = Some(name.to_string());
+        } else if arg == "--colors" {
+            let Some(value) = args.next() else {
+                anyhow::bail!("--colors needs truecolor, 256 or 16");
+            };
+            depth = Some(ColorDepth::parse(&value)?);
+        } else if let Some(value) = arg.strip_prefix("--colors=") {
+            depth = Some(ColorDepth::parse(value)?);
+        } else {
+            file = Some(arg);
+        }
+    }
+
+    // the command line wins over the config files.
+    let (config, mut warnings) = Config::load();
+    let theme_name = theme_name
+        .or(config.theme.clone())
+        .unwrap_or(DEFAULT_THEME.to_string());
+    let depth = depth.or(config.colors);
+
+    let (file_buffer, opened) = Buffer::open(file);
+    warnings.extend(opened);
+    // a broken theme shouldn't keep the file from opening.
+    let theme = match load_named(&theme_name) {
+        Ok(theme) => theme,
+        Err(e) => {
+            warnings.push(format!("{e:#}, using the default theme"));
+            default_theme()
+        }
+    };
+    let mut editor = Editor::new(theme, file_buffer, &config)?;
+    editor.set_color_depth(depth.unwrap_or_else(ColorDepth::detect));
+    if !warnings.is_empty() {
+        editor.set_message(warnings.join("; "));
+    }
     editor.init_editor()?;
 
     Ok(())
@@ -1,2 +1,10 @@
 pub mod _theme;
 pub mod vscode;
+pub mod scope;
+pub mod loader;
+pub mod helix;
+pub mod base16;
+pub mod tmtheme;
+pub mod ui;
+pub mod depth;
+pub mod watch;
@@ -1,5 +1,11 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 use crossterm::style::{Attribute, Attributes, Color, ContentStyle};
 
+use super::scope::build_lookup;
+use super::ui::Ui;
+
 #[derive(Debug, Default, Clone)]
 pub struct Style {
     pub fg: Option<Color>,
@@ -10,6 +16,7 @@ pub struct Style {
 
 #[derive(Debug)]
 pub struct TokenStyle {
+    #[allow(dead_code)]
     pub name: Option<String>,
     pub scope: Vec<String>,
     pub style: Style,
@@ -18,18 +25,41 @@ pub struct TokenStyle {
 #[derive(Debug)]
 pub struct Theme {
     pub name: String,
+    pub path: Option<PathBuf>, // file the theme was read from, None for the built-in one
     pub style: Style,
-    pub token_style: Vec<TokenStyle>,
+    pub ui: Ui,
+    lookup: HashMap<&'static str, HashMap<&'static str, Style>>, // language -> capture -> style, see scope::build_lookup
 }
 
 impl Theme {
-    pub fn get_style(&self, scope: &str) -> Option<Style> {
-        self.token_style.iter().find_map(|ts| {
-            if ts.scope.contains(&scope.to_string()) {
-                return Some(ts.style.clone());
+    /// `colors` holds the editor colors under their VS Code names, see `Ui`.
+    pub fn new(
+        name: String,
+        style: Style,
+        token_style: Vec<TokenStyle>,
+        colors: &HashMap<String, Color>,
+    ) -> Self {
+        Self {
+            name,
+            path: None,
+            ui: Ui::new(colors, &style),
+            style,
+            lookup: build_lookup(&token_style),
+        }
+    }
+
+    /// Style for a tree-sitter capture name in a buffer of `language`.
+    /// Captures the theme has nothing for fall back to their dotted parents,
+    /// `function.method.call` tries `function.method` and then `function`.
+    pub fn get_style(&self, capture: &str, language: &str) -> Option<Style> {
+        let lookup = self.lookup.get(language).unwrap_or(&self.lookup[""]);
+        let mut name = capture;
+        loop {
+            if let Some(style) = lookup.get(name) {
+                return Some(style.clone());
             }
-            return None;
-        })
+            name = name.rsplit_once('.')?.0;
+        }
     }
 }
 
@@ -61,3 +91,105 @@ impl Style {
         }
     }
 }
+
+/// Reads a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` hex code or one of the
+/// 16 terminal color names. The alpha channel is dropped, use
+/// `parse_color_over` for colors that are drawn on top of something.
+pub fn parse_color(value: &str) -> anyhow::Result<Color> {
+    parse_color_over(value, None)
+}
+
+/// Like `parse_color`, but a translucent color is blended with `background`
+/// the way it would look on top of it. Without an rgb background the alpha
+/// is dropped.
+pub fn parse_color_over(value: &str, background: Option<Color>) -> anyhow::Result<Color> {
+    let Some(hex) = value.strip_prefix('#') else {
+        return named_color(value).ok_or_else(|| anyhow::anyhow!("not a valid color: {value}"));
+    };
+    let Some([r, g, b, a]) = parse_hex(hex) else {
+        anyhow::bail!("not a valid hex code: {value}");
+    };
+    let Some(Color::Rgb {
+        r: br,
+        g: bg,
+        b: bb,
+    }) = background
+    else {
+        return Ok(Color::Rgb { r, g, b });
+    };
+    let blend =
+        |fg: u8, bg: u8| ((fg as u32 * a as u32 + bg as u32 * (255 - a as u32)) / 255) as u8;
+    Ok(Color::Rgb {
+        r: blend(r, br),
+        g: blend(g, bg),
+        b: blend(b, bb),
+    })
+}
+
+// rgba channels of a hex code without the '#', the short forms repeat every
+// digit and everything without alpha is opaque.
+fn parse_hex(hex: &str) -> Option<[u8; 4]> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits: Vec<u8> = hex.chars().map(|c| c.to_digit(16).unwrap() as u8).collect();
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.iter().map(|d| d * 17).collect(),
+        6 | 8 => digits.chunks(2).map(|d| d[0] * 16 + d[1]).collect(),
+        _ => return None,
+    };
+    Some([
+        channels[0],
+        channels[1],
+        channels[2],
+        channels.get(3).copied().unwrap_or(255),
+    ])
+}
+
+fn named_color(name: &str) -> Option<Color> {
+    let color = match name.to_lowercase().replace(['-', '_'], "").as_str() {
+        "black" => Color::Black,
+        "red" => Color::DarkRed,
+        "green" => Color::DarkGreen,
+        "yellow" => Color::DarkYellow,
+        "blue" => Color::DarkBlue,
+        "magenta" => Color::DarkMagenta,
+        "cyan" => Color::DarkCyan,
+        "gray" | "grey" | "lightblack" | "darkgray" | "darkgrey" => Color::DarkGrey,
+        "lightgray" | "lightgrey" => Color::Grey,
+        "lightred" => Color::Red,
+        "lightgreen" => Color::Green,
+        "lightyellow" => Color::Yellow,
+        "lightblue" => Color::Blue,
+        "lightmagenta" => Color::Magenta,
+        "lightcyan" => Color::Cyan,
+        "white" => Color::White,
+        "default" | "reset" => Color::Reset,
+        _ => return None,
+    };
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_forms_and_blends_alpha() {
+        let rgb = |r, g, b| Color::Rgb { r, g, b };
+        assert_eq!(parse_color("#fa0").unwrap(), rgb(255, 170, 0));
+        assert_eq!(parse_color("#fa08").unwrap(), rgb(255, 170, 0));
+        assert_eq!(parse_color("#1e1e2e").unwrap(), rgb(30, 30, 46));
+        assert_eq!(parse_color("#1e1e2e80").unwrap(), rgb(30, 30, 46));
+        assert_eq!(parse_color("light-blue").unwrap(), Color::Blue);
+        let white = Some(rgb(255, 255, 255));
+        assert_eq!(
+            parse_color_over("#00000080", white).unwrap(),
+            rgb(127, 127, 127)
+        );
+        assert_eq!(parse_color_over("#000", white).unwrap(), rgb(0, 0, 0));
+        for bad in ["#12", "#12345", "#gggggg", "#1e1e2é", "mauve"] {
+            assert!(parse_color(bad).is_err(), "{bad}");
+        }
+    }
+}
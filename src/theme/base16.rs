@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde_yaml::{Mapping, Value};
+
+use super::_theme::{parse_color, Style, Theme, TokenStyle};
+use super::loader::ThemeLoader;
+
+/// base16 color schemes, sixteen colors in a YAML file. The older format
+/// keeps `base00`..`base0F` at the top, the newer one in a `palette` map.
+pub struct Base16;
+
+// which base color each group of scopes gets, after the base16 styling
+// guidelines.
+#[rustfmt::skip]
+const SCOPES: &[(&str, &[&str])] = &[
+    ("base03", &["comment"]),
+    ("base05", &["keyword.operator", "punctuation", "variable.parameter"]),
+    ("base08", &["variable", "entity.name.tag", "markup.deleted", "markup.link.text"]),
+    ("base09", &["constant", "constant.numeric", "constant.language", "markup.underline.link"]),
+    ("base0A", &["entity.name.type", "support.type", "entity.name.class", "markup.bold"]),
+    ("base0B", &["string", "markup.inserted", "markup.raw"]),
+    ("base0C", &["support", "string.regexp", "constant.character.escape", "markup.quote"]),
+    ("base0D", &["entity.name.function", "support.function", "entity.other.attribute-name", "markup.heading"]),
+    ("base0E", &["keyword", "storage", "markup.italic"]),
+    ("base0F", &["meta.embedded", "punctuation.section.embedded"]),
+];
+
+// editor colors, again after the styling guidelines: base01 for lighter
+// backgrounds, base02 for selections, base03 and base04 for the dim text.
+#[rustfmt::skip]
+const UI_COLORS: &[(&str, &[&str])] = &[
+    ("base00", &["statusBar.normalModeForeground", "statusBar.insertModeForeground", "statusBar.commandModeForeground"]),
+    ("base01", &["statusBar.background", "editor.lineHighlightBackground", "editorWidget.background"]),
+    ("base02", &["editor.selectionBackground", "list.activeSelectionBackground", "editorRuler.foreground"]),
+    ("base03", &["editorLineNumber.foreground", "editorWhitespace.foreground", "editorIndentGuide.background"]),
+    ("base04", &["statusBar.foreground", "editorLineNumber.activeForeground"]),
+    ("base05", &["editorWidget.foreground"]),
+    ("base08", &["editorError.foreground", "editorGutter.deletedBackground"]),
+    ("base0A", &["editorWarning.foreground", "statusBar.commandModeBackground", "editorGutter.modifiedBackground"]),
+    ("base0B", &["statusBar.insertModeBackground", "editorGutter.addedBackground"]),
+    ("base0C", &["editorHint.foreground"]),
+    ("base0D", &["editorInfo.foreground", "statusBar.normalModeBackground", "list.highlightForeground"]),
+];
+
+impl ThemeLoader for Base16 {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["yaml", "yml"]
+    }
+
+    fn load(&self, path: &Path) -> anyhow::Result<Theme> {
+        let scheme: Mapping = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+        parse(&scheme)
+    }
+}
+
+fn parse(scheme: &Mapping) -> anyhow::Result<Theme> {
+    let palette = match scheme.get("palette") {
+        Some(Value::Mapping(palette)) => palette,
+        _ => scheme,
+    };
+    let base = |name: &str| {
+        let Some(value) = palette.get(name).and_then(Value::as_str) else {
+            anyhow::bail!("the scheme has no {name}");
+        };
+        // the hex codes usually come without the '#'.
+        let value = value.trim_start_matches('#');
+        parse_color(&format!("#{value}")).with_context(|| name.to_string())
+    };
+
+    let mut token_style = vec![];
+    for (name, scopes) in SCOPES {
+        token_style.push(TokenStyle {
+            name: None,
+            scope: scopes.iter().map(|s| s.to_string()).collect(),
+            style: Style {
+                fg: Some(base(name)?),
+                ..Default::default()
+            },
+        });
+    }
+
+    let mut colors = HashMap::new();
+    for (name, keys) in UI_COLORS {
+        let color = base(name)?;
+        colors.extend(keys.iter().map(|key| (key.to_string(), color)));
+    }
+
+    let name = ["name", "scheme"]
+        .iter()
+        .find_map(|key| scheme.get(*key).and_then(Value::as_str))
+        .unwrap_or_default();
+    Ok(Theme::new(
+        name.to_string(),
+        Style {
+            fg: Some(base("base05")?),
+            bg: Some(base("base00")?),
+            ..Default::default()
+        },
+        token_style,
+        &colors,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::style::Color;
+
+    fn gray(v: u8) -> Option<Color> {
+        Some(Color::Rgb { r: v, g: v, b: v })
+    }
+
+    #[test]
+    fn maps_the_sixteen_colors() {
+        let scheme: Mapping = serde_yaml::from_str(
+            r##"
+            name: "Grays"
+            palette:
+              base00: "000000"
+              base01: "111111"
+              base02: "222222"
+              base03: "333333"
+              base04: "444444"
+              base05: "555555"
+              base06: "666666"
+              base07: "777777"
+              base08: "888888"
+              base09: "999999"
+              base0A: "aaaaaa"
+              base0B: "bbbbbb"
+              base0C: "cccccc"
+              base0D: "#dddddd"
+              base0E: "eeeeee"
+              base0F: "ffffff"
+            "##,
+        )
+        .unwrap();
+        let theme = parse(&scheme).unwrap();
+        assert_eq!(theme.name, "Grays");
+        assert_eq!((theme.style.fg, theme.style.bg), (gray(0x55), gray(0)));
+        let fg = |capture: &str| theme.get_style(capture, "").unwrap().fg;
+        assert_eq!(fg("comment"), gray(0x33));
+        assert_eq!(fg("function.method"), gray(0xdd));
+        assert_eq!(fg("keyword.storage.type"), gray(0xee));
+        assert_eq!(fg("string"), gray(0xbb));
+        assert_eq!(theme.ui.line_number.fg, gray(0x33));
+        assert_eq!(theme.ui.cursor_line.bg, gray(0x11));
+        assert_eq!(theme.ui.error.fg, gray(0x88));
+
+        // the older format has them at the top, all sixteen are needed.
+        let old: Mapping = serde_yaml::from_str("scheme: Old\nbase00: \"000000\"\n").unwrap();
+        let err = parse(&old).err().unwrap();
+        assert_eq!(err.to_string(), "the scheme has no base03");
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crossterm::style::{Color, ContentStyle};
+
+/// How many colors the terminal can show. Theme colors are rgb, on anything
+/// less than truecolor they are swapped for the closest palette color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+// terminals that do truecolor but don't always say so in COLORTERM.
+const TRUECOLOR_TERMS: &[&str] = &["kitty", "alacritty", "wezterm", "foot", "ghostty"];
+
+// index of the `colors` capability among the terminfo numbers.
+const TERMINFO_COLORS: usize = 13;
+
+// xterm's default rgb values for the 16 basic colors, in the order of their
+// ansi index.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.to_lowercase().as_str() {
+            "truecolor" | "24bit" | "24" => Ok(Self::TrueColor),
+            "256" => Ok(Self::Ansi256),
+            "16" | "8" => Ok(Self::Ansi16),
+            _ => anyhow::bail!("color depth is truecolor, 256 or 16, not {value}"),
+        }
+    }
+
+    /// Works the depth out from COLORTERM, TERM and the terminfo entry of the
+    /// terminal.
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+        let colors = term.as_deref().and_then(terminfo_colors);
+        from_env(colorterm.as_deref(), term.as_deref(), colors)
+    }
+}
+
+fn from_env(colorterm: Option<&str>, term: Option<&str>, colors: Option<u32>) -> ColorDepth {
+    if matches!(colorterm, Some("truecolor" | "24bit")) {
+        return ColorDepth::TrueColor;
+    }
+    let Some(term) = term.filter(|t| !t.is_empty() && *t != "dumb") else {
+        return ColorDepth::Ansi16;
+    };
+    if term.ends_with("-direct") || TRUECOLOR_TERMS.iter().any(|t| term.contains(t)) {
+        return ColorDepth::TrueColor;
+    }
+    match colors {
+        Some(n) if n >= 1 << 24 => ColorDepth::TrueColor,
+        Some(n) if n >= 256 => ColorDepth::Ansi256,
+        Some(_) => ColorDepth::Ansi16,
+        None if term.contains("256color") => ColorDepth::Ansi256,
+        None => ColorDepth::Ansi16,
+    }
+}
+
+// the directories ncurses looks for compiled terminfo entries in.
+fn terminfo_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Ok(list) = std::env::var("TERMINFO_DIRS") {
+        dirs.extend(list.split(':').filter(|d| !d.is_empty()).map(PathBuf::from));
+    }
+    for dir in ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+fn terminfo_colors(term: &str) -> Option<u32> {
+    let first = term.chars().next()?;
+    // entries sit under their first letter, or its hex code on macOS.
+    let subdirs = [first.to_string(), format!("{:x}", first as u32)];
+    terminfo_dirs()
+        .iter()
+        .flat_map(|dir| subdirs.iter().map(move |sub| dir.join(sub).join(term)))
+        .find_map(|path| std::fs::read(path).ok())
+        .and_then(|data| read_colors(&data))
+}
+
+// the `colors` number out of a compiled terminfo entry, see term(5). the
+// header is six little endian shorts: magic, then the sizes of the names,
+// booleans, numbers and strings sections.
+fn read_colors(data: &[u8]) -> Option<u32> {
+    let bytes = |at: usize, len: usize| data.get(at..at + len);
+    let short = |at: usize| Some(u16::from_le_bytes(bytes(at, 2)?.try_into().ok()?));
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    if short(6)? as usize <= TERMINFO_COLORS {
+        return None;
+    }
+    // the numbers start on an even byte.
+    let mut start = 12 + short(2)? as usize + short(4)? as usize;
+    start += start % 2;
+    let at = start + TERMINFO_COLORS * number_size;
+    let colors = match number_size {
+        2 => i16::from_le_bytes(bytes(at, 2)?.try_into().ok()?) as i32,
+        _ => i32::from_le_bytes(bytes(at, 4)?.try_into().ok()?),
+    };
+    u32::try_from(colors).ok()
+}
+
+/// Turns the colors of the styles drawn on screen into ones the terminal can
+/// show, remembering every color it has matched so far.
+#[derive(Debug)]
+pub struct Palette {
+    pub depth: ColorDepth,
+    matched: HashMap<Color, Color>,
+}
+
+impl Palette {
+    pub fn new(depth: ColorDepth) -> Self {
+        Self {
+            depth,
+            matched: HashMap::new(),
+        }
+    }
+
+    pub fn style(&mut self, style: ContentStyle) -> ContentStyle {
+        if self.depth == ColorDepth::TrueColor {
+            return style;
+        }
+        ContentStyle {
+            foreground_color: style.foreground_color.map(|c| self.color(c)),
+            background_color: style.background_color.map(|c| self.color(c)),
+            underline_color: style.underline_color.map(|c| self.color(c)),
+            ..style
+        }
+    }
+
+    pub fn color(&mut self, color: Color) -> Color {
+        let depth = self.depth;
+        *self
+            .matched
+            .entry(color)
+            .or_insert_with(|| downgrade(color, depth))
+    }
+}
+
+fn downgrade(color: Color, depth: ColorDepth) -> Color {
+    let rgb = match color {
+        Color::Rgb { r, g, b } => (r, g, b),
+        Color::AnsiValue(n) if depth == ColorDepth::Ansi16 => ansi256_rgb(n),
+        _ => return color,
+    };
+    match depth {
+        ColorDepth::TrueColor => color,
+        ColorDepth::Ansi256 => {
+            Color::AnsiValue(nearest((16..=255).map(|n| (n, ansi256_rgb(n))), rgb))
+        }
+        ColorDepth::Ansi16 => nearest(ANSI16.iter().copied(), rgb),
+    }
+}
+
+// rgb of one of the 256 colors: the 16 basic ones, a 6x6x6 cube and a ramp of
+// 24 grays.
+fn ansi256_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI16[n as usize].1,
+        16..=231 => {
+            let i = n - 16;
+            let level = |v: u8| CUBE_LEVELS[v as usize];
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+// the candidate that looks closest to rgb, compared in CIELAB where distances
+// follow what the eye sees far better than they do in rgb.
+fn nearest<T>(candidates: impl Iterator<Item = (T, (u8, u8, u8))>, rgb: (u8, u8, u8)) -> T {
+    let target = lab(rgb);
+    candidates
+        .map(|(c, rgb)| {
+            let (l, a, b) = lab(rgb);
+            let distance = (l - target.0).powi(2) + (a - target.1).powi(2) + (b - target.2).powi(2);
+            (c, distance)
+        })
+        .min_by(|x, y| x.1.total_cmp(&y.1))
+        .map(|(c, _)| c)
+        .expect("there is always a candidate")
+}
+
+// sRGB to CIELAB under a D65 white point.
+fn lab((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_depth_from_the_environment() {
+        assert_eq!(
+            from_env(Some("truecolor"), Some("screen"), Some(8)),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            from_env(None, Some("xterm-kitty"), None),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            from_env(None, Some("tmux-256color"), None),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            from_env(None, Some("xterm"), Some(256)),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(from_env(None, Some("linux"), Some(8)), ColorDepth::Ansi16);
+        assert_eq!(from_env(None, None, None), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn reads_colors_from_terminfo() {
+        // legacy format: header, "x\0" for the names, no booleans and 14
+        // numbers of which only colors is set.
+        let mut data = vec![];
+        for short in [0o432u16, 2, 0, 14, 0, 0] {
+            data.extend(short.to_le_bytes());
+        }
+        data.extend(b"x\0");
+        for n in 0..14 {
+            let value: i16 = if n == TERMINFO_COLORS { 256 } else { -1 };
+            data.extend(value.to_le_bytes());
+        }
+        assert_eq!(read_colors(&data), Some(256));
+        assert_eq!(read_colors(&data[..20]), None);
+    }
+
+    #[test]
+    fn matches_the_closest_palette_color() {
+        let mut palette = Palette::new(ColorDepth::Ansi256);
+        let rgb = |r, g, b| Color::Rgb { r, g, b };
+        assert_eq!(palette.color(rgb(255, 0, 0)), Color::AnsiValue(196));
+        assert_eq!(palette.color(rgb(30, 30, 30)), Color::AnsiValue(234));
+        assert_eq!(palette.color(Color::Blue), Color::Blue);
+
+        let mut palette = Palette::new(ColorDepth::Ansi16);
+        assert_eq!(palette.color(rgb(239, 241, 245)), Color::Grey);
+        assert_eq!(palette.color(rgb(210, 15, 57)), Color::DarkRed);
+        assert_eq!(palette.color(Color::AnsiValue(196)), Color::Red);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use toml::{Table, Value};
+
+use super::_theme::{parse_color, Style, Theme, TokenStyle};
+use super::loader::{find_theme, ThemeLoader};
+use crossterm::style::Color;
+
+/// Helix themes, TOML files that map tree-sitter scopes to styles and can
+/// name their colors in a `[palette]` table.
+pub struct Helix;
+
+// a theme that inherits a theme that inherits... stops here.
+const MAX_INHERITS: usize = 8;
+
+// the ui scopes and the VS Code color names their fg and bg end up under,
+// an empty name drops that half. later rows win over earlier ones.
+#[rustfmt::skip]
+const UI_COLORS: &[(&str, &str, &str)] = &[
+    ("ui.statusline", "statusBar.foreground", "statusBar.background"),
+    ("ui.statusline.normal", "statusBar.normalModeForeground", "statusBar.normalModeBackground"),
+    ("ui.statusline.insert", "statusBar.insertModeForeground", "statusBar.insertModeBackground"),
+    ("ui.linenr", "editorLineNumber.foreground", "editorGutter.background"),
+    ("ui.linenr.selected", "editorLineNumber.activeForeground", ""),
+    ("ui.cursorline", "", "editor.lineHighlightBackground"),
+    ("ui.cursorline.primary", "", "editor.lineHighlightBackground"),
+    ("ui.selection", "editor.selectionForeground", "editor.selectionBackground"),
+    ("ui.virtual.whitespace", "editorWhitespace.foreground", ""),
+    ("ui.virtual.indent-guide", "editorIndentGuide.background", ""),
+    ("ui.virtual.ruler", "", "editorRuler.foreground"),
+    ("ui.popup", "editorWidget.foreground", "editorWidget.background"),
+    ("ui.menu", "editorSuggestWidget.foreground", "editorSuggestWidget.background"),
+    ("ui.menu.selected", "list.activeSelectionForeground", "list.activeSelectionBackground"),
+    ("special", "list.highlightForeground", ""),
+    ("error", "editorError.foreground", ""),
+    ("warning", "editorWarning.foreground", ""),
+    ("info", "editorInfo.foreground", ""),
+    ("hint", "editorHint.foreground", ""),
+    ("diff.plus", "editorGutter.addedBackground", ""),
+    ("diff.delta", "editorGutter.modifiedBackground", ""),
+    ("diff.minus", "editorGutter.deletedBackground", ""),
+];
+
+impl ThemeLoader for Helix {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["toml"]
+    }
+
+    fn load(&self, path: &Path) -> anyhow::Result<Theme> {
+        let table = read_table(path, 0)?;
+        parse(&table)
+    }
+}
+
+// the theme's table with everything it inherits merged in underneath it.
+fn read_table(path: &Path, depth: usize) -> anyhow::Result<Table> {
+    let mut table: Table = std::fs::read_to_string(path)?.parse()?;
+    let Some(parent) = table.remove("inherits") else {
+        return Ok(table);
+    };
+    let Some(parent) = parent.as_str() else {
+        anyhow::bail!("inherits has to be the name of a theme");
+    };
+    if depth >= MAX_INHERITS {
+        anyhow::bail!("too many inherited themes, stopped at {parent}");
+    }
+    let mut merged = read_table(&find_theme(parent)?, depth + 1)?;
+    let palette = table.remove("palette");
+    merged.extend(table);
+    if let Some(Value::Table(palette)) = palette {
+        match merged.get_mut("palette") {
+            Some(Value::Table(base)) => base.extend(palette),
+            _ => {
+                merged.insert("palette".into(), Value::Table(palette));
+            }
+        }
+    }
+    Ok(merged)
+}
+
+fn parse(table: &Table) -> anyhow::Result<Theme> {
+    let empty = Table::new();
+    let palette = match table.get("palette") {
+        Some(Value::Table(palette)) => palette,
+        _ => &empty,
+    };
+    let style_of = |key: &str| {
+        let style = table.get(key).map(|v| style(palette, v)).transpose();
+        style.with_context(|| key.to_string())
+    };
+
+    let mut token_style = vec![];
+    for (key, value) in table {
+        // ui styles belong to the editor itself, not to the text.
+        if key == "palette" || key.starts_with("ui.") || key.starts_with("diagnostic") {
+            continue;
+        }
+        token_style.push(TokenStyle {
+            name: None,
+            scope: vec![key.clone()],
+            style: style(palette, value).with_context(|| key.clone())?,
+        });
+    }
+
+    let mut colors = HashMap::new();
+    for (key, fg, bg) in UI_COLORS {
+        let Some(style) = style_of(key)? else {
+            continue;
+        };
+        for (name, color) in [(fg, style.fg), (bg, style.bg)] {
+            if let (false, Some(color)) = (name.is_empty(), color) {
+                colors.insert(name.to_string(), color);
+            }
+        }
+    }
+
+    let background = style_of("ui.background")?.and_then(|s| s.bg);
+    let text = style_of("ui.text")?.and_then(|s| s.fg);
+    Ok(Theme::new(
+        String::new(),
+        Style {
+            fg: Some(text.unwrap_or(Color::Reset)),
+            bg: Some(background.unwrap_or(Color::Reset)),
+            ..Default::default()
+        },
+        token_style,
+        &colors,
+    ))
+}
+
+fn color(palette: &Table, name: &str) -> anyhow::Result<Color> {
+    match palette.get(name).and_then(Value::as_str) {
+        Some(value) => parse_color(value),
+        None => parse_color(name),
+    }
+}
+
+// either just a foreground color or a table with fg, bg and modifiers.
+fn style(palette: &Table, value: &Value) -> anyhow::Result<Style> {
+    let mut style = Style::default();
+    match value {
+        Value::String(fg) => style.fg = Some(color(palette, fg)?),
+        Value::Table(table) => {
+            let get = |key| table.get(key).and_then(Value::as_str);
+            style.fg = get("fg").map(|c| color(palette, c)).transpose()?;
+            style.bg = get("bg").map(|c| color(palette, c)).transpose()?;
+            let modifiers = table.get("modifiers").and_then(Value::as_array);
+            for modifier in modifiers.into_iter().flatten() {
+                match modifier.as_str() {
+                    Some("bold") => style.bold = true,
+                    Some("italic") => style.italic = true,
+                    _ => {}
+                }
+            }
+        }
+        _ => anyhow::bail!("a style is a color or a table, got {value}"),
+    }
+    Ok(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_palette_and_modifiers() {
+        let table: Table = r##"
+            "ui.background" = { bg = "base" }
+            "keyword" = "mauve"
+            "ui.linenr.selected" = { fg = "mauve" }
+            "function.macro" = { fg = "#ff0000", modifiers = ["italic"] }
+            [palette]
+            base = "#1e1e2e"
+            mauve = "#cba6f7"
+        "##
+        .parse()
+        .unwrap();
+        let theme = parse(&table).unwrap();
+        assert_eq!(
+            theme.style.bg,
+            Some(Color::Rgb {
+                r: 30,
+                g: 30,
+                b: 46
+            })
+        );
+        let keyword = theme.get_style("keyword.control", "").unwrap();
+        assert_eq!(
+            keyword.fg,
+            Some(Color::Rgb {
+                r: 203,
+                g: 166,
+                b: 247
+            })
+        );
+        assert!(theme.get_style("function.macro", "").unwrap().italic);
+        assert_eq!(theme.ui.line_number_active.fg, keyword.fg);
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use super::_theme::Theme;
+use super::base16::Base16;
+use super::helix::Helix;
+use super::tmtheme::TmTheme;
+use super::vscode::{self, VsCode};
+
+/// Reads one theme file format into a `Theme`.
+pub trait ThemeLoader: Sync {
+    /// File extensions of the themes this loader reads, lowercase.
+    fn extensions(&self) -> &'static [&'static str];
+    fn load(&self, path: &Path) -> anyhow::Result<Theme>;
+}
+
+/// Theme used when none is asked for. It is built in as well, so there is
+/// always a theme to fall back to.
+pub const DEFAULT_THEME: &str = "latte";
+const DEFAULT_THEME_JSON: &str = include_str!("../../themes/latte.json");
+
+const LOADERS: &[&dyn ThemeLoader] = &[&VsCode, &Helix, &Base16, &TmTheme];
+
+fn loader_for(path: &Path) -> Option<&'static dyn ThemeLoader> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    LOADERS
+        .iter()
+        .copied()
+        .find(|l| l.extensions().contains(&ext.as_str()))
+}
+
+/// Where themes are looked up by name, the user's own directory first.
+pub fn theme_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(config) = config {
+        dirs.push(config.join("rusted").join("themes"));
+    }
+    dirs.push(PathBuf::from("themes"));
+    dirs
+}
+
+// every file in the theme directories that some loader can read.
+fn theme_files() -> impl Iterator<Item = PathBuf> {
+    theme_dirs()
+        .into_iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten().map(|e| e.path()))
+        .filter(|path| path.is_file() && loader_for(path).is_some())
+}
+
+fn theme_name(path: &Path) -> Option<String> {
+    Some(path.file_stem()?.to_str()?.to_string())
+}
+
+/// Names of all the themes in the theme directories, sorted.
+pub fn available_themes() -> Vec<String> {
+    let mut names: Vec<String> = theme_files().filter_map(|p| theme_name(&p)).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Path of the theme called `name`, which can also be a path to a theme
+/// file.
+pub fn find_theme(name: &str) -> anyhow::Result<PathBuf> {
+    let path = Path::new(name);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    theme_files()
+        .find(|p| theme_name(p).as_deref() == Some(name))
+        .ok_or_else(|| anyhow::anyhow!("theme not found: {name}"))
+}
+
+pub fn load_theme(path: &Path) -> anyhow::Result<Theme> {
+    let Some(loader) = loader_for(path) else {
+        anyhow::bail!("unknown theme format: {}", path.display());
+    };
+    let mut theme = loader
+        .load(path)
+        .with_context(|| path.display().to_string())?;
+    if theme.name.is_empty() {
+        theme.name = theme_name(path).unwrap_or_default();
+    }
+    theme.path = Some(path.to_path_buf());
+    Ok(theme)
+}
+
+/// Finds the theme called `name` and loads it.
+pub fn load_named(name: &str) -> anyhow::Result<Theme> {
+    match find_theme(name) {
+        Ok(path) => load_theme(&path),
+        Err(_) if name == DEFAULT_THEME => Ok(default_theme()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn default_theme() -> Theme {
+    vscode::parse_str(DEFAULT_THEME_JSON).expect("the built-in theme is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_theme_parses() {
+        assert_eq!(default_theme().name, "Catppuccin Latte");
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+
+use super::_theme::{Style, TokenStyle};
+use crate::language::_language::registry;
+
+/// TextMate scopes that stand for each tree-sitter capture name, the way
+/// VS Code grammars would tag the same token. The capture name itself is
+/// tried as well, for themes written against tree-sitter names.
+#[rustfmt::skip]
+const CAPTURES: &[(&str, &[&str])] = &[
+    ("attribute", &["entity.other.attribute-name", "meta.attribute", "meta.annotation"]),
+    ("boolean", &["constant.language.boolean", "constant.language"]),
+    ("character", &["constant.character"]),
+    ("comment", &["comment"]),
+    ("comment.documentation", &["comment.block.documentation", "comment.line.documentation", "comment"]),
+    ("constant", &["variable.other.constant", "constant.other", "constant"]),
+    ("constant.builtin", &["constant.language", "support.constant"]),
+    ("constant.character", &["constant.character"]),
+    ("constant.character.escape", &["constant.character.escape"]),
+    ("constant.numeric", &["constant.numeric"]),
+    ("constructor", &["entity.name.function.constructor", "meta.function-call.constructor", "entity.name.type.class"]),
+    ("delimiter", &["punctuation.separator", "punctuation.terminator"]),
+    ("embedded", &["meta.embedded", "punctuation.section.embedded"]),
+    ("escape", &["constant.character.escape"]),
+    ("float", &["constant.numeric.float", "constant.numeric"]),
+    ("function", &["entity.name.function", "support.function"]),
+    ("function.builtin", &["support.function.builtin", "support.function"]),
+    ("function.call", &["entity.name.function.call", "entity.name.function"]),
+    ("function.macro", &["entity.name.function.macro", "support.function.macro", "entity.name.macro"]),
+    ("function.method", &["entity.name.function.member", "entity.name.function.method", "entity.name.function"]),
+    ("function.special", &["entity.name.function.preprocessor", "entity.name.function.macro"]),
+    ("keyword", &["keyword"]),
+    ("keyword.control", &["keyword.control"]),
+    ("keyword.control.conditional", &["keyword.control.conditional", "keyword.control"]),
+    ("keyword.control.import", &["keyword.control.import", "keyword.control"]),
+    ("keyword.control.repeat", &["keyword.control.loop", "keyword.control"]),
+    ("keyword.control.return", &["keyword.control.return", "keyword.control"]),
+    ("keyword.function", &["storage.type.function", "keyword.other.fn", "storage.type"]),
+    ("keyword.operator", &["keyword.operator.word", "keyword.operator"]),
+    ("keyword.return", &["keyword.control.return", "keyword.control"]),
+    ("keyword.storage", &["storage.type", "storage"]),
+    ("keyword.storage.modifier", &["storage.modifier"]),
+    ("keyword.storage.type", &["storage.type"]),
+    ("label", &["entity.name.label", "punctuation.definition.label"]),
+    ("markup.bold", &["markup.bold"]),
+    ("markup.heading", &["markup.heading", "entity.name.section"]),
+    ("markup.italic", &["markup.italic"]),
+    ("markup.link", &["markup.link", "string.other.link"]),
+    ("markup.link.url", &["markup.underline.link"]),
+    ("markup.list", &["markup.list", "punctuation.definition.list"]),
+    ("markup.quote", &["markup.quote"]),
+    ("markup.raw", &["markup.inline.raw", "markup.raw", "markup.fenced_code"]),
+    ("module", &["entity.name.namespace", "entity.name.module", "entity.name.type.module"]),
+    ("namespace", &["entity.name.namespace", "entity.name.module", "entity.name.type.module"]),
+    ("number", &["constant.numeric"]),
+    ("operator", &["keyword.operator"]),
+    ("property", &["variable.other.property", "variable.other.object.property", "support.type.property-name", "meta.property-name"]),
+    ("punctuation", &["punctuation"]),
+    ("punctuation.bracket", &["punctuation.section.brackets", "punctuation.brackets", "punctuation.definition.block"]),
+    ("punctuation.delimiter", &["punctuation.separator", "punctuation.terminator", "punctuation.accessor"]),
+    ("punctuation.special", &["punctuation.definition", "punctuation.special"]),
+    ("string", &["string.quoted", "string"]),
+    ("string.escape", &["constant.character.escape"]),
+    ("string.regex", &["string.regexp"]),
+    ("string.regexp", &["string.regexp"]),
+    ("string.special", &["string.other", "string"]),
+    ("string.special.key", &["support.type.property-name", "entity.name.tag.yaml", "string"]),
+    ("string.special.symbol", &["constant.other.symbol"]),
+    ("tag", &["entity.name.tag"]),
+    ("text.emphasis", &["markup.italic"]),
+    ("text.literal", &["markup.inline.raw", "markup.raw", "markup.fenced_code"]),
+    ("text.reference", &["markup.link", "string.other.link"]),
+    ("text.strong", &["markup.bold"]),
+    ("text.title", &["markup.heading", "entity.name.section"]),
+    ("text.uri", &["markup.underline.link"]),
+    ("type", &["entity.name.type", "support.type", "storage.type"]),
+    ("type.builtin", &["support.type.primitive", "support.type.builtin", "storage.type.primitive", "support.type"]),
+    ("variable", &["variable.other.readwrite", "variable.other", "variable"]),
+    ("variable.builtin", &["variable.language.self", "variable.language"]),
+    ("variable.member", &["variable.other.member", "variable.other.property"]),
+    ("variable.parameter", &["variable.parameter"]),
+];
+
+/// How specific `selector` is for `scope`, counted in dotted parts, or None
+/// when it doesn't match. A selector matches its own scope and every scope
+/// below it, so `entity.name` matches `entity.name.function.rust`.
+pub fn selector_score(selector: &str, scope: &str) -> Option<usize> {
+    let selector = selector.trim();
+    // descendant selectors need the parent scopes, which a capture doesn't
+    // have. exclusions go the same way.
+    if selector.is_empty() || selector.contains(char::is_whitespace) {
+        return None;
+    }
+    let matches = scope == selector
+        || (scope.starts_with(selector) && scope.as_bytes().get(selector.len()) == Some(&b'.'));
+    matches.then(|| selector.split('.').count())
+}
+
+// the last rule of the theme each selector is in. a scope is matched by its
+// dotted prefixes, so looking those up finds every rule for it.
+fn selector_rules(token_style: &[TokenStyle]) -> HashMap<&str, usize> {
+    let mut rules = HashMap::new();
+    for (i, rule) in token_style.iter().enumerate() {
+        for selector in rule.scope.iter().flat_map(|s| s.split(',')) {
+            rules.insert(selector.trim(), i);
+        }
+    }
+    rules
+}
+
+// the rule that matches one of the scopes most specifically, with one of
+// the language `suffixes` after it or without. a rule written for the
+// language beats the others, ties go to the scope listed first and then to
+// the rule that comes last in the theme, as in VS Code.
+fn best_style<'a>(
+    token_style: &'a [TokenStyle],
+    rules: &HashMap<&str, usize>,
+    scopes: &[&str],
+    suffixes: &[&str],
+) -> Option<&'a Style> {
+    let mut best = None;
+    for (rank, scope) in scopes.iter().enumerate() {
+        let candidates = suffixes
+            .iter()
+            .map(|suffix| format!("{scope}.{suffix}"))
+            .chain(std::iter::once(scope.to_string()));
+        for candidate in candidates {
+            let prefixes = candidate
+                .match_indices('.')
+                .map(|(at, _)| &candidate[..at])
+                .chain(std::iter::once(candidate.as_str()));
+            for prefix in prefixes {
+                let Some((selector, &i)) = rules.get_key_value(prefix) else {
+                    continue;
+                };
+                if let Some(score) = selector_score(selector, &candidate) {
+                    let language = selector.len() > scope.len();
+                    let key = (language, score, std::cmp::Reverse(rank), i);
+                    if best.as_ref().is_none_or(|(k, _)| key > *k) {
+                        best = Some((key, i));
+                    }
+                }
+            }
+        }
+    }
+    best.map(|(_, i)| &token_style[i].style)
+}
+
+fn capture_styles(
+    token_style: &[TokenStyle],
+    rules: &HashMap<&str, usize>,
+    suffixes: &[&str],
+) -> HashMap<&'static str, Style> {
+    CAPTURES
+        .iter()
+        .filter_map(|(capture, scopes)| {
+            let scopes: Vec<&str> = std::iter::once(*capture)
+                .chain(scopes.iter().copied())
+                .collect();
+            let style = best_style(token_style, rules, &scopes, suffixes)?;
+            Some((*capture, style.clone()))
+        })
+        .collect()
+}
+
+/// Style of every known capture name under the theme, so highlighting is a
+/// hash lookup instead of a scan over all the rules. TextMate scopes end in
+/// the language, like `storage.type.rust`, so the captures of each language
+/// the theme has such rules for get their own table, under the language's
+/// name. The table under "" is for every other language.
+pub fn build_lookup(
+    token_style: &[TokenStyle],
+) -> HashMap<&'static str, HashMap<&'static str, Style>> {
+    let rules = selector_rules(token_style);
+    let last_parts: HashSet<&str> = rules
+        .keys()
+        .filter_map(|selector| selector.rsplit('.').next())
+        .collect();
+    let mut lookup = HashMap::from([("", capture_styles(token_style, &rules, &[]))]);
+    for language in registry().languages() {
+        // the name and the aliases are what grammars end their scopes in,
+        // `.rust`, `.js` or `.shell`.
+        let suffixes: Vec<&str> = std::iter::once(language.name)
+            .chain(language.aliases.iter().copied())
+            .filter(|suffix| last_parts.contains(suffix))
+            .collect();
+        if !suffixes.is_empty() {
+            lookup.insert(
+                language.name,
+                capture_styles(token_style, &rules, &suffixes),
+            );
+        }
+    }
+    lookup
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::style::Color;
+
+    fn rule(scope: &str, color: Color) -> TokenStyle {
+        TokenStyle {
+            name: None,
+            scope: vec![scope.to_string()],
+            style: Style {
+                fg: Some(color),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        assert_eq!(
+            selector_score("entity.name", "entity.name.function.rust"),
+            Some(2)
+        );
+        assert_eq!(selector_score("entity.nam", "entity.name"), None);
+        assert_eq!(selector_score("meta.fn entity.name", "entity.name"), None);
+
+        let rules = [
+            rule("keyword", Color::Red),
+            rule("entity.name.function", Color::Blue),
+            rule("keyword.control, storage.type", Color::Green),
+            rule("entity.name.function.macro", Color::Yellow),
+        ];
+        let lookup = build_lookup(&rules);
+        let fg = |capture: &str| lookup[""].get(capture).and_then(|s| s.fg);
+        assert_eq!(fg("keyword"), Some(Color::Red));
+        assert_eq!(fg("keyword.control.repeat"), Some(Color::Green));
+        assert_eq!(fg("keyword.function"), Some(Color::Green));
+        assert_eq!(fg("function.method"), Some(Color::Blue));
+        assert_eq!(fg("function.macro"), Some(Color::Yellow));
+        assert_eq!(fg("string"), None);
+    }
+}
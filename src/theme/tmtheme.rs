@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use plist::{Dictionary, Value};
+
+use super::_theme::{parse_color_over, Style, Theme, TokenStyle};
+use super::loader::ThemeLoader;
+use crossterm::style::Color;
+
+/// TextMate / Sublime `.tmTheme` files, a plist with a list of settings.
+/// The entry without a scope holds the editor colors.
+pub struct TmTheme;
+
+// the editor entry's own keys and the VS Code color names they stand for.
+#[rustfmt::skip]
+const UI_COLORS: &[(&str, &str)] = &[
+    ("lineHighlight", "editor.lineHighlightBackground"),
+    ("selection", "editor.selectionBackground"),
+    ("selectionForeground", "editor.selectionForeground"),
+    ("invisibles", "editorWhitespace.foreground"),
+    ("guide", "editorIndentGuide.background"),
+    ("gutter", "editorGutter.background"),
+    ("gutterForeground", "editorLineNumber.foreground"),
+];
+
+impl ThemeLoader for TmTheme {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tmtheme"]
+    }
+
+    fn load(&self, path: &Path) -> anyhow::Result<Theme> {
+        let value = Value::from_file(path)?;
+        let Some(dict) = value.as_dictionary() else {
+            anyhow::bail!("a tmTheme has a dictionary at the top");
+        };
+        parse(dict)
+    }
+}
+
+fn parse(dict: &Dictionary) -> anyhow::Result<Theme> {
+    let entries = dict
+        .get("settings")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("the theme has no settings"))?;
+
+    let entries: Vec<&Dictionary> = entries.iter().filter_map(Value::as_dictionary).collect();
+
+    // translucent colors are blended with the editor background, so that
+    // has to be known before anything else.
+    let editor_settings = entries
+        .iter()
+        .find(|entry| !entry.contains_key("scope"))
+        .and_then(|entry| settings_of(entry));
+    let background = match editor_settings {
+        Some(settings) => color(settings, "background", None)?,
+        None => None,
+    };
+
+    let mut editor = Style::default();
+    let mut colors = HashMap::new();
+    let mut token_style = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        let Some(settings) = settings_of(entry) else {
+            continue;
+        };
+        let style = style(settings, background).with_context(|| format!("settings[{i}]"))?;
+        match entry.get("scope").and_then(Value::as_string) {
+            Some(scope) => token_style.push(TokenStyle {
+                name: entry
+                    .get("name")
+                    .and_then(Value::as_string)
+                    .map(String::from),
+                scope: scope.split(',').map(|s| s.trim().to_string()).collect(),
+                style,
+            }),
+            None => {
+                editor = style;
+                for (key, name) in UI_COLORS {
+                    if let Some(color) = color(settings, key, background)? {
+                        colors.insert(name.to_string(), color);
+                    }
+                }
+            }
+        }
+    }
+
+    let name = dict
+        .get("name")
+        .and_then(Value::as_string)
+        .unwrap_or_default();
+    Ok(Theme::new(
+        name.to_string(),
+        Style {
+            fg: Some(editor.fg.unwrap_or(Color::Reset)),
+            bg: Some(editor.bg.unwrap_or(Color::Reset)),
+            ..Default::default()
+        },
+        token_style,
+        &colors,
+    ))
+}
+
+fn settings_of(entry: &Dictionary) -> Option<&Dictionary> {
+    entry.get("settings").and_then(Value::as_dictionary)
+}
+
+fn color(
+    settings: &Dictionary,
+    key: &str,
+    background: Option<Color>,
+) -> anyhow::Result<Option<Color>> {
+    let Some(value) = settings.get(key).and_then(Value::as_string) else {
+        return Ok(None);
+    };
+    let color = parse_color_over(value, background).with_context(|| key.to_string())?;
+    Ok(Some(color))
+}
+
+fn style(settings: &Dictionary, background: Option<Color>) -> anyhow::Result<Style> {
+    let font_style = settings
+        .get("fontStyle")
+        .and_then(Value::as_string)
+        .unwrap_or_default();
+    Ok(Style {
+        fg: color(settings, "foreground", background)?,
+        bg: color(settings, "background", background)?,
+        bold: font_style.contains("bold"),
+        italic: font_style.contains("italic"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_editor_colors_and_scopes() {
+        let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+  <key>name</key>
+  <string>Small</string>
+  <key>settings</key>
+  <array>
+    <dict>
+      <key>settings</key>
+      <dict>
+        <key>background</key>
+        <string>#000000</string>
+        <key>foreground</key>
+        <string>#cccccc</string>
+        <key>lineHighlight</key>
+        <string>#ffffff33</string>
+        <key>gutterForeground</key>
+        <string>#808080</string>
+      </dict>
+    </dict>
+    <dict>
+      <key>name</key>
+      <string>Keywords</string>
+      <key>scope</key>
+      <string>keyword, storage</string>
+      <key>settings</key>
+      <dict>
+        <key>foreground</key>
+        <string>#ff0000</string>
+        <key>fontStyle</key>
+        <string>bold italic</string>
+      </dict>
+    </dict>
+  </array>
+</dict>
+</plist>"##;
+        let value = Value::from_reader_xml(xml.as_bytes()).unwrap();
+        let theme = parse(value.as_dictionary().unwrap()).unwrap();
+        assert_eq!(theme.name, "Small");
+        assert_eq!(theme.style.bg, Some(Color::Rgb { r: 0, g: 0, b: 0 }));
+        let red = Some(Color::Rgb { r: 255, g: 0, b: 0 });
+        let keyword = theme.get_style("keyword.control", "").unwrap();
+        assert_eq!(keyword.fg, red);
+        assert!(keyword.bold && keyword.italic);
+        assert_eq!(
+            theme.get_style("keyword.storage.modifier", "").unwrap().fg,
+            red
+        );
+        assert!(theme.get_style("string", "").is_none());
+
+        // the line highlight is blended over the background.
+        let gray = |v| Some(Color::Rgb { r: v, g: v, b: v });
+        assert_eq!(theme.ui.cursor_line.bg, gray(0x33));
+        assert_eq!(theme.ui.line_number.fg, gray(0x80));
+    }
+}
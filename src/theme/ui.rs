@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use crossterm::style::Color;
+
+use super::_theme::Style;
+
+/// Styles for everything the editor draws around the text. They come from
+/// the theme's `colors` map, which uses the VS Code color names; the other
+/// loaders translate their own names to those. Whatever the theme leaves out
+/// is derived from the editor foreground and background.
+#[derive(Debug, Clone, Default)]
+pub struct Ui {
+    pub statusline: Style,
+    pub statusline_normal: Style,
+    pub statusline_insert: Style,
+    pub statusline_command: Style,
+    pub line_number: Style,
+    pub line_number_active: Style,
+    pub cursor_line: Style,
+    #[allow(dead_code)] // nothing selects text yet
+    pub selection: Style,
+    pub whitespace: Style,
+    #[allow(dead_code)] // indent guides aren't drawn yet
+    pub indent_guide: Style,
+    pub ruler: Style,
+    pub popup: Style,
+    pub popup_selected: Style,
+    pub popup_match: Style, // the chars a search matched, in pickers
+    pub error: Style,
+    pub warning: Style,
+    pub info: Style,
+    pub hint: Style,
+    pub added: Style,
+    pub changed: Style,
+    pub removed: Style,
+}
+
+// rusted's own keys for the mode part of the statusline come first, VS Code
+// has nothing like it so its themes get one of the terminal colors.
+const NORMAL: [&str; 3] = [
+    "statusBar.normalModeBackground",
+    "terminal.ansiBlue",
+    "focusBorder",
+];
+const INSERT: [&str; 2] = ["statusBar.insertModeBackground", "terminal.ansiGreen"];
+const COMMAND: [&str; 2] = ["statusBar.commandModeBackground", "terminal.ansiYellow"];
+
+impl Ui {
+    pub fn new(colors: &HashMap<String, Color>, text: &Style) -> Self {
+        let get = |keys: &[&str]| keys.iter().find_map(|key| colors.get(*key).copied());
+        let style = |fg, bg| Style {
+            fg,
+            bg,
+            ..Default::default()
+        };
+        let (fg, bg) = (text.fg, text.bg);
+
+        // without any statusline colors it is the text in reverse.
+        let statusline = match (
+            get(&["statusBar.foreground"]),
+            get(&["statusBar.background"]),
+        ) {
+            (None, None) => style(bg, fg),
+            (f, b) => style(f.or(fg), b.or(bg)),
+        };
+        let normal = get(&NORMAL).or(fg);
+        let mode = |keys: &[&str], foreground: &str| {
+            style(get(&[foreground]).or(bg), get(keys).or(normal))
+        };
+
+        let gutter = get(&["editorGutter.background"]).or(bg);
+        let line_number = style(get(&["editorLineNumber.foreground"]).or(fg), gutter);
+        let line_number_active = style(get(&["editorLineNumber.activeForeground"]).or(fg), gutter);
+        let whitespace = style(
+            get(&["editorWhitespace.foreground"]).or(line_number.fg),
+            None,
+        );
+        let selection = match get(&["editor.selectionBackground", "selection.background"]) {
+            Some(selection) => style(get(&["editor.selectionForeground"]), Some(selection)),
+            None => style(bg, fg),
+        };
+        let popup = style(
+            get(&["editorSuggestWidget.foreground", "editorWidget.foreground"]).or(fg),
+            get(&["editorSuggestWidget.background", "editorWidget.background"]).or(statusline.bg),
+        );
+
+        Self {
+            statusline_normal: mode(&NORMAL, "statusBar.normalModeForeground"),
+            statusline_insert: mode(&INSERT, "statusBar.insertModeForeground"),
+            statusline_command: mode(&COMMAND, "statusBar.commandModeForeground"),
+            cursor_line: style(None, get(&["editor.lineHighlightBackground"]).or(bg)),
+            indent_guide: style(
+                get(&[
+                    "editorIndentGuide.background1",
+                    "editorIndentGuide.background",
+                ])
+                .or(whitespace.fg),
+                None,
+            ),
+            ruler: style(None, get(&["editorRuler.foreground"]).or(statusline.bg)),
+            popup_selected: style(
+                get(&[
+                    "editorSuggestWidget.selectedForeground",
+                    "list.activeSelectionForeground",
+                ])
+                .or(popup.fg),
+                get(&[
+                    "editorSuggestWidget.selectedBackground",
+                    "list.activeSelectionBackground",
+                ])
+                .or(selection.bg),
+            ),
+            popup_match: style(
+                get(&[
+                    "editorSuggestWidget.highlightForeground",
+                    "list.highlightForeground",
+                ])
+                .or(get(&["editorInfo.foreground"]))
+                .or(Some(Color::Blue)),
+                None,
+            ),
+            error: style(
+                get(&["editorError.foreground", "errorForeground"]).or(Some(Color::Red)),
+                None,
+            ),
+            warning: style(
+                get(&["editorWarning.foreground"]).or(Some(Color::Yellow)),
+                None,
+            ),
+            info: style(get(&["editorInfo.foreground"]).or(Some(Color::Blue)), None),
+            hint: style(get(&["editorHint.foreground"]).or(Some(Color::Cyan)), None),
+            added: style(
+                get(&["editorGutter.addedBackground"]).or(Some(Color::Green)),
+                None,
+            ),
+            changed: style(
+                get(&["editorGutter.modifiedBackground"]).or(Some(Color::Yellow)),
+                None,
+            ),
+            removed: style(
+                get(&["editorGutter.deletedBackground"]).or(Some(Color::Red)),
+                None,
+            ),
+            statusline,
+            line_number,
+            line_number_active,
+            selection,
+            whitespace,
+            popup,
+        }
+    }
+
+    /// The styles signs can take their color from, by the name `:sign
+    /// define` uses for them.
+    pub fn by_name(&self, name: &str) -> Option<&Style> {
+        let style = match name {
+            "error" => &self.error,
+            "warning" => &self.warning,
+            "info" => &self.info,
+            "hint" => &self.hint,
+            "added" => &self.added,
+            "changed" => &self.changed,
+            "removed" => &self.removed,
+            _ => return None,
+        };
+        Some(style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_text_colors() {
+        let text = Style {
+            fg: Some(Color::Black),
+            bg: Some(Color::White),
+            ..Default::default()
+        };
+        let colors = HashMap::from([
+            ("editorLineNumber.foreground".to_string(), Color::Grey),
+            ("terminal.ansiGreen".to_string(), Color::Green),
+        ]);
+        let ui = Ui::new(&colors, &text);
+        assert_eq!(ui.line_number.fg, Some(Color::Grey));
+        assert_eq!(ui.line_number_active.fg, Some(Color::Black));
+        assert_eq!(ui.statusline.bg, Some(Color::Black));
+        assert_eq!(ui.statusline_insert.bg, Some(Color::Green));
+        // no blue either, normal mode gets the text color.
+        assert_eq!(ui.statusline_normal.bg, Some(Color::Black));
+        assert_eq!(ui.statusline_normal.fg, Some(Color::White));
+    }
+}
@@ -1,10 +1,30 @@
 use crate::log;
 
-use crossterm::style::Color;
 use serde::Deserialize;
 use serde_json::{Map, Value};
 
-use super::_theme::{Style, Theme, TokenStyle};
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use crossterm::style::Color;
+
+use super::_theme::{parse_color_over, Style, Theme, TokenStyle};
+use super::loader::ThemeLoader;
+
+/// VS Code color themes, JSON with `colors` for the editor and
+/// `tokenColors` for the text.
+pub struct VsCode;
+
+impl ThemeLoader for VsCode {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn load(&self, path: &Path) -> anyhow::Result<Theme> {
+        parse_theme(&path.to_string_lossy())
+    }
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
@@ -16,8 +36,8 @@ enum VsCodeScope {
 impl From<VsCodeScope> for Vec<String> {
     fn from(value: VsCodeScope) -> Self {
         match value {
-            VsCodeScope::String(s) => vec![translate_scope(s)],
-            VsCodeScope::Vec(v) => v.into_iter().map(translate_scope).collect(),
+            VsCodeScope::String(s) => vec![s],
+            VsCodeScope::Vec(v) => v,
         }
     }
 }
@@ -25,7 +45,8 @@ impl From<VsCodeScope> for Vec<String> {
 #[derive(Deserialize, Debug)]
 pub struct VsCodeTokenColor {
     name: Option<String>,
-    scope: VsCodeScope,
+    // the entry without a scope holds the old global editor settings.
+    scope: Option<VsCodeScope>,
     settings: Map<String, Value>,
 }
 
@@ -33,108 +54,94 @@ pub struct VsCodeTokenColor {
 #[serde(rename_all = "camelCase")]
 pub struct VsCodeTheme {
     name: Option<String>,
+    #[allow(dead_code)]
     #[serde(rename = "type")]
     typ: Option<String>,
+    #[serde(default)]
     colors: Map<String, Value>,
+    #[serde(default)]
     token_colors: Vec<VsCodeTokenColor>,
 }
 
-fn parse_rgb(hexcode: &str) -> anyhow::Result<Color> {
-    if !hexcode.starts_with('#') {
-        anyhow::bail!("not a valid hex code");
-    }
-
-    let r = &hexcode[1..=2];
-    let g = &hexcode[3..=4];
-    let b = &hexcode[5..=6];
-
-    let r = u8::from_str_radix(r, 16).unwrap();
-    let g = u8::from_str_radix(g, 16).unwrap();
-    let b = u8::from_str_radix(b, 16).unwrap();
-
-    log!("the rgb value is {:?} {:?} {:?} \n", r, g, b);
-
-    Ok(Color::Rgb { r, g, b })
-}
-
-impl From<VsCodeTokenColor> for TokenStyle {
-    fn from(value: VsCodeTokenColor) -> Self {
-        let mut style = Style::default();
-
-        if let Some(fg) = value.settings.get("foreground") {
-            style.fg = Some(parse_rgb(fg.as_str().unwrap()).unwrap());
-        }
-
-        if let Some(bg) = value.settings.get("background") {
-            style.bg = Some(parse_rgb(bg.as_str().unwrap()).unwrap());
-        }
-
-        if let Some(font_style) = value.settings.get("fontStyle") {
-            style.bold = font_style.as_str().unwrap().contains("bold");
-            style.italic = font_style.as_str().unwrap().contains("italic");
-        }
-
-        Self {
-            name: value.name,
-            scope: value.scope.into(),
-            style,
-        }
-    }
+// the color under `key`, blended with the editor background if it is
+// translucent.
+fn color(
+    map: &Map<String, Value>,
+    key: &str,
+    background: Option<Color>,
+) -> anyhow::Result<Option<Color>> {
+    let Some(value) = map.get(key) else {
+        return Ok(None);
+    };
+    let Some(value) = value.as_str() else {
+        anyhow::bail!("{key}: a color has to be a string, got {value}");
+    };
+    let color = parse_color_over(value, background).with_context(|| key.to_string())?;
+    Ok(Some(color))
 }
 
-fn translate_scope(vscode_scope: String) -> String {
-    if vscode_scope == "meta.function-call.constructor" {
-        return "constructor".to_string();
-    }
-    if vscode_scope == "meta.annotation.rust" {
-        return "attribute".to_string();
-    }
-    return vscode_scope.to_string();
+fn parse_token_color(
+    value: VsCodeTokenColor,
+    background: Option<Color>,
+) -> anyhow::Result<TokenStyle> {
+    let settings = &value.settings;
+    let font_style = settings
+        .get("fontStyle")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    Ok(TokenStyle {
+        style: Style {
+            fg: color(settings, "foreground", background)?,
+            bg: color(settings, "background", background)?,
+            bold: font_style.contains("bold"),
+            italic: font_style.contains("italic"),
+        },
+        name: value.name,
+        scope: value.scope.map(Vec::from).unwrap_or_default(),
+    })
 }
 
 pub fn parse_theme(file: &str) -> anyhow::Result<Theme> {
     log!("the file is {file}");
-    let open_file = std::fs::read_to_string(file)?;
-    let vscode_theme_json: VsCodeTheme = match serde_json::from_str(&open_file) {
-        Ok(v) => v,
-        Err(e) => panic!("error {:#?}", e),
-    };
+    let content = std::fs::read_to_string(file)?;
+    parse_str(&content)
+}
+
+pub fn parse_str(content: &str) -> anyhow::Result<Theme> {
+    let theme: VsCodeTheme = serde_json::from_str(content)?;
+
+    // a theme without editor colors draws on the terminal's own.
+    let background = color(&theme.colors, "editor.background", None)
+        .context("colors")?
+        .unwrap_or(Color::Reset);
+    let foreground = color(&theme.colors, "editor.foreground", Some(background))
+        .context("colors")?
+        .unwrap_or(Color::Reset);
 
     let mut token_style: Vec<TokenStyle> = Vec::new();
-    for token_color in vscode_theme_json.token_colors {
-        token_style.push(token_color.into());
+    for (i, token_color) in theme.token_colors.into_iter().enumerate() {
+        let style = parse_token_color(token_color, Some(background))
+            .with_context(|| format!("tokenColors[{i}].settings"))?;
+        token_style.push(style);
     }
 
-    Ok(Theme {
-        name: vscode_theme_json.name.unwrap_or_default(),
-        style: Style {
-            fg: Some(
-                parse_rgb(
-                    vscode_theme_json
-                        .colors
-                        .get("editor.foreground")
-                        .expect("not found")
-                        .as_str()
-                        .expect("already a string"),
-                )
-                .unwrap(),
-            ),
-            bg: Some(
-                parse_rgb(
-                    vscode_theme_json
-                        .colors
-                        .get("editor.background")
-                        .expect("not found")
-                        .as_str()
-                        .expect("already a string"),
-                )
-                .unwrap(),
-            ),
-            bold: false,
-            italic: false,
+    let mut colors = HashMap::new();
+    for key in theme.colors.keys() {
+        if let Some(color) = color(&theme.colors, key, Some(background)).context("colors")? {
+            colors.insert(key.clone(), color);
+        }
+    }
+
+    Ok(Theme::new(
+        theme.name.unwrap_or_default(),
+        Style {
+            fg: Some(foreground),
+            bg: Some(background),
+            ..Default::default()
         },
         token_style,
-    })
+        &colors,
+    ))
 }
 
 #[cfg(test)]
@@ -142,7 +149,53 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_test() {
-        log!("{:#?}", parse_theme("./frappe.json"));
+    fn reports_the_broken_key() {
+        let theme = r##"{
+            "colors": { "editor.background": "#fff", "editor.lineHighlightBackground": "#00000080" },
+            "tokenColors": [
+                { "settings": { "foreground": "#000" } },
+                { "scope": "comment", "settings": { "foreground": "#12" } }
+            ]
+        }"##;
+        let err = parse_str(theme).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "tokenColors[1].settings: foreground: not a valid hex code: #12"
+        );
+
+        let theme = parse_str(&theme.replace("#12", "#123")).unwrap();
+        assert_eq!(
+            theme.ui.cursor_line.bg,
+            Some(Color::Rgb {
+                r: 127,
+                g: 127,
+                b: 127
+            })
+        );
+    }
+
+    #[test]
+    fn matches_the_rust_scopes_of_latte() {
+        let theme = parse_str(include_str!("../../themes/latte.json")).unwrap();
+        let purple = Color::Rgb {
+            r: 0x88,
+            g: 0x39,
+            b: 0xef,
+        };
+        let yellow = Color::Rgb {
+            r: 0xdf,
+            g: 0x8e,
+            b: 0x1d,
+        };
+        let style = |capture: &str, language: &str| theme.get_style(capture, language).unwrap();
+        assert_eq!(style("keyword.storage.type", "rust").fg, Some(purple));
+        assert_eq!(style("keyword.function", "rust").fg, Some(purple));
+        let attribute = style("attribute", "rust");
+        assert_eq!(attribute.fg, Some(yellow));
+        assert!(attribute.italic);
+        assert!(style("function.macro", "rust").italic);
+        // the rust rules stay out of other languages.
+        assert!(!style("attribute", "python").italic);
+        assert!(!style("attribute", "text").italic);
     }
 }
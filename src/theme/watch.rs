@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Watches the file of the active theme. The directory is watched rather
+/// than the file itself, editors often save by writing a new file and
+/// renaming it over the old one.
+pub struct ThemeWatcher {
+    path: PathBuf,
+    events: Receiver<notify::Result<notify::Event>>,
+    _watcher: RecommendedWatcher, // stops watching when dropped
+}
+
+impl ThemeWatcher {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let path = std::fs::canonicalize(path)?;
+        let Some(dir) = path.parent() else {
+            anyhow::bail!("can't watch {}", path.display());
+        };
+        let (tx, events) = channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            path,
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Whether the file was written since the last call. A save usually
+    /// comes as several events, they all count as one change.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter().flatten() {
+            changed |= matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event.paths.iter().any(|p| p == &self.path);
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn sees_writes_to_the_file() {
+        let dir = TestDir::new("watch");
+        let file = dir.join("theme.toml");
+        std::fs::write(&file, "").unwrap();
+
+        let watcher = ThemeWatcher::new(&file).unwrap();
+        std::fs::write(dir.join("other.toml"), "").unwrap();
+        std::fs::write(&file, "\"keyword\" = \"red\"").unwrap();
+        let start = std::time::Instant::now();
+        while !watcher.changed() {
+            assert!(start.elapsed().as_secs() < 5, "no event for the theme");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    }
+}